@@ -6,7 +6,7 @@ extern crate rand;
 
 use rand::rngs::OsRng;
 use test::Bencher;
-use biscuit::{crypto::KeyPair, token::{Biscuit, builder::*}};
+use biscuit::{crypto::KeyPair, format::SerializedBiscuit, token::{Biscuit, builder::*}};
 
 #[bench]
 fn create_block_1(b: &mut Bencher) {
@@ -312,6 +312,41 @@ fn check_signature_5(b: &mut Bencher) {
   });
 }
 
+#[bench]
+fn check_signature_batch_5(b: &mut Bencher) {
+  let mut rng: OsRng = OsRng;
+  let root = KeyPair::new_with_rng(&mut rng);
+
+  let tokens = (0..16).map(|_| {
+    let mut builder = Biscuit::builder(&root);
+    builder.add_authority_fact(fact("right", &[s("authority"), string("file1"), s("read")],));
+    builder.add_authority_fact(fact("right", &[s("authority"), string("file2"), s("read")],));
+    builder.add_authority_fact(fact("right", &[s("authority"), string("file1"), s("write")],));
+
+    let mut token = builder.build_with_rng(&mut rng).unwrap();
+
+    for _ in 0..4 {
+      let keypair = KeyPair::new_with_rng(&mut rng);
+      let mut block_builder = token.create_block();
+      block_builder.check_resource("file1");
+      block_builder.check_operation("read");
+
+      token = token.append_with_rng(&mut rng, &keypair, block_builder).unwrap();
+    }
+
+    token.to_vec().unwrap()
+  }).collect::<Vec<_>>();
+
+  b.bytes = tokens.iter().map(|t| t.len() as u64).sum();
+  b.iter(|| {
+    let deser = tokens.iter()
+      .map(|data| SerializedBiscuit::decode(data).unwrap())
+      .collect::<Vec<_>>();
+    let refs = deser.iter().collect::<Vec<_>>();
+    SerializedBiscuit::verify_batch(&mut rng, &refs).unwrap();
+  });
+}
+
 #[bench]
 fn caveats_block_2(b: &mut Bencher) {
   let mut rng: OsRng = OsRng;
@@ -0,0 +1,374 @@
+//! command line tool to create, attenuate, inspect and verify tokens
+//!
+//! build it with `cargo build --features cli`, then run `biscuit help`
+use biscuit_auth::{
+    crypto::{self, ed25519, KeyPair, PrivateKey, PublicKey},
+    parser,
+    token::{builder::BlockBuilder, json::TokenJson, verifier::Verifier, Biscuit},
+};
+use std::io::{Read, Write};
+use zeroize::Zeroizing;
+
+const USAGE: &str = "\
+usage: biscuit <command> [options]
+
+commands:
+  keypair [--ed25519] [--private-key-file <path>]
+      generates a key pair, or derives the public key of a private key
+  generate --private-key-file <path> [--ed25519] [--context <text>] <datalog file>
+      creates a token with an authority block from a Datalog source
+  attenuate <token> [--context <text>] <datalog file>
+      appends a block from a Datalog source to a token
+  inspect <token> [--json] [--public-key <hex>] [--secret <text>]
+      prints the content of a token
+  seal <token> --secret <text> [--public-key <hex>] [--encrypted]
+      creates a sealed version of a token
+  verify <token> (--public-key <hex> | --secret <text>) [<policy file>]
+         [--fact <datalog>]... [--resource <text>] [--operation <text>] [--time]
+      verifies a token with the facts, rules and caveats of the policy file
+
+Datalog sources contain facts, rules and caveats, each ending with ';'. Caveats
+are prefixed with the `caveat` keyword, and `//` starts a comment.
+
+<token> is a path to a token, or `-` to read it from the standard input. Tokens are
+read and written in URL safe base64, or in raw bytes if `--raw` is set. Private keys
+are read in hexadecimal from a file, or from the standard input with `-`, so that they
+do not appear in the process arguments. Keys are ristretto255 keys unless `--ed25519`
+is set. Use --public-key or --secret
+with inspect and seal to check the token before using it.";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(|s| s.as_str()) {
+        Some("keypair") => Args::parse(&args[1..], &["--ed25519"], &["--private-key-file"]).and_then(keypair),
+        Some("generate") => Args::parse(&args[1..], &["--ed25519", "--raw"], &["--private-key-file", "--context"])
+            .and_then(generate),
+        Some("attenuate") => Args::parse(&args[1..], &["--raw"], &["--context"]).and_then(attenuate),
+        Some("inspect") => Args::parse(&args[1..], &["--json", "--raw"], &["--public-key", "--secret"])
+            .and_then(inspect),
+        Some("seal") => Args::parse(&args[1..], &["--encrypted", "--raw"], &["--secret", "--public-key"])
+            .and_then(seal),
+        Some("verify") => Args::parse(
+            &args[1..],
+            &["--time", "--raw"],
+            &["--public-key", "--secret", "--fact", "--resource", "--operation"],
+        )
+        .and_then(verify),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// positional arguments and options of a command
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// parses the arguments of a command, which accepts the `flags` options
+    /// without a value, and the `valued` options followed by a value
+    fn parse(args: &[String], flags: &[&str], valued: &[&str]) -> Result<Args, String> {
+        let mut positional = vec![];
+        let mut options = vec![];
+
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            if arg.starts_with("--") {
+                if flags.contains(&arg.as_str()) {
+                    options.push((arg.clone(), None));
+                } else if valued.contains(&arg.as_str()) {
+                    let value = it.next().ok_or_else(|| format!("missing value for {}", arg))?;
+                    options.push((arg.clone(), Some(value.clone())));
+                } else {
+                    return Err(format!("unknown option {}", arg));
+                }
+            } else {
+                positional.push(arg.clone());
+            }
+        }
+
+        Ok(Args { positional, options })
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).pop()
+    }
+
+    fn values(&self, name: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(n, _)| n == name)
+            .filter_map(|(_, v)| v.as_deref())
+            .collect()
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.value(name).ok_or_else(|| format!("missing {} option", name))
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(|s| s.as_str())
+            .ok_or_else(|| format!("missing {} argument", name))
+    }
+}
+
+fn keypair(args: Args) -> Result<(), String> {
+    let private_key = args.value("--private-key-file").map(read_private_key).transpose()?;
+
+    let (private, public) = if args.flag("--ed25519") {
+        let keypair = match private_key {
+            Some(hex) => ed25519::KeyPair::from(ed25519::PrivateKey::from_hex(hex.trim()).map_err(display)?),
+            None => ed25519::KeyPair::new(),
+        };
+        (keypair.private().to_hex(), keypair.public().to_hex())
+    } else {
+        let keypair = match private_key {
+            Some(hex) => KeyPair::from(PrivateKey::from_hex(hex.trim()).map_err(display)?),
+            None => KeyPair::new(),
+        };
+        (keypair.private().to_hex(), keypair.public().to_hex())
+    };
+
+    println!("private key: {}", &*private);
+    println!("public key: {}", public);
+    Ok(())
+}
+
+fn generate(args: Args) -> Result<(), String> {
+    let source = read_source(args.positional(0, "datalog file")?)?;
+    let private_key = read_private_key(args.required("--private-key-file")?)?;
+    let private_key = private_key.trim();
+
+    let ed25519_root;
+    let root;
+    let mut builder = if args.flag("--ed25519") {
+        ed25519_root = ed25519::KeyPair::from(ed25519::PrivateKey::from_hex(private_key).map_err(display)?);
+        Biscuit::builder_ed25519(&ed25519_root)
+    } else {
+        root = KeyPair::from(PrivateKey::from_hex(private_key).map_err(display)?);
+        Biscuit::builder(&root)
+    };
+
+    for fact in source.facts {
+        builder.add_authority_fact(fact).map_err(debug)?;
+    }
+    for rule in source.rules {
+        builder.add_authority_rule(rule).map_err(debug)?;
+    }
+    for caveat in source.caveats {
+        let caveat = caveat.convert(&mut builder.symbols);
+        builder.caveats.push(caveat);
+    }
+    if let Some(context) = args.value("--context") {
+        builder.set_context(context.to_string());
+    }
+
+    let token = builder.build().map_err(debug)?;
+    write_token(&args, token.to_vec().map_err(debug)?)
+}
+
+fn attenuate(args: Args) -> Result<(), String> {
+    let token = Biscuit::from(&read_token(&args)?).map_err(debug)?;
+    let source = read_source(args.positional(1, "datalog file")?)?;
+
+    let mut block: BlockBuilder = token.create_block();
+    block.facts = source.facts;
+    block.rules = source.rules;
+    block.caveats = source.caveats;
+    if let Some(context) = args.value("--context") {
+        block.set_context(context.to_string());
+    }
+
+    let token = token.append(&KeyPair::new(), block).map_err(debug)?;
+    write_token(&args, token.to_vec().map_err(debug)?)
+}
+
+fn inspect(args: Args) -> Result<(), String> {
+    let token = load_token(&args)?;
+
+    let output = if args.flag("--json") {
+        serde_json::to_string_pretty(&TokenJson::from_token(&token)).map_err(display)?
+    } else {
+        token.print()
+    };
+    writeln!(std::io::stdout(), "{}", output).map_err(display)
+}
+
+fn seal(args: Args) -> Result<(), String> {
+    let secret = args.required("--secret")?;
+    let data = read_token(&args)?;
+    let token = Biscuit::from(&data).map_err(debug)?;
+    if let Some(public_key) = args.value("--public-key") {
+        check_root_key(&token, public_key)?;
+    }
+
+    let sealed = if args.flag("--encrypted") {
+        token.seal_encrypted(secret.as_bytes())
+    } else {
+        token.seal(secret.as_bytes())
+    };
+    write_token(&args, sealed.map_err(debug)?)
+}
+
+fn verify(args: Args) -> Result<(), String> {
+    let data = read_token(&args)?;
+
+    let mut verifier: Verifier = match (args.value("--public-key"), args.value("--secret")) {
+        (Some(_), Some(_)) => return Err(KEY_AND_SECRET.to_string()),
+        (None, Some(secret)) => Biscuit::from_sealed(&data, secret.as_bytes())
+            .and_then(|token| token.verify_sealed())
+            .map_err(debug)?,
+        (Some(public_key), None) => {
+            let token = Biscuit::from(&data).map_err(debug)?;
+            if token.ed25519_container().is_some() {
+                let root = ed25519::PublicKey::from_hex(public_key).map_err(display)?;
+                token.verify_ed25519(root).map_err(debug)?
+            } else {
+                let root = PublicKey::from_hex(public_key).map_err(display)?;
+                token.verify(root).map_err(debug)?
+            }
+        }
+        (None, None) => return Err("missing --public-key or --secret option".to_string()),
+    };
+
+    if let Some(path) = args.positional.get(1) {
+        let policy = read_source(path)?;
+        for fact in policy.facts {
+            verifier.add_fact(fact).map_err(debug)?;
+        }
+        for rule in policy.rules {
+            verifier.add_rule(rule).map_err(debug)?;
+        }
+        for caveat in policy.caveats {
+            verifier.add_caveat(caveat).map_err(debug)?;
+        }
+    }
+
+    for fact in args.values("--fact") {
+        verifier.add_fact(fact).map_err(|e| format!("invalid fact {:?}: {:?}", fact, e))?;
+    }
+    if let Some(resource) = args.value("--resource") {
+        verifier.add_resource(resource);
+    }
+    if let Some(operation) = args.value("--operation") {
+        verifier.add_operation(operation);
+    }
+    if args.flag("--time") {
+        verifier.set_time();
+    }
+
+    match verifier.verify() {
+        Ok(()) => {
+            println!("verification succeeded");
+            Ok(())
+        }
+        Err(e) => Err(format!("verification failed: {:?}", e)),
+    }
+}
+
+/// sealed tokens are checked with their secret only, their root key is not available
+const KEY_AND_SECRET: &str = "--public-key cannot be used with --secret on sealed tokens";
+
+/// reads a token, checking its root key or its sealing secret if provided
+fn load_token(args: &Args) -> Result<Biscuit, String> {
+    let data = read_token(args)?;
+
+    if let Some(secret) = args.value("--secret") {
+        if args.value("--public-key").is_some() {
+            return Err(KEY_AND_SECRET.to_string());
+        }
+        return Biscuit::from_sealed(&data, secret.as_bytes()).map_err(debug);
+    }
+
+    let token = Biscuit::from(&data).map_err(debug)?;
+    if let Some(public_key) = args.value("--public-key") {
+        check_root_key(&token, public_key)?;
+    }
+    Ok(token)
+}
+
+fn check_root_key(token: &Biscuit, public_key: &str) -> Result<(), String> {
+    if token.ed25519_container().is_some() {
+        let root = crypto::ed25519::PublicKey::from_hex(public_key).map_err(display)?;
+        token.check_root_key_ed25519(root).map_err(debug)
+    } else {
+        let root = PublicKey::from_hex(public_key).map_err(display)?;
+        token.check_root_key(root).map_err(debug)
+    }
+}
+
+/// reads the token argument from a file or the standard input, in base64, or
+/// in raw bytes with `--raw`
+fn read_token(args: &Args) -> Result<Vec<u8>, String> {
+    let path = args.positional(0, "token")?;
+    let data = if path == "-" {
+        let mut data = vec![];
+        std::io::stdin().read_to_end(&mut data).map_err(display)?;
+        data
+    } else {
+        std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e))?
+    };
+
+    if args.flag("--raw") {
+        return Ok(data);
+    }
+
+    let text = std::str::from_utf8(&data)
+        .map_err(|_| format!("{} is not in base64, use --raw for binary tokens", path))?;
+    crypto::encoding::from_base64(text)
+        .map(|data| data.to_vec())
+        .map_err(|e| format!("{} is not in base64: {}", path, e))
+}
+
+/// reads a hexadecimal private key from a file, or the standard input with `-`
+fn read_private_key(path: &str) -> Result<Zeroizing<String>, String> {
+    // the buffer is large enough to not be reallocated, which would leave
+    // copies of the key
+    let mut key = Zeroizing::new(String::with_capacity(256));
+    let result = if path == "-" {
+        std::io::stdin().read_to_string(&mut key)
+    } else {
+        std::fs::File::open(path).and_then(|mut file| file.read_to_string(&mut key))
+    };
+
+    result.map_err(|e| format!("cannot read {}: {}", path, e))?;
+    Ok(key)
+}
+
+fn read_source(path: &str) -> Result<parser::SourceResult, String> {
+    let source = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    parser::source(&source).map_err(|_| format!("cannot parse {}", path))
+}
+
+fn write_token(args: &Args, data: Vec<u8>) -> Result<(), String> {
+    let mut stdout = std::io::stdout();
+    if args.flag("--raw") {
+        stdout.write_all(&data).map_err(display)
+    } else {
+        writeln!(stdout, "{}", crypto::encoding::to_base64(&data)).map_err(display)
+    }
+}
+
+fn display<E: std::fmt::Display>(e: E) -> String {
+    e.to_string()
+}
+
+fn debug<E: std::fmt::Debug>(e: E) -> String {
+    format!("{:?}", e)
+}
@@ -50,6 +50,8 @@ pub extern fn error_message() -> *const c_char {
     })
 }
 
+/// the discriminants are part of the C ABI: new kinds are added after `None`,
+/// with an explicit value
 #[repr(C)]
 pub enum ErrorKind {
     InvalidArgument,
@@ -80,6 +82,20 @@ pub enum ErrorKind {
     TooManyIterations,
     Timeout,
     None,
+    Revoked = 28,
+    RevocationStore = 29,
+    FormatSignatureVersion = 30,
+    FormatLegacySignature = 31,
+    FormatSignatureSigner = 32,
+    FormatInvalidKey = 33,
+    MissingProofKey = 34,
+    InvalidPossessionProof = 35,
+    ProofKeyAlreadyPinned = 36,
+    FormatUnknownSealingKey = 37,
+    FormatDeserializationLimit = 38,
+    LogicInvalidScope = 39,
+    FactProvider = 40,
+    LogicUnknownBuiltin = 41,
 }
 
 #[no_mangle]
@@ -95,6 +111,7 @@ pub extern fn error_kind() -> ErrorKind {
                             Token::InternalError => ErrorKind::InternalError,
                             Token::Format(Format::Signature(Signature::InvalidFormat)) => ErrorKind::FormatSignatureInvalidFormat,
                             Token::Format(Format::Signature(Signature::InvalidSignature)) => ErrorKind::FormatSignatureInvalidSignature,
+                            Token::Format(Format::Signature(Signature::Signer(_))) => ErrorKind::FormatSignatureSigner,
                             Token::Format(Format::SealedSignature) => ErrorKind::FormatSealedSignature,
                             Token::Format(Format::EmptyKeys) => ErrorKind::FormatEmptyKeys,
                             Token::Format(Format::UnknownPublicKey) => ErrorKind::FormatUnknownPublickKey,
@@ -103,6 +120,11 @@ pub extern fn error_kind() -> ErrorKind {
                             Token::Format(Format::BlockDeserializationError(_)) => ErrorKind::FormatBlockDeserializationError,
                             Token::Format(Format::BlockSerializationError(_)) => ErrorKind::FormatBlockSerializationError,
                             Token::Format(Format::Version { .. }) => ErrorKind::FormatVersion,
+                            Token::Format(Format::SignatureVersion { .. }) => ErrorKind::FormatSignatureVersion,
+                            Token::Format(Format::LegacySignature) => ErrorKind::FormatLegacySignature,
+                            Token::Format(Format::InvalidKey(_)) => ErrorKind::FormatInvalidKey,
+                            Token::Format(Format::UnknownSealingKey(_)) => ErrorKind::FormatUnknownSealingKey,
+                            Token::Format(Format::DeserializationLimit(_)) => ErrorKind::FormatDeserializationLimit,
                             Token::InvalidAuthorityIndex(_) => ErrorKind::InvalidAuthorityIndex,
                             Token::InvalidBlockIndex(_) => ErrorKind::InvalidBlockIndex,
                             Token::SymbolTableOverlap => ErrorKind::SymbolTableOverlap,
@@ -115,9 +137,17 @@ pub extern fn error_kind() -> ErrorKind {
                             Token::FailedLogic(Logic::InvalidBlockRule(_,_)) => ErrorKind::LogicInvalidBlockRule,
                             Token::FailedLogic(Logic::FailedCaveats(_)) => ErrorKind::LogicFailedCaveats,
                             Token::FailedLogic(Logic::VerifierNotEmpty) => ErrorKind::LogicVerifierNotEmpty,
+                            Token::FailedLogic(Logic::InvalidScope(_)) => ErrorKind::LogicInvalidScope,
+                            Token::FailedLogic(Logic::UnknownBuiltin(_)) => ErrorKind::LogicUnknownBuiltin,
                             Token::RunLimit(RunLimit::TooManyFacts) => ErrorKind::TooManyFacts,
                             Token::RunLimit(RunLimit::TooManyIterations) => ErrorKind::TooManyIterations,
                             Token::RunLimit(RunLimit::Timeout) => ErrorKind::Timeout,
+                            Token::Revoked { .. } => ErrorKind::Revoked,
+                            Token::RevocationStore(_) => ErrorKind::RevocationStore,
+                            Token::FactProvider(_) => ErrorKind::FactProvider,
+                            Token::MissingProofKey => ErrorKind::MissingProofKey,
+                            Token::InvalidPossessionProof => ErrorKind::InvalidPossessionProof,
+                            Token::ProofKeyAlreadyPinned => ErrorKind::ProofKeyAlreadyPinned,
                         }
                     }
                 }
@@ -152,6 +182,7 @@ pub extern fn error_caveat_id(caveat_index: u64) -> u64 {
                         match v[caveat_index as usize] {
                             FailedCaveat::Block(FailedBlockCaveat { caveat_id, ..}) => caveat_id as u64,
                             FailedCaveat::Verifier(FailedVerifierCaveat { caveat_id, ..}) => caveat_id as u64,
+                            FailedCaveat::Token(FailedTokenCaveat { caveat_id, ..}) => caveat_id as u64,
                         }
                     }
                 },
@@ -172,6 +203,7 @@ pub extern fn error_caveat_block_id(caveat_index: u64) -> u64 {
                     } else {
                         match v[caveat_index as usize] {
                             FailedCaveat::Block(FailedBlockCaveat { block_id, ..}) => block_id as u64,
+                            FailedCaveat::Token(FailedTokenCaveat { block_id, ..}) => block_id as u64,
                             _ => u64::MAX,
                         }
                     }
@@ -200,6 +232,7 @@ pub extern fn error_caveat_rule(caveat_index: u64) -> *const c_char {
                         let rule = match &v[caveat_index as usize] {
                             FailedCaveat::Block(FailedBlockCaveat { rule, ..}) => rule,
                             FailedCaveat::Verifier(FailedVerifierCaveat { rule, ..}) => rule,
+                            FailedCaveat::Token(FailedTokenCaveat { rule, ..}) => rule,
                         };
                         let err = CString::new(rule.clone()).ok();
                         CAVEAT_RULE.with(|ret| {
@@ -226,6 +259,7 @@ pub extern fn error_caveat_is_verifier(caveat_index: u64) -> bool {
                         match v[caveat_index as usize] {
                             FailedCaveat::Block(FailedBlockCaveat { .. }) => false,
                             FailedCaveat::Verifier(FailedVerifierCaveat { .. }) => true,
+                            FailedCaveat::Token(FailedTokenCaveat { .. }) => false,
                         }
                     }
                 },
@@ -308,6 +342,85 @@ pub unsafe extern "C" fn key_pair_deserialize(
     }
 }
 
+/// key encodings for `key_pair_serialize_encoded` and `public_key_serialize_encoded`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum KeyEncoding {
+    Raw,
+    Hex,
+    Base64,
+    Pem,
+}
+
+impl From<KeyEncoding> for crate::crypto::encoding::KeyEncoding {
+    fn from(encoding: KeyEncoding) -> Self {
+        match encoding {
+            KeyEncoding::Raw => crate::crypto::encoding::KeyEncoding::Raw,
+            KeyEncoding::Hex => crate::crypto::encoding::KeyEncoding::Hex,
+            KeyEncoding::Base64 => crate::crypto::encoding::KeyEncoding::Base64,
+            KeyEncoding::Pem => crate::crypto::encoding::KeyEncoding::Pem,
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn key_pair_serialized_size(
+    kp: Option<&KeyPair>,
+    encoding: KeyEncoding,
+) -> usize {
+    if kp.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+    let kp = kp.unwrap();
+
+    kp.0.private().to_encoding(encoding.into()).len()
+}
+
+/// expects a buffer of at least `key_pair_serialized_size` bytes
+#[no_mangle]
+pub unsafe extern "C" fn key_pair_serialize_encoded(
+    kp: Option<&KeyPair>,
+    encoding: KeyEncoding,
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+) -> usize {
+    if kp.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+    let kp = kp.unwrap();
+
+    let v = kp.0.private().to_encoding(encoding.into());
+    if v.len() > buffer_len {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+
+    let output_slice = std::slice::from_raw_parts_mut(buffer_ptr, v.len());
+    output_slice.copy_from_slice(&v[..]);
+    v.len()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn key_pair_deserialize_encoded(
+    encoding: KeyEncoding,
+    buffer_ptr: *const u8,
+    buffer_len: usize,
+) -> Option<Box<KeyPair>> {
+    let input_slice = std::slice::from_raw_parts(buffer_ptr, buffer_len);
+
+    match crate::crypto::PrivateKey::from_encoding(encoding.into(), input_slice) {
+        Err(e) => {
+            update_last_error(Error::Biscuit(crate::error::Token::Format(e)));
+            None
+        },
+        Ok(privkey) => {
+            Some(Box::new(KeyPair(crate::crypto::KeyPair::from(privkey))))
+        }
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn key_pair_free(
     _kp: Option<Box<KeyPair>>,
@@ -351,6 +464,64 @@ pub unsafe extern "C" fn public_key_deserialize(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn public_key_serialized_size(
+    kp: Option<&PublicKey>,
+    encoding: KeyEncoding,
+) -> usize {
+    if kp.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+    let kp = kp.unwrap();
+
+    kp.0.to_encoding(encoding.into()).len()
+}
+
+/// expects a buffer of at least `public_key_serialized_size` bytes
+#[no_mangle]
+pub unsafe extern "C" fn public_key_serialize_encoded(
+    kp: Option<&PublicKey>,
+    encoding: KeyEncoding,
+    buffer_ptr: *mut u8,
+    buffer_len: usize,
+) -> usize {
+    if kp.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+    let kp = kp.unwrap();
+
+    let v = kp.0.to_encoding(encoding.into());
+    if v.len() > buffer_len {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+
+    let output_slice = std::slice::from_raw_parts_mut(buffer_ptr, v.len());
+    output_slice.copy_from_slice(&v[..]);
+    v.len()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn public_key_deserialize_encoded(
+    encoding: KeyEncoding,
+    buffer_ptr: *const u8,
+    buffer_len: usize,
+) -> Option<Box<PublicKey>> {
+    let input_slice = std::slice::from_raw_parts(buffer_ptr, buffer_len);
+
+    match crate::crypto::PublicKey::from_encoding(encoding.into(), input_slice) {
+        Err(e) => {
+            update_last_error(Error::Biscuit(crate::error::Token::Format(e)));
+            None
+        },
+        Ok(pubkey) => {
+            Some(Box::new(PublicKey(pubkey)))
+        }
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn public_key_free(
     _kp: Option<Box<PublicKey>>,
@@ -530,6 +701,72 @@ pub unsafe extern "C" fn biscuit_from_sealed(
     crate::token::Biscuit::from_sealed(biscuit, secret).map(Biscuit).map(Box::new).ok()
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn biscuit_from_base64(
+    biscuit: *const c_char,
+) -> Option<Box<Biscuit>> {
+    let biscuit = match CStr::from_ptr(biscuit).to_str() {
+        Ok(s) => s,
+        Err(_) => {
+            update_last_error(Error::InvalidArgument);
+            return None;
+        }
+    };
+
+    match crate::token::Biscuit::from_base64(biscuit) {
+        Ok(b) => Some(Box::new(Biscuit(b))),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            None
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn biscuit_from_sealed_base64(
+    biscuit: *const c_char,
+    secret_ptr: *const u8,
+    secret_len: usize,
+) -> Option<Box<Biscuit>> {
+    let biscuit = match CStr::from_ptr(biscuit).to_str() {
+        Ok(s) => s,
+        Err(_) => {
+            update_last_error(Error::InvalidArgument);
+            return None;
+        }
+    };
+    let secret = std::slice::from_raw_parts(secret_ptr, secret_len);
+
+    match crate::token::Biscuit::from_sealed_base64(biscuit, secret) {
+        Ok(b) => Some(Box::new(Biscuit(b))),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            None
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn biscuit_from_bearer(
+    header: *const c_char,
+) -> Option<Box<Biscuit>> {
+    let header = match CStr::from_ptr(header).to_str() {
+        Ok(s) => s,
+        Err(_) => {
+            update_last_error(Error::InvalidArgument);
+            return None;
+        }
+    };
+
+    match crate::token::Biscuit::from_bearer(header) {
+        Ok(b) => Some(Box::new(Biscuit(b))),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            None
+        }
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn biscuit_serialized_size(
     biscuit: Option<&Biscuit>,
@@ -641,6 +878,51 @@ pub unsafe extern "C" fn biscuit_serialize_sealed(
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn biscuit_serialize_base64(
+    biscuit: Option<&Biscuit>,
+) -> *mut c_char {
+    if biscuit.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return std::ptr::null_mut();
+    }
+
+    let biscuit = biscuit.unwrap();
+
+    match biscuit.0.to_base64() {
+        // base64 output does not contain null bytes
+        Ok(s) => CString::new(s).unwrap().into_raw(),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            std::ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn biscuit_serialize_sealed_base64(
+    biscuit: Option<&Biscuit>,
+    secret_ptr: *const u8,
+    secret_len: usize,
+) -> *mut c_char {
+    if biscuit.is_none() {
+        update_last_error(Error::InvalidArgument);
+        return std::ptr::null_mut();
+    }
+
+    let biscuit = biscuit.unwrap();
+    let secret = std::slice::from_raw_parts(secret_ptr, secret_len);
+
+    match biscuit.0.seal_base64(secret) {
+        // base64 output does not contain null bytes
+        Ok(s) => CString::new(s).unwrap().into_raw(),
+        Err(e) => {
+            update_last_error(Error::Biscuit(e));
+            std::ptr::null_mut()
+        }
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn biscuit_block_count(
     biscuit: Option<&Biscuit>,
@@ -0,0 +1,902 @@
+//! conformance test vectors, available with the `conformance` feature
+//!
+//! the test vectors are a directory containing serialized tokens and a
+//! `manifest.json` file describing, for each token:
+//! - its expected content, in the format of [`TokenJson`]. This is `null` for
+//!   tokens that must fail deserialization
+//! - a list of validations: facts, rules and caveats added to the verifier
+//!   (in the JSON format of the [builder](crate::token::builder) types), with the
+//!   expected result
+//!
+//! ```json
+//! {
+//!   "root_private_key": "12aca4..",
+//!   "root_public_key": "acdd6d..",
+//!   "testcases": [
+//!     {
+//!       "title": "basic token",
+//!       "filename": "test1_basic.bc",
+//!       "token": { "symbols": [..], "authority": {..}, "blocks": [..] },
+//!       "validations": {
+//!         "": {
+//!           "facts": [{"name": "resource", "ids": [{"symbol": "ambient"}, {"str": "file1"}]}],
+//!           "rules": [],
+//!           "caveats": [],
+//!           "result": {
+//!             "failure": {
+//!               "kind": "logic_failed_caveats",
+//!               "caveats": [{"block_id": 1, "caveat_id": 0}]
+//!             }
+//!           }
+//!         }
+//!       }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Verification errors are recorded as an [`ErrorCode`], which does not depend on
+//! the error messages or on the Rust representation of the errors. The vectors
+//! shipped in the `samples` directory are checked by
+//! `cargo test --features conformance`, and regenerated
+//! with `cargo run --example testcases --features conformance -- samples`.
+use crate::crypto::{KeyPair, PrivateKey, PublicKey};
+use crate::error;
+use crate::token::{
+    builder::{Caveat, Fact, Rule},
+    json::TokenJson,
+    verifier::VerifierLimits,
+    Biscuit,
+};
+use prost::Message;
+use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// name of the manifest file in a test vectors directory
+pub const MANIFEST: &str = "manifest.json";
+
+/// list of test cases, with the root key used to create the tokens
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// in hexadecimal
+    pub root_private_key: String,
+    /// in hexadecimal
+    pub root_public_key: String,
+    pub testcases: Vec<TestCase>,
+}
+
+/// a serialized token and its expected validation results
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TestCase {
+    pub title: String,
+    /// token file, relative to the manifest
+    pub filename: String,
+    /// expected content of the token, `None` if it must be rejected
+    pub token: Option<TokenJson>,
+    /// verifications of the token, indexed by name
+    pub validations: BTreeMap<String, Validation>,
+}
+
+/// data added to the verifier, and expected result
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Validation {
+    pub facts: Vec<Fact>,
+    pub rules: Vec<Rule>,
+    pub caveats: Vec<Caveat>,
+    pub result: Outcome,
+}
+
+/// result of a validation
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure(ErrorCode),
+}
+
+impl From<&Result<(), error::Token>> for Outcome {
+    fn from(result: &Result<(), error::Token>) -> Self {
+        match result {
+            Ok(()) => Outcome::Success,
+            Err(e) => Outcome::Failure(ErrorCode::from(e)),
+        }
+    }
+}
+
+/// stable description of an [`error::Token`]
+///
+/// the kind is named after the variants of the error, in snake case, like
+/// `format_signature_invalid_signature` or `logic_failed_caveats`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorCode {
+    pub kind: String,
+    /// scope of the token, for `revoked` errors on scoped tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// block of `logic_invalid_block_fact`, `logic_invalid_block_rule` and
+    /// `revoked` errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_id: Option<u32>,
+    /// caveats of `logic_failed_caveats` errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub caveats: Vec<CaveatId>,
+}
+
+/// position of a failed caveat
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaveatId {
+    /// scope of the token, for caveats of scoped tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// `None` for caveats of the verifier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_id: Option<u32>,
+    pub caveat_id: u32,
+}
+
+impl From<&error::FailedCaveat> for CaveatId {
+    fn from(caveat: &error::FailedCaveat) -> Self {
+        use error::FailedCaveat;
+
+        match caveat {
+            FailedCaveat::Block(c) => CaveatId { scope: None, block_id: Some(c.block_id), caveat_id: c.caveat_id },
+            FailedCaveat::Verifier(c) => CaveatId { scope: None, block_id: None, caveat_id: c.caveat_id },
+            FailedCaveat::Token(c) => CaveatId {
+                scope: Some(c.scope.clone()),
+                block_id: Some(c.block_id),
+                caveat_id: c.caveat_id,
+            },
+        }
+    }
+}
+
+impl From<&error::Token> for ErrorCode {
+    fn from(e: &error::Token) -> Self {
+        use error::*;
+
+        let mut scope = None;
+        let mut block_id = None;
+        let mut caveats = vec![];
+        let kind = match e {
+            Token::InternalError => "internal_error",
+            Token::Format(Format::Signature(Signature::InvalidFormat)) => "format_signature_invalid_format",
+            Token::Format(Format::Signature(Signature::InvalidSignature)) => "format_signature_invalid_signature",
+            Token::Format(Format::Signature(Signature::Signer(_))) => "format_signature_signer",
+            Token::Format(Format::SealedSignature) => "format_sealed_signature",
+            Token::Format(Format::EmptyKeys) => "format_empty_keys",
+            Token::Format(Format::UnknownPublicKey) => "format_unknown_public_key",
+            Token::Format(Format::DeserializationError(_)) => "format_deserialization_error",
+            Token::Format(Format::SerializationError(_)) => "format_serialization_error",
+            Token::Format(Format::BlockDeserializationError(_)) => "format_block_deserialization_error",
+            Token::Format(Format::BlockSerializationError(_)) => "format_block_serialization_error",
+            Token::Format(Format::Version { .. }) => "format_version",
+            Token::Format(Format::SignatureVersion { .. }) => "format_signature_version",
+            Token::Format(Format::LegacySignature) => "format_legacy_signature",
+            Token::Format(Format::InvalidKey(_)) => "format_invalid_key",
+            Token::Format(Format::UnknownSealingKey(_)) => "format_unknown_sealing_key",
+            Token::Format(Format::DeserializationLimit(_)) => "format_deserialization_limit",
+            Token::InvalidAuthorityIndex(_) => "invalid_authority_index",
+            Token::InvalidBlockIndex(_) => "invalid_block_index",
+            Token::SymbolTableOverlap => "symbol_table_overlap",
+            Token::MissingSymbols => "missing_symbols",
+            Token::Sealed => "sealed",
+            Token::ParseError => "parse_error",
+            Token::FailedLogic(Logic::InvalidAuthorityFact(_)) => "logic_invalid_authority_fact",
+            Token::FailedLogic(Logic::InvalidAmbientFact(_)) => "logic_invalid_ambient_fact",
+            Token::FailedLogic(Logic::InvalidBlockFact(id, _)) => {
+                block_id = Some(*id);
+                "logic_invalid_block_fact"
+            }
+            Token::FailedLogic(Logic::InvalidBlockRule(id, _)) => {
+                block_id = Some(*id);
+                "logic_invalid_block_rule"
+            }
+            Token::FailedLogic(Logic::FailedCaveats(failed)) => {
+                caveats = failed.iter().map(CaveatId::from).collect();
+                "logic_failed_caveats"
+            }
+            Token::FailedLogic(Logic::VerifierNotEmpty) => "logic_verifier_not_empty",
+            Token::FailedLogic(Logic::InvalidScope(_)) => "logic_invalid_scope",
+            Token::FailedLogic(Logic::UnknownBuiltin(_)) => "logic_unknown_builtin",
+            Token::RunLimit(RunLimit::TooManyFacts) => "run_limit_too_many_facts",
+            Token::RunLimit(RunLimit::TooManyIterations) => "run_limit_too_many_iterations",
+            Token::RunLimit(RunLimit::Timeout) => "run_limit_timeout",
+            Token::Revoked { scope: s, block_id: id } => {
+                scope = s.clone();
+                block_id = Some(*id);
+                "revoked"
+            }
+            Token::RevocationStore(_) => "revocation_store",
+            Token::FactProvider(_) => "fact_provider",
+            Token::MissingProofKey => "missing_proof_key",
+            Token::InvalidPossessionProof => "invalid_possession_proof",
+            Token::ProofKeyAlreadyPinned => "proof_key_already_pinned",
+        };
+
+        ErrorCode { kind: kind.to_string(), scope, block_id, caveats }
+    }
+}
+
+impl Manifest {
+    /// loads the manifest of a test vectors directory
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let data = std::fs::read(dir.join(MANIFEST)).map_err(|e| format!("cannot read manifest: {}", e))?;
+        serde_json::from_slice(&data).map_err(|e| format!("invalid manifest: {}", e))
+    }
+
+    /// writes the manifest in a test vectors directory
+    pub fn write(&self, dir: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::from)?;
+        std::fs::write(dir.join(MANIFEST), json)
+    }
+
+    /// checks every test case against the token files of the directory
+    ///
+    /// returns the list of mismatches
+    pub fn check(&self, dir: &Path) -> Result<(), Vec<String>> {
+        let root = match PrivateKey::from_hex(&self.root_private_key).map(KeyPair::from) {
+            Ok(root) if root.public().to_hex() == self.root_public_key => root.public(),
+            _ => return Err(vec!["invalid root key pair".to_string()]),
+        };
+
+        let mut errors = vec![];
+        for case in self.testcases.iter() {
+            if let Err(mut e) = case.check(dir, root) {
+                errors.append(&mut e);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl TestCase {
+    /// checks the content and the validations of the token
+    pub fn check(&self, dir: &Path, root: PublicKey) -> Result<(), Vec<String>> {
+        let data = std::fs::read(dir.join(&self.filename))
+            .map_err(|e| vec![format!("{}: cannot read token: {}", self.filename, e)])?;
+
+        let mut errors = vec![];
+        let token = Biscuit::from(&data).ok().map(|t| TokenJson::from_token(&t));
+        if token != self.token {
+            errors.push(format!(
+                "{}: token content mismatch\nexpected: {:?}\nfound: {:?}",
+                self.filename, self.token, token
+            ));
+        }
+
+        for (name, validation) in self.validations.iter() {
+            let result = Outcome::from(&validation.run(root, &data));
+            if result != validation.result {
+                errors.push(format!(
+                    "{}: validation {:?} mismatch\nexpected: {:?}\nfound: {:?}",
+                    self.filename, name, validation.result, result
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Validation {
+    fn new(facts: &[&str], caveats: &[&str]) -> Self {
+        Validation {
+            facts: facts.iter().map(|f| (*f).try_into().unwrap()).collect(),
+            rules: vec![],
+            caveats: caveats.iter().map(|c| (*c).try_into().unwrap()).collect(),
+            result: Outcome::Success,
+        }
+    }
+
+    /// deserializes the token and verifies it with the facts, rules and caveats
+    ///
+    /// the execution time is not limited, so that results do not depend on the
+    /// speed of the machine
+    pub fn run(&self, root: PublicKey, data: &[u8]) -> Result<(), error::Token> {
+        let token = Biscuit::from(data)?;
+
+        let mut verifier = token.verify(root)?;
+        for fact in self.facts.iter() {
+            verifier.add_fact(fact.clone())?;
+        }
+        for rule in self.rules.iter() {
+            verifier.add_rule(rule.clone())?;
+        }
+        for caveat in self.caveats.iter() {
+            verifier.add_caveat(caveat.clone())?;
+        }
+
+        verifier.verify_with_limits(VerifierLimits {
+            max_time: Duration::from_secs(60),
+            ..Default::default()
+        })
+    }
+}
+
+/// test case before serialization
+struct Sample {
+    title: &'static str,
+    filename: &'static str,
+    data: Vec<u8>,
+    /// whether the token is expected to deserialize
+    valid: bool,
+    validations: Vec<(&'static str, Validation)>,
+}
+
+/// generates the test vectors in a directory, and writes their manifest
+///
+/// results are computed with this implementation
+pub fn generate<T: RngCore + CryptoRng>(rng: &mut T, dir: &Path) -> std::io::Result<Manifest> {
+    let root = KeyPair::new_with_rng(rng);
+
+    let samples = vec![
+        basic_token(rng, &root),
+        different_root_key(rng),
+        invalid_signature_format(rng, &root),
+        random_block(rng, &root),
+        invalid_signature(rng, &root),
+        reordered_blocks(rng, &root),
+        invalid_block_fact_authority(rng, &root),
+        invalid_block_fact_ambient(rng, &root),
+        expired_token(rng, &root),
+        authority_rules(rng, &root),
+        verifier_authority_caveats(rng, &root),
+        authority_caveats(rng, &root),
+        block_rules(rng, &root),
+        regex_constraint(rng, &root),
+        multi_queries_caveats(rng, &root),
+        caveat_head_name(rng, &root),
+        schema_v0(rng, &root),
+        schema_v1(rng, &root),
+        schema_v2(rng, &root),
+        schema_v3(rng, &root),
+    ];
+
+    let mut testcases = vec![];
+    for sample in samples {
+        std::fs::write(dir.join(sample.filename), &sample.data)?;
+
+        let token = if sample.valid {
+            Biscuit::from(&sample.data).ok().map(|t| TokenJson::from_token(&t))
+        } else {
+            None
+        };
+
+        let data = sample.data;
+        let validations = sample
+            .validations
+            .into_iter()
+            .map(|(name, mut validation)| {
+                validation.result = Outcome::from(&validation.run(root.public(), &data));
+                (name.to_string(), validation)
+            })
+            .collect();
+
+        testcases.push(TestCase {
+            title: sample.title.to_string(),
+            filename: sample.filename.to_string(),
+            token,
+            validations,
+        });
+    }
+
+    let manifest = Manifest {
+        root_private_key: root.private().to_hex().to_string(),
+        root_public_key: root.public().to_hex(),
+        testcases,
+    };
+    manifest.write(dir)?;
+
+    Ok(manifest)
+}
+
+/// token with 3 rights, and a block restricting it to read access
+fn rights_token<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Biscuit {
+    let mut builder = Biscuit::builder(root);
+    builder.add_authority_fact("right(#authority, \"file1\", #read)").unwrap();
+    builder.add_authority_fact("right(#authority, \"file2\", #read)").unwrap();
+    builder.add_authority_fact("right(#authority, \"file1\", #write)").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2
+        .add_caveat("caveat1($0) <- resource(#ambient, $0), operation(#ambient, #read), right(#authority, $0, #read)")
+        .unwrap();
+
+    let keypair2 = KeyPair::new_with_rng(rng);
+    biscuit1.append_with_rng(rng, &keypair2, block2).unwrap()
+}
+
+fn file1() -> Validation {
+    Validation::new(&["resource(#ambient, \"file1\")"], &[])
+}
+
+fn basic_token<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let biscuit2 = rights_token(rng, root);
+
+    Sample {
+        title: "basic token",
+        filename: "test1_basic.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![("", file1())],
+    }
+}
+
+fn different_root_key<T: RngCore + CryptoRng>(rng: &mut T) -> Sample {
+    let root2 = KeyPair::new_with_rng(rng);
+    let mut builder = Biscuit::builder(&root2);
+    builder.add_authority_fact("right(#authority, \"file1\", #read)").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2
+        .add_caveat("caveat1($0) <- resource(#ambient, $0), operation(#ambient, #read), right(#authority, $0, #read)")
+        .unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "different root key",
+        filename: "test2_different_root_key.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![("", file1())],
+    }
+}
+
+fn invalid_signature_format<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let biscuit2 = rights_token(rng, root);
+
+    let mut proto = biscuit2.container().unwrap().to_proto();
+    proto.signature.z.truncate(16);
+    let mut data = Vec::new();
+    proto.encode(&mut data).unwrap();
+
+    Sample {
+        title: "invalid signature format",
+        filename: "test3_invalid_signature_format.bc",
+        data,
+        valid: false,
+        validations: vec![("", file1())],
+    }
+}
+
+fn random_block<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let biscuit2 = rights_token(rng, root);
+
+    let mut proto = biscuit2.container().unwrap().to_proto();
+    let mut block = [0u8; 32];
+    rng.fill_bytes(&mut block);
+    proto.blocks[0] = block.to_vec();
+    let mut data = Vec::new();
+    proto.encode(&mut data).unwrap();
+
+    Sample {
+        title: "random block",
+        filename: "test4_random_block.bc",
+        data,
+        valid: false,
+        validations: vec![("", file1())],
+    }
+}
+
+fn invalid_signature<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let biscuit2 = rights_token(rng, root);
+
+    let mut serialized = biscuit2.container().unwrap().clone();
+    serialized.signature.z += curve25519_dalek::scalar::Scalar::one();
+
+    Sample {
+        title: "invalid signature",
+        filename: "test5_invalid_signature.bc",
+        data: serialized.to_vec().unwrap(),
+        valid: false,
+        validations: vec![("", file1())],
+    }
+}
+
+fn reordered_blocks<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let biscuit2 = rights_token(rng, root);
+
+    let mut block3 = biscuit2.create_block();
+    block3.add_caveat("caveat2($0) <- resource(#ambient, \"file1\")").unwrap();
+    let keypair3 = KeyPair::new_with_rng(rng);
+    let biscuit3 = biscuit2.append_with_rng(rng, &keypair3, block3).unwrap();
+
+    let mut serialized = biscuit3.container().unwrap().clone();
+    serialized.blocks.swap(0, 1);
+    serialized.keys.swap(1, 2);
+
+    Sample {
+        title: "reordered blocks",
+        filename: "test6_reordered_blocks.bc",
+        data: serialized.to_vec().unwrap(),
+        valid: false,
+        validations: vec![("", file1())],
+    }
+}
+
+fn invalid_block_fact<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair, fact: &str) -> Vec<u8> {
+    let mut builder = Biscuit::builder(root);
+    builder.add_authority_fact("right(#authority, \"file1\", #read)").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2.add_caveat("caveat1($0) <- operation(#ambient, #read)").unwrap();
+    block2.add_fact(fact).unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    biscuit2.to_vec().unwrap()
+}
+
+fn invalid_block_fact_authority<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    Sample {
+        title: "invalid block fact with authority tag",
+        filename: "test7_invalid_block_fact_authority.bc",
+        data: invalid_block_fact(rng, root, "right(#authority, \"file1\", #write)"),
+        valid: true,
+        validations: vec![("", file1())],
+    }
+}
+
+fn invalid_block_fact_ambient<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    Sample {
+        title: "invalid block fact with ambient tag",
+        filename: "test8_invalid_block_fact_ambient.bc",
+        data: invalid_block_fact(rng, root, "right(#ambient, \"file1\", #write)"),
+        valid: true,
+        validations: vec![("", file1())],
+    }
+}
+
+fn expired_token<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let biscuit1 = Biscuit::builder(root).build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2.add_caveat("caveat1(\"file1\") <- resource(#ambient, \"file1\")").unwrap();
+    // December 20 2018
+    block2.expiration_date(UNIX_EPOCH + Duration::from_secs(49 * 365 * 24 * 3600));
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "expired token",
+        filename: "test9_expired_token.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![(
+            "",
+            Validation::new(
+                &[
+                    "resource(#ambient, \"file1\")",
+                    "operation(#ambient, #read)",
+                    "time(#ambient, 2020-12-21T09:23:12+00:00)",
+                ],
+                &[],
+            ),
+        )],
+    }
+}
+
+fn authority_rules<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder
+        .add_authority_rule("right(#authority, $1, #read) <- resource(#ambient, $1), owner(#ambient, $0, $1)")
+        .unwrap();
+    builder
+        .add_authority_rule("right(#authority, $1, #write) <- resource(#ambient, $1), owner(#ambient, $0, $1)")
+        .unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2
+        .add_caveat("caveat1($0, $1) <- right(#authority, $0, $1), resource(#ambient, $0), operation(#ambient, $1)")
+        .unwrap();
+    block2
+        .add_caveat("caveat2($0) <- resource(#ambient, $0), owner(#ambient, #alice, $0)")
+        .unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "authority rules",
+        filename: "test10_authority_rules.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![(
+            "",
+            Validation::new(
+                &[
+                    "resource(#ambient, \"file1\")",
+                    "operation(#ambient, #read)",
+                    "owner(#ambient, #alice, \"file1\")",
+                ],
+                &[],
+            ),
+        )],
+    }
+}
+
+fn verifier_authority_caveats<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_authority_fact("right(#authority, \"file1\", #read)").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    Sample {
+        title: "verifier authority caveats",
+        filename: "test11_verifier_authority_caveats.bc",
+        data: biscuit1.to_vec().unwrap(),
+        valid: true,
+        validations: vec![(
+            "",
+            Validation::new(
+                &["resource(#ambient, \"file2\")", "operation(#ambient, #read)"],
+                &["caveat1($0, $1) <- right(#authority, $0, $1), resource(#ambient, $0), operation(#ambient, $1)"],
+            ),
+        )],
+    }
+}
+
+fn authority_caveats<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder
+        .add_authority_caveat("caveat1(\"file1\") <- resource(#ambient, \"file1\")")
+        .unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    Sample {
+        title: "authority caveats",
+        filename: "test12_authority_caveats.bc",
+        data: biscuit1.to_vec().unwrap(),
+        valid: true,
+        validations: vec![
+            (
+                "file1",
+                Validation::new(&["resource(#ambient, \"file1\")", "operation(#ambient, #read)"], &[]),
+            ),
+            (
+                "file2",
+                Validation::new(&["resource(#ambient, \"file2\")", "operation(#ambient, #read)"], &[]),
+            ),
+        ],
+    }
+}
+
+fn block_rules<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_authority_fact("right(#authority, \"file1\", #read)").unwrap();
+    builder.add_authority_fact("right(#authority, \"file2\", #read)").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    // generate valid_date("file1") if before Thursday, December 31, 2030 12:59:59 PM UTC
+    block2
+        .add_rule(
+            "valid_date(\"file1\") <- time(#ambient, $0), resource(#ambient, \"file1\") @ $0 <= 2030-12-31T12:59:59+00:00",
+        )
+        .unwrap();
+    // generate a valid date fact for any file other than "file1" if before
+    // Friday, December 31, 1999 12:59:59 PM UTC
+    block2
+        .add_rule(
+            "valid_date($1) <- time(#ambient, $0), resource(#ambient, $1) @ $0 <= 1999-12-31T12:59:59+00:00, $1 not in [\"file1\"]",
+        )
+        .unwrap();
+    block2.add_caveat("caveat1($0) <- valid_date($0), resource(#ambient, $0)").unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    let time = "time(#ambient, 2020-12-21T09:23:12+00:00)";
+    Sample {
+        title: "block rules",
+        filename: "test13_block_rules.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![
+            ("file1", Validation::new(&["resource(#ambient, \"file1\")", time], &[])),
+            ("file2", Validation::new(&["resource(#ambient, \"file2\")", time], &[])),
+        ],
+    }
+}
+
+fn regex_constraint<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder
+        .add_authority_caveat("resource_match($0) <- resource(#ambient, $0) @ $0 matches /file[0-9]+.txt/")
+        .unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    Sample {
+        title: "regex constraint",
+        filename: "test14_regex_constraint.bc",
+        data: biscuit1.to_vec().unwrap(),
+        valid: true,
+        validations: vec![
+            ("file1", Validation::new(&["resource(#ambient, \"file1\")"], &[])),
+            ("file123.txt", Validation::new(&["resource(#ambient, \"file123.txt\")"], &[])),
+        ],
+    }
+}
+
+fn multi_queries_caveats<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_authority_fact("must_be_present(#authority, \"hello\")").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    Sample {
+        title: "multi queries caveats",
+        filename: "test15_multi_queries_caveats.bc",
+        data: biscuit1.to_vec().unwrap(),
+        valid: true,
+        validations: vec![(
+            "",
+            Validation::new(
+                &[],
+                &["test_must_be_present_authority($0) <- must_be_present(#authority, $0) || test_must_be_present($0) <- must_be_present($0)"],
+            ),
+        )],
+    }
+}
+
+fn caveat_head_name<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_authority_caveat("caveat1(#test) <- resource(#ambient, #hello)").unwrap();
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2.add_fact("caveat1(#test)").unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "caveat head name should be independent from fact names",
+        filename: "test16_caveat_head_name.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![("", Validation::new(&[], &[]))],
+    }
+}
+
+fn schema_v0<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_right("/folder1/file1", "read");
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2.resource_prefix("/folder1/");
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "blocks using only version 0 features",
+        filename: "test17_schema_v0.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![(
+            "",
+            Validation::new(&["resource(#ambient, \"/folder1/file1\")", "operation(#ambient, #read)"], &[]),
+        )],
+    }
+}
+
+fn schema_v1<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_right("/folder1/file1", "read");
+    builder.add_right("/folder2/file1", "read");
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2
+        .add_caveat("resource_contains($0) <- resource(#ambient, $0) @ $0 contains \"folder1\"")
+        .unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "block using the version 1 contains constraint",
+        filename: "test18_schema_v1.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![
+            ("/folder1/file1", Validation::new(&["resource(#ambient, \"/folder1/file1\")"], &[])),
+            ("/folder2/file1", Validation::new(&["resource(#ambient, \"/folder2/file1\")"], &[])),
+        ],
+    }
+}
+
+fn schema_v2<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_right("file1", "read");
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2
+        .add_caveat("office_network($0) <- client_ip(#ambient, $0) @ $0 in cidr:10.0.0.0/8")
+        .unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "block using the version 2 IP network constraint",
+        filename: "test19_schema_v2.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![
+            ("10.1.2.3", Validation::new(&["client_ip(#ambient, ip:10.1.2.3)"], &[])),
+            ("192.168.0.1", Validation::new(&["client_ip(#ambient, ip:192.168.0.1)"], &[])),
+        ],
+    }
+}
+
+fn schema_v3<T: RngCore + CryptoRng>(rng: &mut T, root: &KeyPair) -> Sample {
+    let mut builder = Biscuit::builder(root);
+    builder.add_right("/files/team/file1", "read");
+    builder.add_right("/files/teams/file1", "read");
+    let biscuit1 = builder.build_with_rng(rng).unwrap();
+
+    let mut block2 = biscuit1.create_block();
+    block2.resource_under("/files/team");
+    block2
+        .add_caveat("file1($0) <- resource(#ambient, $0) @ $0 glob \"/files/**/file1\"")
+        .unwrap();
+    let keypair2 = KeyPair::new_with_rng(rng);
+    let biscuit2 = biscuit1.append_with_rng(rng, &keypair2, block2).unwrap();
+
+    Sample {
+        title: "block using the version 3 path constraints",
+        filename: "test20_schema_v3.bc",
+        data: biscuit2.to_vec().unwrap(),
+        valid: true,
+        validations: vec![
+            ("/files/team/file1", Validation::new(&["resource(#ambient, \"/files/team/file1\")"], &[])),
+            ("/files/teams/file1", Validation::new(&["resource(#ambient, \"/files/teams/file1\")"], &[])),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn samples() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("samples");
+        let manifest = Manifest::load(&dir).unwrap();
+        assert_eq!(manifest.testcases.len(), 20);
+
+        if let Err(errors) = manifest.check(&dir) {
+            panic!("conformance failures:\n{}", errors.join("\n"));
+        }
+    }
+
+    #[test]
+    fn generated_samples() {
+        let dir = std::env::temp_dir().join(format!("biscuit-samples-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(1234);
+        let manifest = generate(&mut rng, &dir).unwrap();
+        let result = Manifest::load(&dir).map(|m| (m == manifest, m.check(&dir)));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (same, checked) = result.unwrap();
+        assert!(same);
+        checked.unwrap();
+    }
+}
@@ -0,0 +1,231 @@
+//! Ed25519 keys and signatures, used by the alternative token format
+//!
+//! see [`Ed25519SerializedBiscuit`](`crate::format::ed25519::Ed25519SerializedBiscuit`)
+use super::encoding::{self, ED25519_PKCS8_PREFIX, ED25519_SPKI_PREFIX};
+use crate::error;
+use rand_core::{CryptoRng, RngCore};
+use std::{convert::TryFrom, fmt};
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::{Zeroize, Zeroizing};
+
+pub struct KeyPair {
+    pub(crate) private: PrivateKey,
+    pub(crate) public: PublicKey,
+}
+
+impl KeyPair {
+    pub fn new() -> Self {
+        Self::new_with_rng(&mut rand::rngs::OsRng)
+    }
+
+    pub fn new_with_rng<T: RngCore + CryptoRng>(rng: &mut T) -> Self {
+        KeyPair::from(PrivateKey(ed25519_dalek::SecretKey::generate(rng)))
+    }
+
+    pub fn from(key: PrivateKey) -> Self {
+        let public = PublicKey(ed25519_dalek::PublicKey::from(&key.0));
+
+        KeyPair {
+            private: key,
+            public,
+        }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        // the expanded key is zeroized on drop by ed25519-dalek
+        let expanded = ed25519_dalek::ExpandedSecretKey::from(&self.private.0);
+        Signature(expanded.sign(message, &self.public.0))
+    }
+
+    /// borrows the private key, which is wiped when the keypair is dropped
+    pub fn private(&self) -> &PrivateKey {
+        &self.private
+    }
+
+    pub fn public(&self) -> PublicKey {
+        self.public
+    }
+}
+
+impl Default for KeyPair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "KeyPair {{ public: {:?} }}", self.public())
+    }
+}
+
+/// private key, wiped from memory on drop
+///
+/// this type does not implement `Clone`, to avoid leaving copies of the key
+pub struct PrivateKey(pub(crate) ed25519_dalek::SecretKey);
+
+impl PrivateKey {
+    /// returns the key bytes, in a buffer that is wiped on drop
+    pub fn to_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.0.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        ed25519_dalek::SecretKey::from_bytes(bytes).ok().map(PrivateKey)
+    }
+}
+
+/// the PEM body of Ed25519 keys is a DER encoded PKCS#8 structure (RFC 8410)
+impl PrivateKey {
+    fn to_pem_body(&self) -> Zeroizing<Vec<u8>> {
+        let mut der = Zeroizing::new(Vec::with_capacity(ED25519_PKCS8_PREFIX.len() + 32));
+        der.extend_from_slice(&ED25519_PKCS8_PREFIX);
+        der.extend_from_slice(self.0.as_bytes());
+        der
+    }
+
+    fn from_pem_body(der: &[u8]) -> Result<Self, error::Format> {
+        let bytes = encoding::strip_prefix(&ED25519_PKCS8_PREFIX, der)?;
+        Self::from_bytes(bytes).ok_or_else(|| encoding::invalid_key("invalid key bytes"))
+    }
+}
+
+encoding::key_encodings!(PrivateKey, encoding::PKCS8_LABEL, secret);
+
+// the secret key is also zeroized on drop by ed25519-dalek
+impl Zeroize for PrivateKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl ConstantTimeEq for PrivateKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.as_bytes().ct_eq(other.0.as_bytes())
+    }
+}
+
+impl PartialEq for PrivateKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for PrivateKey {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PublicKey(pub(crate) ed25519_dalek::PublicKey);
+
+impl ConstantTimeEq for PublicKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.as_bytes().ct_eq(other.0.as_bytes())
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl PublicKey {
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        ed25519_dalek::PublicKey::from_bytes(bytes).ok().map(PublicKey)
+    }
+
+    /// checks the signature of a message
+    ///
+    /// this uses strict verification, rejecting weak keys and malleable signatures
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        self.0.verify_strict(message, &signature.0).is_ok()
+    }
+}
+
+/// a DER encoded SubjectPublicKeyInfo structure (RFC 8410)
+impl PublicKey {
+    fn to_pem_body(self) -> Vec<u8> {
+        let mut der = ED25519_SPKI_PREFIX.to_vec();
+        der.extend_from_slice(self.0.as_bytes());
+        der
+    }
+
+    fn from_pem_body(der: &[u8]) -> Result<Self, error::Format> {
+        let bytes = encoding::strip_prefix(&ED25519_SPKI_PREFIX, der)?;
+        Self::from_bytes(bytes).ok_or_else(|| encoding::invalid_key("invalid key bytes"))
+    }
+}
+
+encoding::key_encodings!(PublicKey, encoding::SPKI_LABEL, public);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Signature(pub(crate) ed25519_dalek::Signature);
+
+impl ConstantTimeEq for Signature {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.to_bytes()[..].ct_eq(&other.to_bytes()[..])
+    }
+}
+
+impl PartialEq for Signature {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Signature {}
+
+impl Signature {
+    pub fn to_bytes(&self) -> [u8; 64] {
+        self.0.to_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        ed25519_dalek::Signature::try_from(bytes).ok().map(Signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let keypair = KeyPair::new_with_rng(&mut rng);
+
+        let signature = keypair.sign(&b"hello"[..]);
+        assert!(keypair.public().verify(&b"hello"[..], &signature));
+        assert!(!keypair.public().verify(&b"hello!"[..], &signature));
+
+        let signature2 = Signature::from_bytes(&signature.to_bytes()[..]).unwrap();
+        let public2 = PublicKey::from_bytes(&keypair.public().to_bytes()[..]).unwrap();
+        assert!(public2.verify(&b"hello"[..], &signature2));
+
+        let keypair2 = KeyPair::from(PrivateKey::from_bytes(&keypair.private().to_bytes()[..]).unwrap());
+        assert_eq!(keypair.public(), keypair2.public());
+    }
+
+    #[test]
+    fn secrets_zeroized() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let keypair = KeyPair::new_with_rng(&mut rng);
+
+        let mut private = PrivateKey::from_bytes(&keypair.private().to_bytes()[..]).unwrap();
+        let mut bytes = private.to_bytes();
+        assert_ne!(*private.0.as_bytes(), [0u8; 32]);
+        assert_ne!(*bytes, [0u8; 32]);
+
+        bytes.zeroize();
+        private.zeroize();
+
+        assert_eq!(*bytes, [0u8; 32]);
+        assert_eq!(*private.0.as_bytes(), [0u8; 32]);
+    }
+}
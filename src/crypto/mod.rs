@@ -7,21 +7,34 @@
 //!
 //! The implementation is based on [curve25519_dalek](https://github.com/dalek-cryptography/curve25519-dalek),
 //! a Rust implementation of the Ristretto group over Ed25519.
+//!
+//! There are two versions of the signature scheme, indicated by the `version`
+//! field of the serialized token:
+//! - version 0 ([`LEGACY_SIGNATURE_VERSION`]): the challenges are hashes of the
+//!   points and messages, without domain separation
+//! - version 1 ([`SIGNATURE_VERSION`]): the challenges are domain separated,
+//!   and bind the block index and a hash of all the previous blocks' keys,
+//!   nonces and messages
 #![allow(non_snake_case)]
+pub mod ed25519;
+pub mod encoding;
+
 use super::error;
 use curve25519_dalek::{
     constants::RISTRETTO_BASEPOINT_POINT,
     ristretto::{CompressedRistretto, RistrettoPoint},
     scalar::Scalar,
-    traits::Identity,
+    traits::{IsIdentity, VartimeMultiscalarMul},
 };
-use zeroize::Zeroize;
+use hkdf::Hkdf;
+use subtle::{Choice, ConstantTimeEq};
+use zeroize::{Zeroize, Zeroizing};
 use rand_core::{RngCore, CryptoRng};
 use sha2::{Digest, Sha512};
 use std::{ops::{Deref, Drop}, convert::TryInto};
 
 pub struct KeyPair {
-    pub(crate) private: Scalar,
+    pub(crate) private: PrivateKey,
     pub(crate) public: RistrettoPoint,
 }
 
@@ -31,18 +44,49 @@ impl KeyPair {
     }
 
     pub fn new_with_rng<T: RngCore + CryptoRng>(rng: &mut T) -> Self {
-        let private = Scalar::random(rng);
-        let public = private * RISTRETTO_BASEPOINT_POINT;
-
-        KeyPair { private, public }
+        KeyPair::from(PrivateKey(Scalar::random(rng)))
     }
 
     pub fn from(key: PrivateKey) -> Self {
-        let private = key.0;
+        let public = key.0 * RISTRETTO_BASEPOINT_POINT;
+
+        KeyPair { private: key, public }
+    }
+
+    /// derives a keypair from a master seed and a path
+    ///
+    /// The path is a list of components separated by `/`, like `tenants/1234`
+    /// (empty components are ignored).
+    /// Each component is applied successively with HKDF-SHA512, and the last
+    /// output is reduced to a scalar, so the same seed and path always give the
+    /// same keypair, and knowing a derived key does not reveal the seed or
+    /// the keys of other paths.
+    ///
+    /// Since the derivation is not linear, there is no way to derive the public
+    /// key without the master seed.
+    ///
+    /// The master seed must be at least 32 bytes long and kept secret.
+    pub fn derive(master_seed: &[u8], path: &str) -> Result<Self, error::Format> {
+        if master_seed.len() < 32 {
+            return Err(error::Format::InvalidKey(format!(
+                "the master seed must be at least 32 bytes, got {}",
+                master_seed.len()
+            )));
+        }
 
-        let public = private * RISTRETTO_BASEPOINT_POINT;
+        let mut chain = hkdf_expand(master_seed, &[DERIVATION_CHAIN_INFO]);
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            let next = hkdf_expand(&chain[..], &[DERIVATION_CHAIN_INFO, component.as_bytes()]);
+            chain.zeroize();
+            chain = next;
+        }
 
-        KeyPair { private, public }
+        let mut output = hkdf_expand(&chain[..], &[DERIVATION_KEY_INFO]);
+        let private = Scalar::from_bytes_mod_order_wide(&output);
+        chain.zeroize();
+        output.zeroize();
+
+        Ok(KeyPair::from(PrivateKey(private)))
     }
 
     #[allow(dead_code)]
@@ -51,28 +95,69 @@ impl KeyPair {
         let A = r * RISTRETTO_BASEPOINT_POINT;
         let d = hash_points(&[A]);
         let e = hash_message(self.public, message);
-        let z = r * d - e * self.private;
+        let z = r * d - e * self.private.0;
 
         r.zeroize();
 
         (d, z)
     }
 
-    pub fn private(&self) -> PrivateKey {
-        PrivateKey(self.private)
+    /// borrows the private key, which is wiped when the keypair is dropped
+    pub fn private(&self) -> &PrivateKey {
+        &self.private
     }
 
     pub fn public(&self) -> PublicKey {
         PublicKey(self.public)
     }
+
+    /// signs a request or a challenge, to prove possession of this key
+    ///
+    /// the signature is domain separated from token signatures, so it cannot
+    /// be used as a block signature
+    pub fn sign_possession<T: RngCore + CryptoRng>(&self, rng: &mut T, message: &[u8]) -> Signature {
+        let mut r = Scalar::random(rng);
+        let A = r * RISTRETTO_BASEPOINT_POINT;
+        let c = possession_challenge(&self.public, &A, message);
+        let z = r + c * self.private.0;
+
+        r.zeroize();
+
+        Signature { A, z }
+    }
 }
 
-impl Drop for KeyPair {
-    fn drop(&mut self) {
-        self.private.zeroize();
+impl std::fmt::Debug for KeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "KeyPair {{ public: {:?} }}", self.public())
     }
 }
 
+const POSSESSION_DOMAIN: &[u8] = b"biscuit possession proof v1";
+
+fn possession_challenge(key: &RistrettoPoint, A: &RistrettoPoint, message: &[u8]) -> Scalar {
+    Scalar::from_hash(
+        Sha512::new()
+            .chain(POSSESSION_DOMAIN)
+            .chain(key.compress().as_bytes())
+            .chain(A.compress().as_bytes())
+            .chain((message.len() as u64).to_be_bytes())
+            .chain(message),
+    )
+}
+
+const DERIVATION_SALT: &[u8] = b"biscuit key derivation v1";
+const DERIVATION_CHAIN_INFO: &[u8] = b"chain";
+const DERIVATION_KEY_INFO: &[u8] = b"key";
+
+fn hkdf_expand(ikm: &[u8], info: &[&[u8]]) -> [u8; 64] {
+    let hk = Hkdf::<Sha512>::new(Some(DERIVATION_SALT), ikm);
+    let mut output = [0u8; 64];
+    // 64 bytes is below the maximum output size of HKDF-SHA512
+    hk.expand_multi_info(info, &mut output).unwrap();
+    output
+}
+
 #[allow(dead_code)]
 fn verify(public: &RistrettoPoint, message: &[u8], signature: &(Scalar, Scalar)) -> bool {
     let (d, z) = signature;
@@ -83,11 +168,96 @@ fn verify(public: &RistrettoPoint, message: &[u8], signature: &(Scalar, Scalar))
     hash_points(&[A]) == *d
 }
 
+/// signs the authority block of a token
+///
+/// [`KeyPair`] is the in memory implementation. Other implementations can keep
+/// the root private key outside of the process, and forward the message to
+/// a signing service that calls [`Signer::sign`] on its own [`KeyPair`].
+pub trait Signer {
+    /// public key matching the signing key
+    fn public_key(&self) -> PublicKey;
+
+    /// signs the message as the first block of a token
+    fn sign(&self, rng: &mut dyn CryptoRngCore, message: &[u8]) -> Result<Signature, error::Signature>;
+}
+
+impl Signer for KeyPair {
+    fn public_key(&self) -> PublicKey {
+        self.public()
+    }
+
+    fn sign(&self, mut rng: &mut dyn CryptoRngCore, message: &[u8]) -> Result<Signature, error::Signature> {
+        let (A, z) = Transcript::new(SIGNATURE_VERSION).sign(&mut rng, self, message);
+        Ok(Signature { A, z })
+    }
+}
+
+impl<S: Signer + ?Sized> Signer for &S {
+    fn public_key(&self) -> PublicKey {
+        (**self).public_key()
+    }
+
+    fn sign(&self, rng: &mut dyn CryptoRngCore, message: &[u8]) -> Result<Signature, error::Signature> {
+        (**self).sign(rng, message)
+    }
+}
+
+/// object safe combination of `RngCore` and `CryptoRng`, used by [`Signer`]
+pub trait CryptoRngCore: RngCore + CryptoRng {}
+
+impl<T: RngCore + CryptoRng> CryptoRngCore for T {}
+
+/// signature of the first block of a token, returned by a [`Signer`]
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub(crate) A: RistrettoPoint,
+    pub(crate) z: Scalar,
+}
+
+impl ConstantTimeEq for Signature {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.A.ct_eq(&other.A) & self.z.ct_eq(&other.z)
+    }
+}
+
+impl PartialEq for Signature {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for Signature {}
+
+impl Signature {
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(self.A.compress().as_bytes());
+        bytes[32..].copy_from_slice(self.z.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 64 {
+            return None;
+        }
+
+        let A = CompressedRistretto::from_slice(&bytes[..32]).decompress()?;
+        let z: [u8; 32] = bytes[32..].try_into().ok()?;
+        let z = Scalar::from_canonical_bytes(z)?;
+
+        Some(Signature { A, z })
+    }
+}
+
+/// private key, wiped from memory on drop
+///
+/// this type does not implement `Clone`, to avoid leaving copies of the key
 pub struct PrivateKey(pub(crate) Scalar);
 
 impl PrivateKey {
-    pub fn to_bytes(&self) -> [u8; 32] {
-        self.0.to_bytes()
+    /// returns the key bytes, in a buffer that is wiped on drop
+    pub fn to_bytes(&self) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(self.0.to_bytes())
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
@@ -96,27 +266,111 @@ impl PrivateKey {
     }
 }
 
+/// the PEM body of Ristretto255 keys is the raw 32 bytes of the key, not a
+/// DER structure, since there is no registered algorithm identifier for them
+impl PrivateKey {
+    fn to_pem_body(&self) -> Zeroizing<Vec<u8>> {
+        Zeroizing::new(self.to_bytes().to_vec())
+    }
+
+    fn from_pem_body(body: &[u8]) -> Result<Self, error::Format> {
+        Self::from_bytes(body).ok_or_else(|| encoding::invalid_key("invalid key bytes"))
+    }
+}
+
+encoding::key_encodings!(PrivateKey, encoding::RISTRETTO_PRIVATE_LABEL, secret);
+
+impl Zeroize for PrivateKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
 impl Drop for PrivateKey {
     fn drop(&mut self) {
-        self.0.zeroize();
+        self.zeroize();
+    }
+}
+
+impl ConstantTimeEq for PrivateKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for PrivateKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Eq for PrivateKey {}
+
+#[derive(Debug, Clone, Copy)]
 pub struct PublicKey(pub(crate) RistrettoPoint);
 
+impl ConstantTimeEq for PublicKey {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for PublicKey {}
+
 impl PublicKey {
     pub fn to_bytes(&self) -> [u8; 32] {
         self.0.compress().to_bytes()
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 32 {
+            return None;
+        }
+
         CompressedRistretto::from_slice(bytes)
             .decompress()
             .map(PublicKey)
     }
 }
 
+/// raw 32 bytes, like private keys
+impl PublicKey {
+    fn to_pem_body(self) -> Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+
+    fn from_pem_body(body: &[u8]) -> Result<Self, error::Format> {
+        Self::from_bytes(body).ok_or_else(|| encoding::invalid_key("invalid key bytes"))
+    }
+}
+
+encoding::key_encodings!(PublicKey, encoding::RISTRETTO_PUBLIC_LABEL, public);
+
+impl PublicKey {
+    /// checks a signature created by [`KeyPair::sign_possession`]
+    pub fn verify_possession(&self, message: &[u8], signature: &Signature) -> Result<(), error::Signature> {
+        let c = possession_challenge(&self.0, &signature.A, message);
+
+        // z * B - c * X - A must be the identity point
+        let res = RistrettoPoint::vartime_multiscalar_mul(
+            &[signature.z, -c, -Scalar::one()],
+            &[RISTRETTO_BASEPOINT_POINT, self.0, signature.A],
+        );
+
+        if res.is_identity() {
+            Ok(())
+        } else {
+            Err(error::Signature::InvalidSignature)
+        }
+    }
+}
+
 #[allow(dead_code)]
 /// test structure for aggregated signatures
 struct Token {
@@ -144,7 +398,7 @@ impl Token {
         keypair: &KeyPair,
         message: &[u8],
     ) -> Self {
-        let signature = self.signature.sign(rng, keypair, message);
+        let signature = self.signature.sign(rng, &self.keys, &self.messages, keypair, message);
 
         let mut t = Token {
             messages: self.messages.clone(),
@@ -164,40 +418,105 @@ impl Token {
     }
 }
 
+/// signature scheme used for new tokens
+pub const SIGNATURE_VERSION: u32 = 1;
+
+/// original signature scheme, accepted only on request
+pub const LEGACY_SIGNATURE_VERSION: u32 = 0;
+
 #[derive(Clone, Debug)]
 pub struct TokenSignature {
     pub parameters: Vec<RistrettoPoint>,
     pub z: Scalar,
+    /// version of the signature scheme
+    pub version: u32,
 }
 
+impl ConstantTimeEq for TokenSignature {
+    fn ct_eq(&self, other: &Self) -> Choice {
+        let mut res = Choice::from((self.parameters.len() == other.parameters.len()
+            && self.version == other.version) as u8);
+        for (p1, p2) in self.parameters.iter().zip(other.parameters.iter()) {
+            res &= p1.ct_eq(p2);
+        }
+        res & self.z.ct_eq(&other.z)
+    }
+}
+
+impl PartialEq for TokenSignature {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+impl Eq for TokenSignature {}
+
 impl TokenSignature {
     pub fn new<T: RngCore + CryptoRng>(rng: &mut T, keypair: &KeyPair, message: &[u8]) -> Self {
-        let mut r = Scalar::random(rng);
-        let A = r * RISTRETTO_BASEPOINT_POINT;
-        let d = hash_points(&[A]);
-        let e = hash_message(keypair.public, message);
-        let z = r * d - e * keypair.private;
+        Self::new_with_version(rng, SIGNATURE_VERSION, keypair, message)
+    }
 
-        r.zeroize();
+    /// creates a signature with a specific version of the signature scheme
+    pub fn new_with_version<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        version: u32,
+        keypair: &KeyPair,
+        message: &[u8],
+    ) -> Self {
+        let transcript = Transcript::new(version);
+        let (A, z) = transcript.sign(rng, keypair, message);
 
         TokenSignature {
             parameters: vec![A],
             z,
+            version,
         }
     }
 
-    pub fn sign<T: RngCore + CryptoRng>(&self, rng: &mut T, keypair: &KeyPair, message: &[u8]) -> Self {
-        let mut r = Scalar::random(rng);
-        let A = r * RISTRETTO_BASEPOINT_POINT;
-        let d = hash_points(&[A]);
-        let e = hash_message(keypair.public, message);
-        let z = r * d - e * keypair.private;
+    /// creates a signature with an external signer
+    ///
+    /// the signature returned by the signer is checked before use
+    pub fn new_with_signer<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        signer: &dyn Signer,
+        message: &[u8],
+    ) -> Result<Self, error::Signature> {
+        let signature = signer.sign(rng, message)?;
 
-        r.zeroize();
+        let token_signature = TokenSignature {
+            parameters: vec![signature.A],
+            z: signature.z,
+            version: SIGNATURE_VERSION,
+        };
+        token_signature.verify(&[signer.public_key()], &[message])?;
+
+        Ok(token_signature)
+    }
+
+    /// adds a signature for a new message
+    ///
+    /// the public keys and messages already covered by this signature are
+    /// needed to compute the challenges for the new message
+    pub fn sign<T: RngCore + CryptoRng, M: Deref<Target = [u8]>>(
+        &self,
+        rng: &mut T,
+        public_keys: &[PublicKey],
+        messages: &[M],
+        keypair: &KeyPair,
+        message: &[u8],
+    ) -> Self {
+        let mut transcript = Transcript::new(self.version);
+        for ((pubkey, message), A) in public_keys.iter().zip(messages).zip(&self.parameters) {
+            let (e, _) = transcript.challenges(&pubkey.0, A, message);
+            transcript.advance(&pubkey.0, A, &e);
+        }
+
+        let (A, z) = transcript.sign(rng, keypair, message);
 
         let mut t = TokenSignature {
             parameters: self.parameters.clone(),
             z: self.z + z,
+            version: self.version,
         };
 
         t.parameters.push(A);
@@ -214,44 +533,179 @@ impl TokenSignature {
             return Err(error::Signature::InvalidFormat);
         }
 
-        let zP = self.z * RISTRETTO_BASEPOINT_POINT;
-        let eiXi = public_keys
-            .iter()
-            .zip(messages)
-            .map(|(pubkey, message)| {
-                let e = hash_message((*pubkey).0, message);
-                e * pubkey.0
-            })
-            .fold(RistrettoPoint::identity(), |acc, point| acc + point);
-
-        let diAi = self
-            .parameters
-            .iter()
-            .map(|A| {
-                let d = hash_points(&[*A]);
-                d * A
-            })
-            .fold(RistrettoPoint::identity(), |acc, point| acc + point);
-
-        let res = zP + eiXi - diAi;
-
-        /*
-        println!("verify identity={:?}", RistrettoPoint::identity());
-        println!("verify res={:?}", res);
-        println!("verify identity={:?}", RistrettoPoint::identity().compress());
-        println!("verify res={:?}", res.compress());
-        println!("returning: {:?}", RistrettoPoint::identity() == res);
-        */
-
-        if RistrettoPoint::identity() == res {
+        let (scalars, points) = self.verification_terms(public_keys, messages);
+        let res = RistrettoPoint::vartime_multiscalar_mul(scalars, points);
+
+        if res.is_identity() {
+            Ok(())
+        } else {
+            Err(error::Signature::InvalidSignature)
+        }
+    }
+
+    /// verifies multiple signatures at once
+    ///
+    /// each element contains the signature, the public keys and the messages.
+    /// The verification equations are combined with random coefficients and checked
+    /// with one multiscalar multiplication. If the batch fails, this does not
+    /// indicate which signature was invalid: use [`TokenSignature::verify`] on each
+    /// element to find it
+    pub fn verify_batch<T: RngCore + CryptoRng, M: Deref<Target = [u8]>>(
+        rng: &mut T,
+        signatures: &[(&TokenSignature, &[PublicKey], &[M])],
+    ) -> Result<(), error::Signature> {
+        // the base point coefficient is shared by all signatures
+        let mut scalars = vec![Scalar::zero()];
+        let mut points = vec![RISTRETTO_BASEPOINT_POINT];
+
+        for (signature, public_keys, messages) in signatures.iter() {
+            if !(public_keys.len() == messages.len()
+                && public_keys.len() == signature.parameters.len())
+            {
+                return Err(error::Signature::InvalidFormat);
+            }
+
+            let r = Scalar::random(rng);
+            let (s, p) = signature.verification_terms(public_keys, messages);
+
+            // the first term is z * B
+            scalars[0] += r * s[0];
+            scalars.extend(s[1..].iter().map(|scalar| r * scalar));
+            points.extend_from_slice(&p[1..]);
+        }
+
+        let res = RistrettoPoint::vartime_multiscalar_mul(scalars, points);
+
+        if res.is_identity() {
             Ok(())
         } else {
             Err(error::Signature::InvalidSignature)
         }
     }
+
+    /// generates the scalars and points of the verification equation
+    ///
+    /// z * B + sum(e_i * X_i) - sum(d_i * A_i) must be the identity point
+    fn verification_terms<M: Deref<Target = [u8]>>(
+        &self,
+        public_keys: &[PublicKey],
+        messages: &[M],
+    ) -> (Vec<Scalar>, Vec<RistrettoPoint>) {
+        let size = 1 + public_keys.len() + self.parameters.len();
+        let mut scalars = Vec::with_capacity(size);
+        let mut points = Vec::with_capacity(size);
+
+        scalars.push(self.z);
+        points.push(RISTRETTO_BASEPOINT_POINT);
+
+        let mut transcript = Transcript::new(self.version);
+        for ((pubkey, message), A) in public_keys.iter().zip(messages).zip(&self.parameters) {
+            let (e, d) = transcript.challenges(&pubkey.0, A, message);
+            transcript.advance(&pubkey.0, A, &e);
+
+            scalars.push(e);
+            points.push(pubkey.0);
+            scalars.push(-d);
+            points.push(*A);
+        }
+
+        (scalars, points)
+    }
+}
+
+const MESSAGE_DOMAIN: &[u8] = b"biscuit signature v1: message";
+const NONCE_DOMAIN: &[u8] = b"biscuit signature v1: nonce";
+const STATE_DOMAIN: &[u8] = b"biscuit signature v1: state";
+
+/// generates the challenges of each block in an aggregated signature
+///
+/// in version 1, the challenges for block `i` are:
+/// - `e_i = H(MESSAGE_DOMAIN || i || state_i || X_i || len(m_i) || m_i)`
+/// - `d_i = H(NONCE_DOMAIN || i || state_i || A_i)`
+///
+/// with `state_0` filled with zeros and
+/// `state_i+1 = SHA512(STATE_DOMAIN || i || state_i || X_i || A_i || e_i)`,
+/// so the challenges for a block depend on its position and on all of the
+/// previous blocks.
+struct Transcript {
+    version: u32,
+    index: u32,
+    state: [u8; 64],
+}
+
+impl Transcript {
+    fn new(version: u32) -> Self {
+        Transcript {
+            version,
+            index: 0,
+            state: [0u8; 64],
+        }
+    }
+
+    /// returns the `(e, d)` challenges for the current block
+    fn challenges(&self, key: &RistrettoPoint, A: &RistrettoPoint, message: &[u8]) -> (Scalar, Scalar) {
+        if self.version == LEGACY_SIGNATURE_VERSION {
+            return (hash_message(*key, message), hash_points(&[*A]));
+        }
+
+        let e = Scalar::from_hash(
+            Sha512::new()
+                .chain(MESSAGE_DOMAIN)
+                .chain(self.index.to_be_bytes())
+                .chain(&self.state[..])
+                .chain(key.compress().as_bytes())
+                .chain((message.len() as u64).to_be_bytes())
+                .chain(message),
+        );
+
+        let d = Scalar::from_hash(
+            Sha512::new()
+                .chain(NONCE_DOMAIN)
+                .chain(self.index.to_be_bytes())
+                .chain(&self.state[..])
+                .chain(A.compress().as_bytes()),
+        );
+
+        (e, d)
+    }
+
+    /// moves to the next block
+    fn advance(&mut self, key: &RistrettoPoint, A: &RistrettoPoint, e: &Scalar) {
+        if self.version != LEGACY_SIGNATURE_VERSION {
+            let h = Sha512::new()
+                .chain(STATE_DOMAIN)
+                .chain(self.index.to_be_bytes())
+                .chain(&self.state[..])
+                .chain(key.compress().as_bytes())
+                .chain(A.compress().as_bytes())
+                .chain(e.as_bytes())
+                .finalize();
+            self.state.copy_from_slice(&h[..]);
+        }
+
+        self.index += 1;
+    }
+
+    /// signs the current block, returns the `A` parameter and the `z` scalar
+    fn sign<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        keypair: &KeyPair,
+        message: &[u8],
+    ) -> (RistrettoPoint, Scalar) {
+        let mut r = Scalar::random(rng);
+        let A = r * RISTRETTO_BASEPOINT_POINT;
+        let (e, d) = self.challenges(&keypair.public, &A, message);
+        let z = r * d - e * keypair.private.0;
+
+        r.zeroize();
+
+        (A, z)
+    }
 }
 
-//FIXME: is the output value in the right set?
+// legacy challenges. `Scalar::from_hash` reduces the 512 bits output modulo
+// the group order, so the result is uniformly distributed over the scalars
 fn hash_points(points: &[RistrettoPoint]) -> Scalar {
     let mut h = Sha512::new();
     for point in points.iter() {
@@ -360,4 +814,152 @@ mod tests {
             "cannot verify third token"
         );
     }
+
+    #[test]
+    fn batch_verification() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+
+        let keypair1 = KeyPair::new_with_rng(&mut rng);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let keypair3 = KeyPair::new_with_rng(&mut rng);
+
+        let token1 = Token::new(&mut rng, &keypair1, &b"hello"[..]);
+        let token2 = token1.append(&mut rng, &keypair2, &b"world"[..]);
+        let token3 = Token::new(&mut rng, &keypair3, &b"!!!"[..]);
+
+        let batch = [
+            (&token1.signature, &token1.keys[..], &token1.messages[..]),
+            (&token2.signature, &token2.keys[..], &token2.messages[..]),
+            (&token3.signature, &token3.keys[..], &token3.messages[..]),
+        ];
+        assert_eq!(TokenSignature::verify_batch(&mut rng, &batch[..]), Ok(()));
+
+        let mut token4 = token2.append(&mut rng, &keypair3, &b"!!!"[..]);
+        token4.messages[1] = Vec::from(&b"you"[..]);
+
+        let batch = [
+            (&token1.signature, &token1.keys[..], &token1.messages[..]),
+            (&token4.signature, &token4.keys[..], &token4.messages[..]),
+            (&token3.signature, &token3.keys[..], &token3.messages[..]),
+        ];
+        assert_eq!(
+            TokenSignature::verify_batch(&mut rng, &batch[..]),
+            Err(error::Signature::InvalidSignature)
+        );
+
+        let batch = [
+            (&token1.signature, &token2.keys[..], &token1.messages[..]),
+        ];
+        assert_eq!(
+            TokenSignature::verify_batch(&mut rng, &batch[..]),
+            Err(error::Signature::InvalidFormat)
+        );
+    }
+
+    #[test]
+    fn reordered_messages() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+
+        for version in [LEGACY_SIGNATURE_VERSION, SIGNATURE_VERSION].iter() {
+            let keypair1 = KeyPair::new_with_rng(&mut rng);
+            let keypair2 = KeyPair::new_with_rng(&mut rng);
+            let keypair3 = KeyPair::new_with_rng(&mut rng);
+
+            let signature = TokenSignature::new_with_version(&mut rng, *version, &keypair1, &b"hello"[..]);
+            let mut token1 = Token::new(&mut rng, &keypair1, &b"hello"[..]);
+            token1.signature = signature;
+
+            let token2 = token1.append(&mut rng, &keypair2, &b"world"[..]);
+            let mut token3 = token2.append(&mut rng, &keypair3, &b"!!!"[..]);
+            assert_eq!(token3.verify(), Ok(()));
+
+            token3.messages.swap(1, 2);
+            token3.keys.swap(1, 2);
+            token3.signature.parameters.swap(1, 2);
+
+            if *version == LEGACY_SIGNATURE_VERSION {
+                // the legacy scheme does not bind the position of blocks
+                assert_eq!(token3.verify(), Ok(()));
+            } else {
+                assert_eq!(token3.verify(), Err(error::Signature::InvalidSignature));
+            }
+        }
+    }
+
+    #[test]
+    fn derivation() {
+        let seed = [1u8; 32];
+
+        let tenant1 = KeyPair::derive(&seed, "tenants/1").unwrap();
+        let tenant1_again = KeyPair::derive(&seed, "/tenants/1/").unwrap();
+        let tenant2 = KeyPair::derive(&seed, "tenants/2").unwrap();
+        let tenants = KeyPair::derive(&seed, "tenants").unwrap();
+        let other_seed = KeyPair::derive(&[2u8; 32], "tenants/1").unwrap();
+
+        assert_eq!(tenant1.public(), tenant1_again.public());
+        assert_ne!(tenant1.public(), tenant2.public());
+        assert_ne!(tenant1.public(), tenants.public());
+        assert_ne!(tenant1.public(), other_seed.public());
+
+        // components are not concatenated
+        assert_ne!(
+            KeyPair::derive(&seed, "ab/c").unwrap().public(),
+            KeyPair::derive(&seed, "a/bc").unwrap().public()
+        );
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let mut token = Token::new(&mut rng, &tenant1, &b"hello"[..]);
+        token = token.append(&mut rng, &tenant2, &b"world"[..]);
+        assert_eq!(token.verify(), Ok(()));
+
+        assert!(KeyPair::derive(&[0u8; 16], "tenants/1").is_err());
+    }
+
+    #[test]
+    fn secrets_zeroized() {
+        use std::mem::MaybeUninit;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let keypair = KeyPair::new_with_rng(&mut rng);
+
+        // the values are dropped in place, so their storage stays owned by the
+        // test and can still be read once the `Drop` implementations have run
+        let mut private = MaybeUninit::new(
+            PrivateKey::from_bytes(&keypair.private().to_bytes()[..]).unwrap(),
+        );
+        let mut bytes = MaybeUninit::new(keypair.private().to_bytes());
+        assert_ne!(unsafe { &*private.as_ptr() }.0, Scalar::zero());
+        assert_ne!(**unsafe { &*bytes.as_ptr() }, [0u8; 32]);
+
+        assert_eq!(std::mem::size_of::<PrivateKey>(), 32);
+        assert_eq!(std::mem::size_of::<Zeroizing<[u8; 32]>>(), 32);
+        let (private, bytes) = unsafe {
+            std::ptr::drop_in_place(private.as_mut_ptr());
+            std::ptr::drop_in_place(bytes.as_mut_ptr());
+            (
+                std::ptr::read(private.as_ptr() as *const [u8; 32]),
+                std::ptr::read(bytes.as_ptr() as *const [u8; 32]),
+            )
+        };
+
+        assert_eq!(private, [0u8; 32]);
+        assert_eq!(bytes, [0u8; 32]);
+    }
+
+    #[test]
+    fn constant_time_equality() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let keypair1 = KeyPair::new_with_rng(&mut rng);
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+
+        assert!(bool::from(keypair1.public().ct_eq(&keypair1.public())));
+        assert!(!bool::from(keypair1.public().ct_eq(&keypair2.public())));
+        assert!(keypair1.private() == keypair1.private());
+        assert!(keypair1.private() != keypair2.private());
+
+        let signature1 = TokenSignature::new(&mut rng, &keypair1, &b"hello"[..]);
+        let signature2 = signature1.sign(&mut rng, &[keypair1.public()], &[&b"hello"[..]], &keypair2, &b"world"[..]);
+        assert_eq!(signature1, signature1.clone());
+        assert_ne!(signature1, signature2);
+    }
 }
@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// IP network in CIDR notation, like `10.0.0.0/8`
+///
+/// the host bits of the address are cleared, so `10.1.2.3/8` and `10.0.0.0/8`
+/// are the same network
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(into = "String", try_from = "String"))]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// returns `None` if the prefix is longer than the address
+    pub fn new(address: IpAddr, prefix: u8) -> Option<Self> {
+        let address = match address {
+            IpAddr::V4(_) if prefix > 32 => return None,
+            IpAddr::V6(_) if prefix > 128 => return None,
+            IpAddr::V4(a) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V4((u32::from(a) & mask).into())
+            }
+            IpAddr::V6(a) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                IpAddr::V6((u128::from(a) & mask).into())
+            }
+        };
+
+        Some(IpNetwork { address, prefix })
+    }
+
+    /// first address of the network
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    /// number of bits of the network part of addresses
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// an IPv4 address is never part of an IPv6 network, and the reverse
+    ///
+    /// IPv4-mapped addresses and networks, like `::ffff:10.1.2.3` and
+    /// `::ffff:10.0.0.0/104`, are compared in their IPv4 form
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let network = self.canonical();
+        match (canonical_ip(*ip), network.address) {
+            (ip @ IpAddr::V4(_), IpAddr::V4(_)) | (ip @ IpAddr::V6(_), IpAddr::V6(_)) => {
+                IpNetwork::new(ip, network.prefix).map(|n| n.address) == Some(network.address)
+            }
+            _ => false,
+        }
+    }
+
+    /// converts an IPv4-mapped network to IPv4
+    fn canonical(&self) -> IpNetwork {
+        match canonical_ip(self.address) {
+            IpAddr::V4(v4) if self.address.is_ipv6() && self.prefix >= 96 => IpNetwork {
+                address: IpAddr::V4(v4),
+                prefix: self.prefix - 96,
+            },
+            _ => *self,
+        }
+    }
+}
+
+/// converts an IPv4-mapped IPv6 address, like `::ffff:10.1.2.3`, to IPv4
+///
+/// dual stack sockets report IPv4 clients in that form
+pub(crate) fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// checks that the address, or its IPv4 or IPv4-mapped form, is in the set
+pub(crate) fn set_contains_ip(set: &HashSet<IpAddr>, ip: IpAddr) -> bool {
+    match canonical_ip(ip) {
+        IpAddr::V4(v4) => set.contains(&IpAddr::V4(v4)) || set.contains(&IpAddr::V6(v4.to_ipv6_mapped())),
+        ip => set.contains(&ip),
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// error returned when parsing an invalid network
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("invalid IP network: {0}")]
+pub struct InvalidNetwork(pub String);
+
+impl FromStr for IpNetwork {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let address = parts.next().and_then(|a| a.parse().ok());
+        let prefix = parts.next().and_then(|p| p.parse().ok());
+
+        match (address, prefix) {
+            (Some(address), Some(prefix)) => IpNetwork::new(address, prefix),
+            _ => None,
+        }
+        .ok_or_else(|| InvalidNetwork(s.to_string()))
+    }
+}
+
+impl From<IpNetwork> for String {
+    fn from(n: IpNetwork) -> Self {
+        n.to_string()
+    }
+}
+
+impl TryFrom<String> for IpNetwork {
+    type Error = InvalidNetwork;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
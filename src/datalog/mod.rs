@@ -1,13 +1,19 @@
 //! Logic language implementation for caveats
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
 use std::convert::AsRef;
 use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use regex::Regex;
 
 pub type Symbol = u64;
 mod symbol;
 pub use symbol::*;
+mod ip;
+pub use ip::*;
+mod path;
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub enum ID {
@@ -17,6 +23,10 @@ pub enum ID {
     Str(String),
     Date(u64),
     Bytes(Vec<u8>),
+    /// since version 2 of the format
+    Ip(IpAddr),
+    /// since version 2 of the format
+    Network(IpNetwork),
 }
 
 impl From<&ID> for ID {
@@ -28,6 +38,8 @@ impl From<&ID> for ID {
             ID::Str(ref s) => ID::Str(s.clone()),
             ID::Date(ref d) => ID::Date(*d),
             ID::Bytes(ref b) => ID::Bytes(b.clone()),
+            ID::Ip(ref ip) => ID::Ip(*ip),
+            ID::Network(ref n) => ID::Network(*n),
         }
     }
 }
@@ -98,9 +110,13 @@ pub enum ConstraintKind {
     Date(DateConstraint),
     Symbol(SymbolConstraint),
     Bytes(BytesConstraint),
+    /// since version 2 of the format
+    Ip(IpConstraint),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum IntConstraint {
     Lower(i64),
     Larger(i64),
@@ -112,6 +128,8 @@ pub enum IntConstraint {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum StrConstraint {
     Prefix(String),
     Suffix(String),
@@ -119,6 +137,17 @@ pub enum StrConstraint {
     In(HashSet<String>),
     NotIn(HashSet<String>),
     Regex(String),
+    /// since version 1 of the format
+    Contains(String),
+    /// path equal to or below this one, comparing whole segments
+    ///
+    /// since version 3 of the format
+    Under(String),
+    /// path matching a pattern where a `*` segment matches one segment, and `**`
+    /// matches any number of segments
+    ///
+    /// since version 3 of the format
+    Glob(String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -134,14 +163,31 @@ pub enum SymbolConstraint {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum BytesConstraint {
-    Equal(Vec<u8>),
-    In(HashSet<Vec<u8>>),
-    NotIn(HashSet<Vec<u8>>),
+    Equal(#[cfg_attr(feature = "serde", serde(with = "crate::token::json::hex_bytes"))] Vec<u8>),
+    In(#[cfg_attr(feature = "serde", serde(with = "crate::token::json::hex_bytes_set"))] HashSet<Vec<u8>>),
+    NotIn(#[cfg_attr(feature = "serde", serde(with = "crate::token::json::hex_bytes_set"))] HashSet<Vec<u8>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IpConstraint {
+    Equal(IpAddr),
+    In(HashSet<IpAddr>),
+    NotIn(HashSet<IpAddr>),
+    InNetwork(IpNetwork),
 }
 
 impl Constraint {
     pub fn check(&self, name: u32, id: &ID) -> bool {
+        self.check_with(name, id, None)
+    }
+
+    /// checks the constraint, using the precompiled regular expressions if available
+    pub fn check_with(&self, name: u32, id: &ID, regexes: Option<&CompiledRegexes>) -> bool {
         if name != self.id {
             return true;
         }
@@ -161,16 +207,20 @@ impl Constraint {
                 StrConstraint::Prefix(pref) => s.as_str().starts_with(pref.as_str()),
                 StrConstraint::Suffix(suff) => s.as_str().ends_with(suff.as_str()),
                 StrConstraint::Equal(s2) => s == s2,
-                StrConstraint::Regex(r) => {
-                  if let Some(re) = Regex::new(r).ok() {
+                StrConstraint::Regex(r) => match regexes.and_then(|regexes| regexes.0.get(r)) {
+                  Some(re) => re.as_ref().map(|re| re.is_match(s)).unwrap_or(false),
+                  None => if let Some(re) = Regex::new(r).ok() {
                     re.is_match(s)
                   } else {
                     // an invalid regex will never match
                     false
-                  }
+                  },
                 },
                 StrConstraint::In(h) => h.contains(s),
                 StrConstraint::NotIn(h) => !h.contains(s),
+                StrConstraint::Contains(sub) => s.contains(sub.as_str()),
+                StrConstraint::Under(prefix) => path::is_under(s, prefix),
+                StrConstraint::Glob(pattern) => path::glob_match(s, pattern),
             },
             (ID::Date(d), ConstraintKind::Date(c)) => match c {
                 DateConstraint::Before(b) => d <= b,
@@ -185,6 +235,13 @@ impl Constraint {
                 BytesConstraint::In(h) => h.contains(s),
                 BytesConstraint::NotIn(h) => !h.contains(s),
             },
+            (ID::Ip(ip), ConstraintKind::Ip(c)) => match c {
+                // IPv4 addresses match their IPv4-mapped IPv6 form
+                IpConstraint::Equal(ip2) => canonical_ip(*ip) == canonical_ip(*ip2),
+                IpConstraint::In(h) => set_contains_ip(h, *ip),
+                IpConstraint::NotIn(h) => !set_contains_ip(h, *ip),
+                IpConstraint::InNetwork(n) => n.contains(ip),
+            },
             _ => false,
         }
     }
@@ -195,6 +252,42 @@ pub struct Caveat {
     pub queries: Vec<Rule>,
 }
 
+/// regular expressions of rule constraints, compiled once to be evaluated repeatedly
+///
+/// invalid patterns are stored as `None`, and never match
+#[derive(Debug, Clone, Default)]
+pub struct CompiledRegexes(HashMap<String, Option<Regex>>);
+
+impl CompiledRegexes {
+    pub fn new() -> Self {
+        CompiledRegexes::default()
+    }
+
+    /// compiles the regular expressions used in the rule's constraints
+    pub fn add_rule(&mut self, rule: &Rule) {
+        for constraint in rule.constraints.iter() {
+            if let ConstraintKind::Str(StrConstraint::Regex(r)) = &constraint.kind {
+                if !self.0.contains_key(r) {
+                    self.0.insert(r.clone(), Regex::new(r).ok());
+                }
+            }
+        }
+    }
+
+    pub fn add_caveat(&mut self, caveat: &Caveat) {
+        for query in caveat.queries.iter() {
+            self.add_rule(query);
+        }
+    }
+}
+
+/// compiled regexes only depend on their patterns
+impl PartialEq for CompiledRegexes {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.keys().all(|k| other.0.contains_key(k))
+    }
+}
+
 
 impl fmt::Display for Fact {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -203,7 +296,35 @@ impl fmt::Display for Fact {
 }
 
 impl Rule {
-    pub fn apply(&self, facts: &HashSet<Fact>, new_facts: &mut Vec<Fact>) {
+    pub fn apply<'a, F: Into<FactSet<'a>>>(&self, facts: F, new_facts: &mut Vec<Fact>, regexes: &CompiledRegexes) {
+        new_facts.extend(self.bindings(facts.into(), Some(regexes)).map(|h| {
+            let mut p = self.head.clone();
+            for index in 0..p.ids.len() {
+                let value = match &p.ids[index] {
+                    ID::Variable(i) => match h.get(i) {
+                      Some(val) => val,
+                      None => {
+                        println!("error: variables that appear in the head should appear in the body and constraints as well");
+                        continue;
+                      }
+                    },
+                    _ => continue,
+                };
+
+                p.ids[index] = value.clone();
+            }
+
+            Fact { predicate: p }
+        }));
+    }
+
+    /// checks if the rule would generate at least one fact, without generating it
+    pub fn matches<'a, F: Into<FactSet<'a>>>(&self, facts: F, regexes: &CompiledRegexes) -> bool {
+        self.bindings(facts.into(), Some(regexes)).next().is_some()
+    }
+
+    /// iterates over the variable bindings that satisfy the body and constraints
+    fn bindings<'a>(&'a self, facts: FactSet<'a>, regexes: Option<&'a CompiledRegexes>) -> CombineIt<'a> {
         // gather all of the variables used in that rule
         let variables_set = self
             .body
@@ -220,27 +341,77 @@ impl Rule {
 
         let variables = MatchedVariables::new(variables_set);
 
-        new_facts.extend(
-            CombineIt::new(variables, &self.body, &self.constraints, facts).map(|h| {
-                let mut p = self.head.clone();
-                for index in 0..p.ids.len() {
-                    let value = match &p.ids[index] {
-                        ID::Variable(i) => match h.get(i) {
-                          Some(val) => val,
-                          None => {
-                            println!("error: variables that appear in the head should appear in the body and constraints as well");
-                            continue;
-                          }
-                        },
-                        _ => continue,
-                    };
+        CombineIt::new(variables, &self.body, &self.constraints, facts, regexes)
+    }
+}
 
-                    p.ids[index] = value.clone();
-                }
+/// facts looked up during rule application instead of being stored in a world
+pub trait FactLookup: Send + Sync {
+    /// returns true if the facts with this predicate name are looked up
+    fn provides(&self, name: Symbol) -> bool;
 
-                Fact { predicate: p }
-            }),
-        );
+    /// returns true if the predicate is computed by the lookup only: facts with
+    /// this name stored in worlds are ignored
+    fn is_builtin(&self, _name: Symbol) -> bool {
+        false
+    }
+
+    /// returns facts that can match the predicate
+    ///
+    /// the terms bound by the current join are set in the predicate, the
+    /// others are variables
+    fn lookup(&self, predicate: &Predicate) -> Arc<Vec<Fact>>;
+}
+
+/// [`FactLookup`] attached to a world
+#[derive(Clone)]
+pub struct Lookup(pub Arc<dyn FactLookup>);
+
+impl fmt::Debug for Lookup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Lookup")
+    }
+}
+
+impl PartialEq for Lookup {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// facts visible during rule application: the facts of a world and those of its bases,
+/// and the facts provided by their lookup
+#[derive(Clone, Copy)]
+pub struct FactSet<'a> {
+    base: Option<&'a World>,
+    facts: &'a HashSet<Fact>,
+    lookup: Option<&'a dyn FactLookup>,
+}
+
+impl<'a> FactSet<'a> {
+    pub fn iter(self) -> Box<dyn Iterator<Item = &'a Fact> + 'a> {
+        match self.base {
+            None => Box::new(self.facts.iter()),
+            Some(base) => Box::new(base.all_facts().iter().chain(self.facts.iter())),
+        }
+    }
+
+    pub fn contains(&self, fact: &Fact) -> bool {
+        self.facts.contains(fact) || self.base.map(|b| b.all_facts().contains(fact)).unwrap_or(false)
+    }
+
+    pub fn len(&self) -> usize {
+        self.facts.len() + self.base.map(|b| b.all_facts().len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a> From<&'a HashSet<Fact>> for FactSet<'a> {
+    fn from(facts: &'a HashSet<Fact>) -> Self {
+        FactSet { base: None, facts, lookup: None }
     }
 }
 
@@ -249,8 +420,9 @@ pub struct CombineIt<'a> {
     variables: MatchedVariables,
     predicates: &'a [Predicate],
     constraints: &'a [Constraint],
-    all_facts: &'a HashSet<Fact>,
-    current_facts: Box<dyn Iterator<Item = &'a Fact> + 'a>,
+    all_facts: FactSet<'a>,
+    regexes: Option<&'a CompiledRegexes>,
+    current_facts: Box<dyn Iterator<Item = Cow<'a, Fact>> + 'a>,
     current_it: Option<Box<CombineIt<'a>>>,
 }
 
@@ -259,19 +431,42 @@ impl<'a> CombineIt<'a> {
         variables: MatchedVariables,
         predicates: &'a [Predicate],
         constraints: &'a [Constraint],
-        facts: &'a HashSet<Fact>,
+        facts: FactSet<'a>,
+        regexes: Option<&'a CompiledRegexes>,
     ) -> Self {
-        let p = predicates[0].clone();
+        let mut p = predicates[0].clone();
+        let stored = facts.iter().map(Cow::Borrowed);
+        let current_facts: Box<dyn Iterator<Item = Cow<'a, Fact>> + 'a> = match facts.lookup {
+            Some(lookup) if lookup.provides(p.name) => {
+                // look up facts with the variables bound so far
+                let mut query = p.clone();
+                for id in query.ids.iter_mut() {
+                    if let ID::Variable(v) = id {
+                        if let Some(Some(value)) = variables.0.get(v) {
+                            *id = value.clone();
+                        }
+                    }
+                }
+                let provided = lookup.lookup(&query);
+                let provided = (0..provided.len()).map(move |i| Cow::Owned(provided[i].clone()));
+                let builtin = lookup.is_builtin(p.name);
+                p = query;
+                if builtin {
+                    Box::new(provided)
+                } else {
+                    Box::new(stored.chain(provided))
+                }
+            }
+            _ => Box::new(stored),
+        };
+
         CombineIt {
             variables,
             predicates,
             constraints,
             all_facts: facts,
-            current_facts: Box::new(
-                facts
-                    .iter()
-                    .filter(move |fact| match_preds(&fact.predicate, &p)),
-            ),
+            regexes,
+            current_facts: Box::new(current_facts.filter(move |fact| match_preds(&fact.predicate, &p))),
             current_it: None,
         }
     }
@@ -300,7 +495,7 @@ impl<'a> Iterator for CombineIt<'a> {
                         for (key, id) in pred.ids.iter().zip(&current_fact.predicate.ids) {
                             if let (ID::Variable(k), id) = (key, id) {
                                 for c in self.constraints {
-                                    if !c.check(*k, id) {
+                                    if !c.check_with(*k, id, self.regexes) {
                                         match_ids = false;
                                         break;
                                     }
@@ -332,7 +527,8 @@ impl<'a> Iterator for CombineIt<'a> {
                                 vars,
                                 &self.predicates[1..],
                                 self.constraints,
-                                &self.all_facts,
+                                self.all_facts,
+                                self.regexes,
                             )));
                         }
                         break;
@@ -463,6 +659,9 @@ pub fn match_preds(pred1: &Predicate, pred2: &Predicate) -> bool {
                 (ID::Integer(i), ID::Integer(j)) => i == j,
                 (ID::Str(i), ID::Str(j)) => i == j,
                 (ID::Date(i), ID::Date(j)) => i == j,
+                (ID::Bytes(i), ID::Bytes(j)) => i == j,
+                (ID::Ip(i), ID::Ip(j)) => i == j,
+                (ID::Network(i), ID::Network(j)) => i == j,
                 _ => false,
             })
 }
@@ -471,6 +670,15 @@ pub fn match_preds(pred1: &Predicate, pred2: &Predicate) -> bool {
 pub struct World {
     pub facts: HashSet<Fact>,
     pub rules: Vec<Rule>,
+    /// shared facts and rules evaluated along with this world's own
+    ///
+    /// facts generated by rules are only added to this world, the base is never
+    /// modified. A base can have its own base
+    pub base: Option<Arc<World>>,
+    /// facts looked up during rule application, used instead of the bases' lookup
+    pub lookup: Option<Lookup>,
+    /// regular expressions of the rules added with `add_rule`
+    pub regexes: CompiledRegexes,
 }
 
 impl World {
@@ -478,11 +686,50 @@ impl World {
         World::default()
     }
 
+    /// creates an empty world on top of shared facts and rules
+    pub fn with_base(base: Arc<World>) -> Self {
+        World {
+            base: Some(base),
+            ..World::default()
+        }
+    }
+
+    /// facts of this world and of its bases
+    pub fn all_facts(&self) -> FactSet<'_> {
+        let base = self.base.as_deref();
+        FactSet {
+            base,
+            facts: &self.facts,
+            lookup: match self.lookup.as_ref() {
+                Some(lookup) => Some(lookup.0.as_ref()),
+                None => base.and_then(|b| b.all_facts().lookup),
+            },
+        }
+    }
+
+    /// rules of the bases, then rules of this world
+    pub fn all_rules(&self) -> Box<dyn Iterator<Item = &Rule> + '_> {
+        match self.base.as_ref() {
+            None => Box::new(self.rules.iter()),
+            Some(base) => Box::new(base.all_rules().chain(self.rules.iter())),
+        }
+    }
+
+    /// like `all_rules`, with the compiled regexes of the world holding each rule
+    fn all_rules_with_regexes(&self) -> Box<dyn Iterator<Item = (&Rule, &CompiledRegexes)> + '_> {
+        let rules = self.rules.iter().map(move |rule| (rule, &self.regexes));
+        match self.base.as_ref() {
+            None => Box::new(rules),
+            Some(base) => Box::new(base.all_rules_with_regexes().chain(rules)),
+        }
+    }
+
     pub fn add_fact(&mut self, fact: Fact) {
         self.facts.insert(fact);
     }
 
     pub fn add_rule(&mut self, rule: Rule) {
+        self.regexes.add_rule(&rule);
         self.rules.push(rule);
     }
 
@@ -497,13 +744,18 @@ impl World {
 
         loop {
             let mut new_facts: Vec<Fact> = Vec::new();
-            for rule in self.rules.iter() {
-                rule.apply(&self.facts, &mut new_facts);
+            for (rule, regexes) in self.all_rules_with_regexes() {
+                rule.apply(self.all_facts(), &mut new_facts, regexes);
                 //println!("new_facts after applying {:?}:\n{:#?}", rule, new_facts);
             }
 
             let len = self.facts.len();
-            self.facts.extend(new_facts.drain(..));
+            let base = self.base.as_ref().map(|b| b.all_facts());
+            self.facts.extend(
+                new_facts
+                    .drain(..)
+                    .filter(|f| !base.map(|b| b.contains(f)).unwrap_or(false)),
+            );
             if self.facts.len() == len {
                 break;
             }
@@ -513,7 +765,7 @@ impl World {
                 return Err(crate::error::RunLimit::TooManyIterations);
             }
 
-            if self.facts.len() >= limits.max_facts as usize {
+            if self.all_facts().len() >= limits.max_facts as usize {
                 return Err(crate::error::RunLimit::TooManyFacts);
             }
 
@@ -527,7 +779,7 @@ impl World {
     }
 
     pub fn query(&self, pred: Predicate) -> Vec<&Fact> {
-        self.facts
+        self.all_facts()
             .iter()
             .filter(|f| {
                 f.predicate.name == pred.name
@@ -541,6 +793,9 @@ impl World {
                             (ID::Integer(i), ID::Integer(ref j)) => i == j,
                             (ID::Str(i), ID::Str(ref j)) => i == j,
                             (ID::Date(i), ID::Date(ref j)) => i == j,
+                            (ID::Bytes(i), ID::Bytes(ref j)) => i == j,
+                            (ID::Ip(i), ID::Ip(ref j)) => i == j,
+                            (ID::Network(i), ID::Network(ref j)) => i == j,
                             _ => false,
                         })
             })
@@ -548,10 +803,17 @@ impl World {
     }
 
     pub fn query_rule(&self, rule: Rule) -> Vec<Fact> {
+        let mut regexes = CompiledRegexes::new();
+        regexes.add_rule(&rule);
         let mut new_facts: Vec<Fact> = Vec::new();
-        rule.apply(&self.facts, &mut new_facts);
+        rule.apply(self.all_facts(), &mut new_facts, &regexes);
         new_facts
     }
+
+    /// checks if the rule matches at least one combination of facts
+    pub fn query_match(&self, rule: &Rule, regexes: &CompiledRegexes) -> bool {
+        rule.matches(self.all_facts(), regexes)
+    }
 }
 
 pub fn sym(syms: &mut SymbolTable, name: &str) -> ID {
@@ -664,6 +926,49 @@ mod tests {
         */
     }
 
+    #[test]
+    fn base_world() {
+        let mut base = World::new();
+        let mut syms = SymbolTable::new();
+
+        let a = syms.add("A");
+        let b = syms.add("B");
+        let c = syms.add("C");
+        let parent = syms.insert("parent");
+        let grandparent = syms.insert("grandparent");
+
+        base.add_fact(fact(parent, &[&a, &b]));
+        base.add_rule(rule(
+            grandparent,
+            &[var(&mut syms, "grandparent"), var(&mut syms, "grandchild")],
+            &[
+                pred(parent, &[var(&mut syms, "grandparent"), var(&mut syms, "parent")]),
+                pred(parent, &[var(&mut syms, "parent"), var(&mut syms, "grandchild")]),
+            ],
+        ));
+        let base = Arc::new(base);
+        let limits = || RunLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let mut w = World::with_base(base.clone());
+        w.add_fact(fact(parent, &[&b, &c]));
+        w.run_with_limits(limits()).unwrap();
+
+        let res = w.query(pred(grandparent, &[var(&mut syms, "grandparent"), var(&mut syms, "grandchild")]));
+        assert_eq!(res, vec![&fact(grandparent, &[&a, &c])]);
+        assert_eq!(w.all_facts().len(), 3);
+        // base facts are not copied in the world
+        assert_eq!(w.facts.len(), 2);
+
+        // the base is not modified
+        assert_eq!(base.facts.len(), 1);
+        let mut w2 = World::with_base(base);
+        w2.run_with_limits(limits()).unwrap();
+        assert!(w2.query(pred(grandparent, &[var(&mut syms, "grandparent"), var(&mut syms, "grandchild")])).is_empty());
+    }
+
     #[test]
     fn numbers() {
         let mut w = World::new();
@@ -796,6 +1101,53 @@ mod tests {
         assert_eq!(res2, compared);
     }
 
+    #[test]
+    fn compiled_regexes() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let route = syms.insert("route");
+        let matched = syms.insert("matched");
+        w.add_fact(fact(route, &[&string("example.com")]));
+        w.add_fact(fact(route, &[&string("test.fr")]));
+
+        let mut regex_rule = |pattern: &str| constrained_rule(
+            matched,
+            &[var(&mut syms, "domain")],
+            &[pred(route, &[var(&mut syms, "domain")])],
+            &[Constraint {
+                id: syms.insert("domain") as u32,
+                kind: ConstraintKind::Str(StrConstraint::Regex(pattern.to_string())),
+            }],
+        );
+        let r1 = regex_rule(r"\.fr$");
+        let r2 = regex_rule(r"\.org$");
+        let r3 = regex_rule("(");
+
+        let mut regexes = CompiledRegexes::new();
+        regexes.add_rule(&r1);
+        regexes.add_rule(&r2);
+        regexes.add_rule(&r3);
+        assert_eq!(regexes.0.len(), 3);
+        assert!(regexes.0["("].is_none());
+
+        assert!(w.query_match(&r1, &regexes));
+        assert!(!w.query_match(&r2, &regexes));
+        assert!(!w.query_match(&r3, &regexes));
+
+        // results do not depend on the precompiled regexes
+        assert!(w.query_match(&r1, &CompiledRegexes::new()));
+        assert_eq!(w.query_rule(r1.clone()), vec![fact(matched, &[&string("test.fr")])]);
+
+        // rules added to a world are compiled once, before running it
+        w.add_rule(r1);
+        w.add_rule(r3);
+        assert_eq!(w.regexes.0.len(), 2);
+        w.run().unwrap();
+        assert!(w.facts.contains(&fact(matched, &[&string("test.fr")])));
+        assert!(!w.facts.contains(&fact(matched, &[&string("example.com")])));
+    }
+
     #[test]
     fn date_constraint() {
         let mut w = World::new();
@@ -959,6 +1311,135 @@ mod tests {
         assert_eq!(res2, compared);
     }
 
+    #[test]
+    fn ipv4_mapped_ip() {
+        let v4: IpAddr = "10.1.2.3".parse().unwrap();
+        let mapped: IpAddr = "::ffff:10.1.2.3".parse().unwrap();
+        let check = |ip: IpAddr, c: IpConstraint| {
+            Constraint { id: 0, kind: ConstraintKind::Ip(c) }.check(0, &ID::Ip(ip))
+        };
+
+        for (ip, constant) in [(v4, mapped), (mapped, v4)].iter() {
+            let set: HashSet<IpAddr> = [*constant].iter().cloned().collect();
+            assert!(check(*ip, IpConstraint::Equal(*constant)));
+            assert!(check(*ip, IpConstraint::In(set.clone())));
+            assert!(!check(*ip, IpConstraint::NotIn(set)));
+        }
+
+        assert!(check(mapped, IpConstraint::InNetwork("10.0.0.0/8".parse().unwrap())));
+        assert!(check(v4, IpConstraint::InNetwork("::ffff:10.0.0.0/104".parse().unwrap())));
+        assert!(!check(mapped, IpConstraint::InNetwork("192.168.0.0/16".parse().unwrap())));
+        assert!(!check("::1".parse().unwrap(), IpConstraint::InNetwork("0.0.0.0/0".parse().unwrap())));
+    }
+
+    #[test]
+    fn ip_constraint() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let client = syms.insert("client");
+        let office = syms.insert("office");
+        let allowed = syms.insert("allowed");
+        let blocked = syms.insert("blocked");
+
+        let v4: IpAddr = "10.1.2.3".parse().unwrap();
+        let other_v4: IpAddr = "192.168.0.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        for ip in [v4, other_v4, v6].iter() {
+            w.add_fact(fact(client, &[&ID::Ip(*ip)]));
+        }
+
+        let network: IpNetwork = "10.0.0.0/8".parse().unwrap();
+        assert_eq!(network, "10.255.0.1/8".parse().unwrap());
+        assert_eq!(network.to_string(), "10.0.0.0/8");
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("10.0.0.0".parse::<IpNetwork>().is_err());
+        assert!(!"::/0".parse::<IpNetwork>().unwrap().contains(&v4));
+
+        let res = w.query_rule(constrained_rule(
+            office,
+            &[var(&mut syms, "ip")],
+            &[pred(client, &[var(&mut syms, "ip")])],
+            &[Constraint {
+                id: syms.insert("ip") as u32,
+                kind: ConstraintKind::Ip(IpConstraint::InNetwork(network)),
+            }],
+        ));
+        assert_eq!(res, vec![fact(office, &[&ID::Ip(v4)])]);
+
+        let res = w.query_rule(constrained_rule(
+            allowed,
+            &[var(&mut syms, "ip")],
+            &[pred(client, &[var(&mut syms, "ip")])],
+            &[Constraint {
+                id: syms.insert("ip") as u32,
+                kind: ConstraintKind::Ip(IpConstraint::Equal(v6)),
+            }],
+        ));
+        assert_eq!(res, vec![fact(allowed, &[&ID::Ip(v6)])]);
+
+        let res = w.query_rule(constrained_rule(
+            blocked,
+            &[var(&mut syms, "ip")],
+            &[pred(client, &[var(&mut syms, "ip")])],
+            &[Constraint {
+                id: syms.insert("ip") as u32,
+                kind: ConstraintKind::Ip(IpConstraint::NotIn([v4, v6].iter().cloned().collect())),
+            }],
+        ));
+        assert_eq!(res, vec![fact(blocked, &[&ID::Ip(other_v4)])]);
+        assert_eq!(syms.print_fact(&res[0]), "blocked(ip:192.168.0.1)");
+
+        // constants in a predicate are matched like other terms
+        let ok = syms.add("ok");
+        let res = w.query_rule(rule(office, &[&ok], &[pred(client, &[&ID::Ip(v4)])]));
+        assert_eq!(res, vec![fact(office, &[&ok])]);
+        assert_eq!(w.query(pred(client, &[&ID::Ip(v6)])).len(), 1);
+
+        w.add_fact(fact(office, &[&ID::Network(network)]));
+        let res = w.query_rule(rule(allowed, &[&ok], &[pred(office, &[&ID::Network(network)])]));
+        assert_eq!(res, vec![fact(allowed, &[&ok])]);
+        assert_eq!(w.query(pred(office, &[&ID::Network(network)])).len(), 1);
+        let other_network = "10.0.0.0/16".parse().unwrap();
+        assert!(w.query(pred(office, &[&ID::Network(other_network)])).is_empty());
+    }
+
+    #[test]
+    fn path_constraint() {
+        let under = |path: &str, prefix: &str| Constraint {
+            id: 0,
+            kind: ConstraintKind::Str(StrConstraint::Under(prefix.to_string())),
+        }
+        .check(0, &ID::Str(path.to_string()));
+        let glob = |path: &str, pattern: &str| Constraint {
+            id: 0,
+            kind: ConstraintKind::Str(StrConstraint::Glob(pattern.to_string())),
+        }
+        .check(0, &ID::Str(path.to_string()));
+
+        assert!(under("/a/b", "/a/b"));
+        assert!(under("/a/b/", "/a/b"));
+        assert!(under("/a/b/c", "/a/b/"));
+        assert!(under("/a", "/"));
+        assert!(!under("/a/bc", "/a/b"));
+        assert!(!under("/a", "/a/b"));
+        assert!(!under("/a/b/../../etc", "/a/b"));
+        assert!(!under("a/b", "/a"));
+
+        assert!(glob("/files/1/read", "/files/*/read"));
+        assert!(!glob("/files/1/2/read", "/files/*/read"));
+        assert!(!glob("/files/read", "/files/*/read"));
+        assert!(glob("/files", "/files/**"));
+        assert!(glob("/files/1/2", "/files/**"));
+        assert!(!glob("/filesystem", "/files/**"));
+        assert!(glob("/files/1/2/read", "/files/**/read"));
+        assert!(glob("/files/read", "/files/**/read"));
+        assert!(!glob("/files/1/write", "/files/**/read"));
+        assert!(glob("/a/b/c/d", "/**/*/**/d"));
+        assert!(!glob("/files/a.txt", "/files/*.txt"));
+        assert!(!glob("/files/./1/read", "/files/**"));
+    }
+
     #[test]
     fn resource() {
         let mut w = World::new();
@@ -0,0 +1,59 @@
+//! segment aware matching of `/` separated paths
+//!
+//! trailing `/` are ignored, and paths containing `.` or `..` segments never
+//! match, so that `/files/../etc` is not considered under `/files`
+
+/// splits a path in segments, or returns `None` if it contains `.` or `..`
+fn segments(path: &str) -> Option<Vec<&str>> {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+    if segments.iter().any(|s| *s == "." || *s == "..") {
+        None
+    } else {
+        Some(segments)
+    }
+}
+
+/// checks that `path` is `prefix` or one of its descendants
+///
+/// `/a/b` and `/a/b/c` are under `/a/b`, but `/a/bc` is not
+pub(crate) fn is_under(path: &str, prefix: &str) -> bool {
+    match (segments(path), segments(prefix)) {
+        (Some(path), Some(prefix)) => path.starts_with(&prefix),
+        _ => false,
+    }
+}
+
+/// matches `path` with a glob pattern, where a `*` segment matches exactly one
+/// segment, and a `**` segment matches zero or more segments
+///
+/// `*` is only a wildcard when it is the whole segment, `/files/*.txt` only
+/// matches the literal `/files/*.txt`
+pub(crate) fn glob_match(path: &str, pattern: &str) -> bool {
+    let (path, pattern) = match (segments(path), segments(pattern)) {
+        (Some(path), Some(pattern)) => (path, pattern),
+        _ => return false,
+    };
+
+    // matched[j] is true if the pattern segments seen so far match path[..j].
+    // Unlike backtracking, this does not blow up with several `**` segments
+    let mut matched = vec![false; path.len() + 1];
+    matched[0] = true;
+
+    for segment in pattern {
+        let mut next = vec![false; path.len() + 1];
+        if segment == "**" {
+            let mut any = false;
+            for (j, m) in matched.iter().enumerate() {
+                any |= *m;
+                next[j] = any;
+            }
+        } else {
+            for j in 1..=path.len() {
+                next[j] = matched[j - 1] && (segment == "*" || segment == path[j - 1]);
+            }
+        }
+        matched = next;
+    }
+
+    matched[path.len()]
+}
@@ -5,7 +5,7 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 
 pub type Symbol = u64;
 use super::{ID, World, Fact, Rule, Constraint, ConstraintKind, Caveat,
-  IntConstraint, StrConstraint, SymbolConstraint, BytesConstraint,
+  IntConstraint, StrConstraint, SymbolConstraint, BytesConstraint, IpConstraint,
   DateConstraint, Predicate};
 
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -46,13 +46,12 @@ impl SymbolTable {
 
     pub fn print_world(&self, w: &World) -> String {
         let facts = w
-            .facts
+            .all_facts()
             .iter()
             .map(|f| self.print_fact(f))
             .collect::<Vec<_>>();
         let rules = w
-            .rules
-            .iter()
+            .all_rules()
             .map(|r| self.print_rule(r))
             .collect::<Vec<_>>();
         format!("World {{\n  facts: {:#?}\n  rules: {:#?}\n}}", facts, rules)
@@ -76,6 +75,8 @@ impl SymbolTable {
                     format!("{:?}", t)
                 },
                 ID::Bytes(s) => format!("hex:{}", hex::encode(s)),
+                ID::Ip(ip) => format!("ip:{}", ip),
+                ID::Network(n) => format!("cidr:{}", n),
             })
             .collect::<Vec<_>>();
         format!(
@@ -103,6 +104,9 @@ impl SymbolTable {
             ConstraintKind::Str(StrConstraint::Regex(i)) => format!("${} matches /{}/", self.print_symbol(c.id as u64), i),
             ConstraintKind::Str(StrConstraint::In(i)) => format!("${} in {:?}", self.print_symbol(c.id as u64), i),
             ConstraintKind::Str(StrConstraint::NotIn(i)) => format!("${} not in {:?}", self.print_symbol(c.id as u64), i),
+            ConstraintKind::Str(StrConstraint::Contains(i)) => format!("${} contains {}", self.print_symbol(c.id as u64), i),
+            ConstraintKind::Str(StrConstraint::Under(i)) => format!("${} under {}", self.print_symbol(c.id as u64), i),
+            ConstraintKind::Str(StrConstraint::Glob(i)) => format!("${} glob {}", self.print_symbol(c.id as u64), i),
             ConstraintKind::Date(DateConstraint::Before(i)) => {
               let date = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(*i as i64, 0), Utc);
               format!("${} <= {}", self.print_symbol(c.id as u64), date.to_rfc3339())
@@ -124,6 +128,16 @@ impl SymbolTable {
                 format!("${} not in {:?}", self.print_symbol(c.id as u64), i.iter()
                         .map(|s| format!("hex:{}", hex::encode(s))).collect::<HashSet<_>>())
             },
+            ConstraintKind::Ip(IpConstraint::Equal(ip)) => format!("${} == ip:{}", self.print_symbol(c.id as u64), ip),
+            ConstraintKind::Ip(IpConstraint::In(i)) => {
+                format!("${} in {:?}", self.print_symbol(c.id as u64), i.iter()
+                        .map(|ip| format!("ip:{}", ip)).collect::<HashSet<_>>())
+            },
+            ConstraintKind::Ip(IpConstraint::NotIn(i)) => {
+                format!("${} not in {:?}", self.print_symbol(c.id as u64), i.iter()
+                        .map(|ip| format!("ip:{}", ip)).collect::<HashSet<_>>())
+            },
+            ConstraintKind::Ip(IpConstraint::InNetwork(n)) => format!("${} in cidr:{}", self.print_symbol(c.id as u64), n),
         }
     }
 
@@ -26,6 +26,23 @@ pub enum Token {
     ParseError,
     #[error("Reached Datalog execution limits")]
     RunLimit(RunLimit),
+    #[error("the token was revoked")]
+    Revoked {
+        /// scope of the token, `None` for the token added with
+        /// [`Verifier::add_token`](crate::token::verifier::Verifier::add_token)
+        scope: Option<String>,
+        block_id: u32,
+    },
+    #[error("could not query the revocation store")]
+    RevocationStore(String),
+    #[error("could not query a fact provider")]
+    FactProvider(String),
+    #[error("no key is available for the proof of possession")]
+    MissingProofKey,
+    #[error("the proof of possession did not match the pinned key")]
+    InvalidPossessionProof,
+    #[error("a proof of possession key is already pinned in the token")]
+    ProofKeyAlreadyPinned,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -56,6 +73,35 @@ pub enum Format {
     BlockSerializationError(String),
     #[error("Block format version is higher than supported")]
     Version { maximum: u32, actual: u32 },
+    #[error("Signature version is higher than supported")]
+    SignatureVersion { maximum: u32, actual: u32 },
+    #[error("the token uses the legacy signature scheme")]
+    LegacySignature,
+    #[error("could not parse the key")]
+    InvalidKey(String),
+    #[error("the sealing key is unknown or expired")]
+    UnknownSealingKey(Option<u32>),
+    #[error("the token exceeds the deserialization limits")]
+    DeserializationLimit(DeserializationLimit),
+}
+
+/// deserialization limits errors
+#[derive(Error, Clone, Debug, PartialEq)]
+pub enum DeserializationLimit {
+    #[error("the serialized token is too large")]
+    TooLarge,
+    #[error("too many blocks")]
+    TooManyBlocks,
+    #[error("too many symbols in a block")]
+    TooManySymbols,
+    #[error("too many facts in a block")]
+    TooManyFacts,
+    #[error("too many rules in a block")]
+    TooManyRules,
+    #[error("too many terms in a predicate")]
+    TooManyTerms,
+    #[error("a string or byte array is too long")]
+    StringTooLong,
 }
 
 /// Signature errors
@@ -65,6 +111,8 @@ pub enum Signature {
     InvalidFormat,
     #[error("the signature did not match")]
     InvalidSignature,
+    #[error("the external signer failed")]
+    Signer(String),
 }
 
 /// errors in the Datalog evaluation
@@ -82,6 +130,10 @@ pub enum Logic {
     FailedCaveats(Vec<FailedCaveat>),
     #[error("the verifier already contains a token")]
     VerifierNotEmpty,
+    #[error("the token scope is reserved or already used")]
+    InvalidScope(String),
+    #[error("a rule or caveat references a builtin predicate that was not registered")]
+    UnknownBuiltin(String),
 }
 
 /// caveat check errors
@@ -91,6 +143,8 @@ pub enum FailedCaveat {
     Block(FailedBlockCaveat),
     #[error("a caveat provided by the verifier failed")]
     Verifier(FailedVerifierCaveat),
+    #[error("a caveat failed in a block of a scoped token")]
+    Token(FailedTokenCaveat),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -108,6 +162,17 @@ pub struct FailedVerifierCaveat {
     pub rule: String,
 }
 
+/// caveat of a token loaded with [`Verifier::add_scoped_token`](crate::token::verifier::Verifier::add_scoped_token)
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailedTokenCaveat {
+    /// scope of the token
+    pub scope: String,
+    pub block_id: u32,
+    pub caveat_id: u32,
+    /// pretty print of the rule that failed
+    pub rule: String,
+}
+
 /// runtime limits errors
 #[derive(Error, Clone, Debug, PartialEq)]
 pub enum RunLimit {
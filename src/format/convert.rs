@@ -4,6 +4,8 @@ use curve25519_dalek::{ristretto::CompressedRistretto, scalar::Scalar};
 
 use super::schema;
 use crate::datalog::*;
+use std::convert::TryFrom;
+use std::net::IpAddr;
 use crate::error;
 use crate::token::Block;
 
@@ -18,7 +20,14 @@ pub fn token_sig_to_proto_sig(input: &TokenSignature) -> schema::Signature {
     }
 }
 
-pub fn proto_sig_to_token_sig(input: schema::Signature) -> Result<TokenSignature, error::Format> {
+pub fn proto_sig_to_token_sig(input: schema::Signature, version: u32) -> Result<TokenSignature, error::Format> {
+    if version > crate::crypto::SIGNATURE_VERSION {
+        return Err(error::Format::SignatureVersion {
+            maximum: crate::crypto::SIGNATURE_VERSION,
+            actual: version,
+        });
+    }
+
     let mut parameters = vec![];
 
     for data in input.parameters {
@@ -55,7 +64,7 @@ pub fn proto_sig_to_token_sig(input: schema::Signature) -> Result<TokenSignature
         )));
     };
 
-    Ok(TokenSignature { parameters, z })
+    Ok(TokenSignature { parameters, z, version })
 }
 
 pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
@@ -71,6 +80,15 @@ pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
 }
 
 pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error::Format> {
+    // the version is checked first, so that unknown features are reported as such
+    let version = input.version.unwrap_or(crate::token::MIN_SCHEMA_VERSION);
+    if version > crate::token::MAX_SCHEMA_VERSION {
+        return Err(error::Format::Version {
+            maximum: crate::token::MAX_SCHEMA_VERSION,
+            actual: version,
+        });
+    }
+
     let mut facts = vec![];
     for fact in input.facts.iter() {
         facts.push(proto_fact_to_token_fact(fact)?);
@@ -88,15 +106,7 @@ pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error:
 
     let context = input.context.clone();
 
-    let version = input.version.unwrap_or(0);
-    if version > crate::token::MAX_SCHEMA_VERSION {
-        return Err(error::Format::Version {
-            maximum: crate::token::MAX_SCHEMA_VERSION,
-            actual: version,
-        });
-    }
-
-    Ok(Block {
+    let block = Block {
         index: input.index,
         symbols: SymbolTable {
             symbols: input.symbols.clone(),
@@ -106,7 +116,19 @@ pub fn proto_block_to_token_block(input: &schema::Block) -> Result<Block, error:
         caveats,
         context,
         version,
-    })
+    };
+
+    // a block must declare the version of the features it uses, so that older
+    // implementations reject it instead of misreading it
+    let required = block.required_version();
+    if required > version {
+        return Err(error::Format::DeserializationError(format!(
+            "deserialization error: block uses features of version {} but declares version {}",
+            required, version
+        )));
+    }
+
+    Ok(block)
 }
 
 pub fn token_fact_to_proto_fact(input: &Fact) -> schema::Fact {
@@ -207,6 +229,8 @@ pub fn token_id_to_proto_id(input: &ID) -> schema::Id {
             str: None,
             date: None,
             bytes: None,
+            ip: None,
+            network: None,
         },
         ID::Variable(v) => schema::Id {
             kind: Kind::Variable as i32,
@@ -216,6 +240,8 @@ pub fn token_id_to_proto_id(input: &ID) -> schema::Id {
             str: None,
             date: None,
             bytes: None,
+            ip: None,
+            network: None,
         },
         ID::Integer(i) => schema::Id {
             kind: Kind::Integer as i32,
@@ -225,6 +251,8 @@ pub fn token_id_to_proto_id(input: &ID) -> schema::Id {
             str: None,
             date: None,
             bytes: None,
+            ip: None,
+            network: None,
         },
         ID::Str(s) => schema::Id {
             kind: Kind::Str as i32,
@@ -234,6 +262,8 @@ pub fn token_id_to_proto_id(input: &ID) -> schema::Id {
             str: Some(s.clone()),
             date: None,
             bytes: None,
+            ip: None,
+            network: None,
         },
         ID::Date(d) => schema::Id {
             kind: Kind::Date as i32,
@@ -243,6 +273,8 @@ pub fn token_id_to_proto_id(input: &ID) -> schema::Id {
             str: None,
             date: Some(*d),
             bytes: None,
+            ip: None,
+            network: None,
         },
         ID::Bytes(s) => schema::Id {
             kind: Kind::Bytes as i32,
@@ -252,6 +284,30 @@ pub fn token_id_to_proto_id(input: &ID) -> schema::Id {
             str: None,
             date: None,
             bytes: Some(s.clone()),
+            ip: None,
+            network: None,
+        },
+        ID::Ip(ip) => schema::Id {
+            kind: Kind::Ip as i32,
+            symbol: None,
+            variable: None,
+            integer: None,
+            str: None,
+            date: None,
+            bytes: None,
+            ip: Some(token_ip_to_proto_ip(ip)),
+            network: None,
+        },
+        ID::Network(n) => schema::Id {
+            kind: Kind::Network as i32,
+            symbol: None,
+            variable: None,
+            integer: None,
+            str: None,
+            date: None,
+            bytes: None,
+            ip: None,
+            network: Some(token_network_to_proto_network(n)),
         },
     }
 }
@@ -298,6 +354,16 @@ pub fn proto_id_to_token_id(input: &schema::Id) -> Result<ID, error::Format> {
                 return Ok(ID::Bytes(s.clone()));
             }
         }
+        Kind::Ip => {
+            if let Some(ref ip) = input.ip {
+                return proto_ip_to_token_ip(ip).map(ID::Ip);
+            }
+        }
+        Kind::Network => {
+            if let Some(ref n) = input.network {
+                return proto_network_to_token_network(n).map(ID::Network);
+            }
+        }
     }
 
     Err(error::Format::DeserializationError(
@@ -317,6 +383,7 @@ pub fn token_constraint_to_proto_constraint(input: &Constraint) -> schema::Const
             date: None,
             symbol: None,
             bytes: None,
+            ip: None,
         },
         ConstraintKind::Str(ref c) => schema::Constraint {
             id: input.id,
@@ -326,6 +393,7 @@ pub fn token_constraint_to_proto_constraint(input: &Constraint) -> schema::Const
             date: None,
             symbol: None,
             bytes: None,
+            ip: None,
         },
         ConstraintKind::Date(ref c) => schema::Constraint {
             id: input.id,
@@ -335,6 +403,7 @@ pub fn token_constraint_to_proto_constraint(input: &Constraint) -> schema::Const
             date: Some(token_date_constraint_to_proto_date_constraint(c)),
             symbol: None,
             bytes: None,
+            ip: None,
         },
         ConstraintKind::Symbol(ref c) => schema::Constraint {
             id: input.id,
@@ -344,6 +413,7 @@ pub fn token_constraint_to_proto_constraint(input: &Constraint) -> schema::Const
             date: None,
             symbol: Some(token_symbol_constraint_to_proto_symbol_constraint(c)),
             bytes: None,
+            ip: None,
         },
         ConstraintKind::Bytes(ref c) => schema::Constraint {
             id: input.id,
@@ -353,7 +423,18 @@ pub fn token_constraint_to_proto_constraint(input: &Constraint) -> schema::Const
             date: None,
             symbol: None,
             bytes: Some(token_bytes_constraint_to_proto_bytes_constraint(c)),
-        }
+            ip: None,
+        },
+        ConstraintKind::Ip(ref c) => schema::Constraint {
+            id: input.id,
+            kind: Kind::Ip as i32,
+            int: None,
+            str: None,
+            date: None,
+            symbol: None,
+            bytes: None,
+            ip: Some(token_ip_constraint_to_proto_ip_constraint(c)),
+        },
     }
 }
 
@@ -411,6 +492,14 @@ pub fn proto_constraint_to_token_constraint(
                 });
             }
         }
+        Kind::Ip => {
+            if let Some(ref i) = input.ip {
+                return proto_ip_constraint_to_token_ip_constraint(i).map(|c| Constraint {
+                    id: input.id,
+                    kind: ConstraintKind::Ip(c),
+                });
+            }
+        }
     }
 
     Err(error::Format::DeserializationError(
@@ -569,6 +658,9 @@ pub fn token_str_constraint_to_proto_str_constraint(
             regex: None,
             in_set: vec![],
             not_in_set: vec![],
+            contains: None,
+            under: None,
+            glob: None,
         },
         StrConstraint::Suffix(s) => schema::StringConstraint {
             kind: Kind::Suffix as i32,
@@ -578,6 +670,9 @@ pub fn token_str_constraint_to_proto_str_constraint(
             regex: None,
             in_set: vec![],
             not_in_set: vec![],
+            contains: None,
+            under: None,
+            glob: None,
         },
         StrConstraint::Equal(s) => schema::StringConstraint {
             kind: Kind::Equal as i32,
@@ -587,6 +682,9 @@ pub fn token_str_constraint_to_proto_str_constraint(
             regex: None,
             in_set: vec![],
             not_in_set: vec![],
+            contains: None,
+            under: None,
+            glob: None,
         },
         StrConstraint::Regex(r) => schema::StringConstraint {
             kind: Kind::Regex as i32,
@@ -596,6 +694,9 @@ pub fn token_str_constraint_to_proto_str_constraint(
             in_set: vec![],
             not_in_set: vec![],
             regex: Some(r.clone()),
+            contains: None,
+            under: None,
+            glob: None,
         },
         StrConstraint::In(s) => schema::StringConstraint {
             kind: Kind::In as i32,
@@ -605,6 +706,9 @@ pub fn token_str_constraint_to_proto_str_constraint(
             regex: None,
             in_set: s.iter().cloned().collect(),
             not_in_set: vec![],
+            contains: None,
+            under: None,
+            glob: None,
         },
         StrConstraint::NotIn(s) => schema::StringConstraint {
             kind: Kind::NotIn as i32,
@@ -614,6 +718,45 @@ pub fn token_str_constraint_to_proto_str_constraint(
             regex: None,
             in_set: vec![],
             not_in_set: s.iter().cloned().collect(),
+            contains: None,
+            under: None,
+            glob: None,
+        },
+        StrConstraint::Contains(s) => schema::StringConstraint {
+            kind: Kind::Contains as i32,
+            prefix: None,
+            suffix: None,
+            equal: None,
+            regex: None,
+            in_set: vec![],
+            not_in_set: vec![],
+            contains: Some(s.clone()),
+            under: None,
+            glob: None,
+        },
+        StrConstraint::Under(s) => schema::StringConstraint {
+            kind: Kind::Under as i32,
+            prefix: None,
+            suffix: None,
+            equal: None,
+            regex: None,
+            in_set: vec![],
+            not_in_set: vec![],
+            contains: None,
+            under: Some(s.clone()),
+            glob: None,
+        },
+        StrConstraint::Glob(s) => schema::StringConstraint {
+            kind: Kind::Glob as i32,
+            prefix: None,
+            suffix: None,
+            equal: None,
+            regex: None,
+            in_set: vec![],
+            not_in_set: vec![],
+            contains: None,
+            under: None,
+            glob: Some(s.clone()),
         },
     }
 }
@@ -664,6 +807,21 @@ pub fn proto_str_constraint_to_token_str_constraint(
                 ));
             }
         }
+        Kind::Contains => {
+            if let Some(ref s) = input.contains {
+                return Ok(StrConstraint::Contains(s.clone()));
+            }
+        }
+        Kind::Under => {
+            if let Some(ref s) = input.under {
+                return Ok(StrConstraint::Under(s.clone()));
+            }
+        }
+        Kind::Glob => {
+            if let Some(ref s) = input.glob {
+                return Ok(StrConstraint::Glob(s.clone()));
+            }
+        }
     }
 
     Err(error::Format::DeserializationError(
@@ -837,3 +995,133 @@ pub fn proto_bytes_constraint_to_token_bytes_constraint(
         "deserialization error: invalid string constraint".to_string(),
     ))
 }
+
+pub fn token_ip_to_proto_ip(input: &IpAddr) -> Vec<u8> {
+    match input {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+pub fn proto_ip_to_token_ip(input: &[u8]) -> Result<IpAddr, error::Format> {
+    if let Ok(octets) = <[u8; 4]>::try_from(input) {
+        return Ok(IpAddr::from(octets));
+    }
+    if let Ok(octets) = <[u8; 16]>::try_from(input) {
+        return Ok(IpAddr::from(octets));
+    }
+
+    Err(error::Format::DeserializationError(
+        "deserialization error: invalid ip address".to_string(),
+    ))
+}
+
+pub fn token_network_to_proto_network(input: &IpNetwork) -> schema::IpNetwork {
+    schema::IpNetwork {
+        address: token_ip_to_proto_ip(&input.address()),
+        prefix: u32::from(input.prefix()),
+    }
+}
+
+pub fn proto_network_to_token_network(
+    input: &schema::IpNetwork,
+) -> Result<IpNetwork, error::Format> {
+    let address = proto_ip_to_token_ip(&input.address)?;
+
+    u8::try_from(input.prefix)
+        .ok()
+        .and_then(|prefix| IpNetwork::new(address, prefix))
+        .ok_or_else(|| {
+            error::Format::DeserializationError(
+                "deserialization error: invalid ip network".to_string(),
+            )
+        })
+}
+
+pub fn token_ip_constraint_to_proto_ip_constraint(
+    input: &IpConstraint,
+) -> schema::IpConstraint {
+    use schema::ip_constraint::Kind;
+
+    match input {
+        IpConstraint::Equal(ip) => schema::IpConstraint {
+            kind: Kind::Equal as i32,
+            equal: Some(token_ip_to_proto_ip(ip)),
+            in_set: vec![],
+            not_in_set: vec![],
+            in_network: None,
+        },
+        IpConstraint::In(s) => schema::IpConstraint {
+            kind: Kind::In as i32,
+            equal: None,
+            in_set: s.iter().map(token_ip_to_proto_ip).collect(),
+            not_in_set: vec![],
+            in_network: None,
+        },
+        IpConstraint::NotIn(s) => schema::IpConstraint {
+            kind: Kind::NotIn as i32,
+            equal: None,
+            in_set: vec![],
+            not_in_set: s.iter().map(token_ip_to_proto_ip).collect(),
+            in_network: None,
+        },
+        IpConstraint::InNetwork(n) => schema::IpConstraint {
+            kind: Kind::InNetwork as i32,
+            equal: None,
+            in_set: vec![],
+            not_in_set: vec![],
+            in_network: Some(token_network_to_proto_network(n)),
+        },
+    }
+}
+
+pub fn proto_ip_constraint_to_token_ip_constraint(
+    input: &schema::IpConstraint,
+) -> Result<IpConstraint, error::Format> {
+    use schema::ip_constraint::Kind;
+
+    let kind = if let Some(i) = Kind::from_i32(input.kind) {
+        i
+    } else {
+        return Err(error::Format::DeserializationError(
+            "deserialization error: invalid ip constraint kind".to_string(),
+        ));
+    };
+
+    match kind {
+        Kind::Equal => {
+            if let Some(ref ip) = input.equal {
+                return proto_ip_to_token_ip(ip).map(IpConstraint::Equal);
+            }
+        }
+        Kind::In => {
+            if !input.in_set.is_empty() {
+                return input
+                    .in_set
+                    .iter()
+                    .map(|ip| proto_ip_to_token_ip(ip))
+                    .collect::<Result<_, _>>()
+                    .map(IpConstraint::In);
+            }
+        }
+        Kind::NotIn => {
+            if !input.not_in_set.is_empty() {
+                return input
+                    .not_in_set
+                    .iter()
+                    .map(|ip| proto_ip_to_token_ip(ip))
+                    .collect::<Result<_, _>>()
+                    .map(IpConstraint::NotIn);
+            }
+        }
+        Kind::InNetwork => {
+            if let Some(ref n) = input.in_network {
+                return proto_network_to_token_network(n).map(IpConstraint::InNetwork);
+            }
+        }
+    }
+
+    Err(error::Format::DeserializationError(
+        "deserialization error: invalid ip constraint".to_string(),
+    ))
+}
@@ -0,0 +1,224 @@
+//! alternative token format, using Ed25519 signatures
+//!
+//! Each block carries the public key of the next block, and an Ed25519 signature
+//! of the serialized block followed by that next key. The authority block is signed
+//! by the root key, and every following block by the key carried in the previous
+//! block.
+//!
+//! The private key matching the last block's next key is carried in the token
+//! (the proof), so that the holder can sign a new block.
+use super::{convert::token_block_to_proto_block, schema};
+use crate::crypto::ed25519::{KeyPair, PublicKey, Signature};
+use crate::error;
+use crate::token::Block;
+use prost::Message;
+use rand_core::{CryptoRng, RngCore};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+/// serialized block along with the key of the next block
+#[derive(Clone, Debug)]
+pub struct SignedBlock {
+    pub data: Vec<u8>,
+    pub next_key: PublicKey,
+    pub signature: Signature,
+}
+
+impl SignedBlock {
+    fn new(signer: &KeyPair, data: Vec<u8>, next_key: PublicKey) -> Self {
+        let signature = signer.sign(&Self::payload(&data, &next_key));
+
+        SignedBlock {
+            data,
+            next_key,
+            signature,
+        }
+    }
+
+    fn verify(&self, key: &PublicKey) -> bool {
+        key.verify(&Self::payload(&self.data, &self.next_key), &self.signature)
+    }
+
+    fn payload(data: &[u8], next_key: &PublicKey) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(data.len() + 32);
+        payload.extend_from_slice(data);
+        payload.extend_from_slice(&next_key.to_bytes());
+        payload
+    }
+
+    fn from_proto(block: schema::Ed25519Block) -> Result<Self, error::Format> {
+        let next_key = PublicKey::from_bytes(&block.next_key).ok_or_else(|| {
+            error::Format::DeserializationError(format!(
+                "deserialization error: invalid next key of {} bytes",
+                block.next_key.len()
+            ))
+        })?;
+        let signature = Signature::from_bytes(&block.signature)
+            .ok_or(error::Format::Signature(error::Signature::InvalidFormat))?;
+
+        Ok(SignedBlock {
+            data: block.block,
+            next_key,
+            signature,
+        })
+    }
+
+    fn to_proto(&self) -> schema::Ed25519Block {
+        schema::Ed25519Block {
+            block: self.data.clone(),
+            next_key: self.next_key.to_bytes().to_vec(),
+            signature: self.signature.to_bytes().to_vec(),
+        }
+    }
+}
+
+/// Intermediate structure for serialization of tokens in the Ed25519 format
+#[derive(Clone, Debug)]
+pub struct Ed25519SerializedBiscuit {
+    pub root: PublicKey,
+    pub authority: SignedBlock,
+    pub blocks: Vec<SignedBlock>,
+    /// private key of the next block, shared between clones of the token
+    pub proof: Arc<KeyPair>,
+}
+
+impl Ed25519SerializedBiscuit {
+    /// deserializes a token and checks its signatures
+    pub fn from_slice(slice: &[u8]) -> Result<Self, error::Format> {
+        let data = schema::Ed25519Biscuit::decode(slice).map_err(|e| {
+            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+        })?;
+
+        Self::from_proto(data)
+    }
+
+    pub(crate) fn from_proto(data: schema::Ed25519Biscuit) -> Result<Self, error::Format> {
+        let root = PublicKey::from_bytes(&data.root_key).ok_or_else(|| {
+            error::Format::DeserializationError(format!(
+                "deserialization error: invalid root key of {} bytes",
+                data.root_key.len()
+            ))
+        })?;
+
+        let authority = SignedBlock::from_proto(data.authority)?;
+        let blocks = data
+            .blocks
+            .into_iter()
+            .map(SignedBlock::from_proto)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let proof = crate::crypto::ed25519::PrivateKey::from_bytes(&data.proof)
+            .map(|key| Arc::new(KeyPair::from(key)))
+            .ok_or_else(|| {
+                error::Format::DeserializationError(
+                    "deserialization error: invalid proof".to_string(),
+                )
+            })?;
+
+        let deser = Ed25519SerializedBiscuit {
+            root,
+            authority,
+            blocks,
+            proof,
+        };
+
+        deser.verify()?;
+        Ok(deser)
+    }
+
+    /// serializes the token
+    pub fn to_proto(&self) -> schema::Ed25519Biscuit {
+        schema::Ed25519Biscuit {
+            root_key: self.root.to_bytes().to_vec(),
+            authority: self.authority.to_proto(),
+            blocks: self.blocks.iter().map(|b| b.to_proto()).collect(),
+            proof: self.proof.private().to_bytes().to_vec(),
+        }
+    }
+
+    pub fn serialized_size(&self) -> usize {
+        self.to_proto().encoded_len()
+    }
+
+    /// serializes the token
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
+        let b = self.to_proto();
+
+        let mut v = Vec::new();
+
+        b.encode(&mut v)
+            .map(|_| v)
+            .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
+    }
+
+    /// creates a new token, signed by the root key
+    pub fn new<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        root: &KeyPair,
+        authority: &Block,
+    ) -> Result<Self, error::Format> {
+        let v = serialize_block(authority)?;
+
+        let next = KeyPair::new_with_rng(rng);
+        let authority = SignedBlock::new(root, v, next.public());
+
+        Ok(Ed25519SerializedBiscuit {
+            root: root.public(),
+            authority,
+            blocks: vec![],
+            proof: Arc::new(next),
+        })
+    }
+
+    /// adds a new block, serializes it and signs it with the proof
+    pub fn append<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        block: &Block,
+    ) -> Result<Self, error::Format> {
+        let v = serialize_block(block)?;
+
+        let next = KeyPair::new_with_rng(rng);
+        let signed = SignedBlock::new(&self.proof, v, next.public());
+
+        let mut t = self.clone();
+        t.blocks.push(signed);
+        t.proof = Arc::new(next);
+
+        Ok(t)
+    }
+
+    /// checks the chain of signatures
+    pub fn verify(&self) -> Result<(), error::Format> {
+        let mut key = self.root;
+
+        for block in std::iter::once(&self.authority).chain(self.blocks.iter()) {
+            if !block.verify(&key) {
+                return Err(error::Format::Signature(error::Signature::InvalidSignature));
+            }
+            key = block.next_key;
+        }
+
+        if self.proof.public() != key {
+            return Err(error::Format::Signature(error::Signature::InvalidSignature));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_root_key(&self, root: PublicKey) -> Result<(), error::Format> {
+        if !bool::from(self.root.ct_eq(&root)) {
+            return Err(error::Format::UnknownPublicKey);
+        }
+
+        Ok(())
+    }
+}
+
+fn serialize_block(block: &Block) -> Result<Vec<u8>, error::Format> {
+    let mut v = Vec::new();
+    token_block_to_proto_block(block)
+        .encode(&mut v)
+        .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))?;
+    Ok(v)
+}
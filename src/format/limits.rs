@@ -0,0 +1,290 @@
+//! resource limits applied when deserializing tokens
+//!
+//! a token is checked against those limits before its blocks are converted,
+//! so that a hostile token cannot make us allocate a lot of memory
+use super::schema;
+use crate::error::{self, DeserializationLimit};
+
+/// limits on the size and content of a deserialized token
+#[derive(Debug, Clone)]
+pub struct DeserializationLimits {
+    /// maximum size of the serialized token, in bytes
+    pub max_size: usize,
+    /// maximum number of blocks, including the authority block
+    pub max_blocks: usize,
+    /// maximum number of symbols defined by a block
+    pub max_symbols: usize,
+    /// maximum number of facts in a block
+    pub max_facts: usize,
+    /// maximum number of rules and caveat queries in a block
+    pub max_rules: usize,
+    /// maximum number of terms in a predicate
+    pub max_terms: usize,
+    /// maximum length of symbols, strings and byte arrays
+    pub max_string_length: usize,
+}
+
+impl Default for DeserializationLimits {
+    fn default() -> Self {
+        DeserializationLimits {
+            max_size: 65536,
+            max_blocks: 64,
+            max_symbols: 256,
+            max_facts: 1000,
+            max_rules: 256,
+            max_terms: 32,
+            max_string_length: 4096,
+        }
+    }
+}
+
+impl DeserializationLimits {
+    /// checks the size of a serialized token
+    pub fn check_size(&self, slice: &[u8]) -> Result<(), error::Format> {
+        check(slice.len() <= self.max_size, DeserializationLimit::TooLarge)
+    }
+
+    /// checks the number of blocks of a token, including the authority block
+    pub fn check_block_count(&self, count: usize) -> Result<(), error::Format> {
+        check(count <= self.max_blocks, DeserializationLimit::TooManyBlocks)
+    }
+
+    /// checks the content of a block, before its conversion
+    pub fn check_block(&self, block: &schema::Block) -> Result<(), error::Format> {
+        check(
+            block.symbols.len() <= self.max_symbols,
+            DeserializationLimit::TooManySymbols,
+        )?;
+        check(
+            block.facts.len() <= self.max_facts,
+            DeserializationLimit::TooManyFacts,
+        )?;
+
+        let queries = block.caveats.iter().map(|c| c.queries.len()).sum::<usize>();
+        check(
+            block.rules.len() + queries <= self.max_rules,
+            DeserializationLimit::TooManyRules,
+        )?;
+
+        for symbol in block.symbols.iter() {
+            self.check_string(symbol.as_bytes())?;
+        }
+        if let Some(context) = block.context.as_ref() {
+            self.check_string(context.as_bytes())?;
+        }
+
+        for fact in block.facts.iter() {
+            self.check_predicate(&fact.predicate)?;
+        }
+
+        let caveat_rules = block.caveats.iter().flat_map(|c| c.queries.iter());
+        for rule in block.rules.iter().chain(caveat_rules) {
+            self.check_rule(rule)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_rule(&self, rule: &schema::Rule) -> Result<(), error::Format> {
+        self.check_predicate(&rule.head)?;
+        for predicate in rule.body.iter() {
+            self.check_predicate(predicate)?;
+        }
+
+        for constraint in rule.constraints.iter() {
+            if let Some(c) = constraint.str.as_ref() {
+                let strings = c
+                    .prefix
+                    .iter()
+                    .chain(c.suffix.iter())
+                    .chain(c.equal.iter())
+                    .chain(c.regex.iter())
+                    .chain(c.contains.iter())
+                    .chain(c.under.iter())
+                    .chain(c.glob.iter())
+                    .chain(c.in_set.iter())
+                    .chain(c.not_in_set.iter());
+                for s in strings {
+                    self.check_string(s.as_bytes())?;
+                }
+            }
+
+            if let Some(c) = constraint.bytes.as_ref() {
+                let bytes = c.equal.iter().chain(c.in_set.iter()).chain(c.not_in_set.iter());
+                for b in bytes {
+                    self.check_string(b)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_predicate(&self, predicate: &schema::Predicate) -> Result<(), error::Format> {
+        check(
+            predicate.ids.len() <= self.max_terms,
+            DeserializationLimit::TooManyTerms,
+        )?;
+
+        for id in predicate.ids.iter() {
+            if let Some(s) = id.str.as_ref() {
+                self.check_string(s.as_bytes())?;
+            }
+            if let Some(b) = id.bytes.as_ref() {
+                self.check_string(b)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_string(&self, s: &[u8]) -> Result<(), error::Format> {
+        check(
+            s.len() <= self.max_string_length,
+            DeserializationLimit::StringTooLong,
+        )
+    }
+}
+
+fn check(condition: bool, limit: DeserializationLimit) -> Result<(), error::Format> {
+    if condition {
+        Ok(())
+    } else {
+        Err(error::Format::DeserializationLimit(limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::{builder::*, Biscuit};
+    use rand::prelude::*;
+
+    #[test]
+    fn limits() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.add_right("file2", "read");
+        builder.add_authority_fact(fact("user", &[s("authority"), string(&"a".repeat(100))])).unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, biscuit1.create_block())
+            .unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+
+        Biscuit::from_with_limits(&serialized, DeserializationLimits::default()).unwrap();
+
+        let cases = vec![
+            (
+                DeserializationLimits { max_size: 100, ..Default::default() },
+                DeserializationLimit::TooLarge,
+            ),
+            (
+                DeserializationLimits { max_blocks: 1, ..Default::default() },
+                DeserializationLimit::TooManyBlocks,
+            ),
+            (
+                DeserializationLimits { max_symbols: 0, ..Default::default() },
+                DeserializationLimit::TooManySymbols,
+            ),
+            (
+                DeserializationLimits { max_facts: 2, ..Default::default() },
+                DeserializationLimit::TooManyFacts,
+            ),
+            (
+                DeserializationLimits { max_terms: 2, ..Default::default() },
+                DeserializationLimit::TooManyTerms,
+            ),
+            (
+                DeserializationLimits { max_string_length: 50, ..Default::default() },
+                DeserializationLimit::StringTooLong,
+            ),
+        ];
+
+        for (limits, expected) in cases {
+            assert_eq!(
+                Biscuit::from_with_limits(&serialized, limits).unwrap_err(),
+                error::Token::Format(error::Format::DeserializationLimit(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn glob_length() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        let glob = "/**".repeat(100);
+        block2
+            .add_caveat(format!("check($0) <- resource(#ambient, $0) @ $0 glob \"{}\"", glob).as_str())
+            .unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+
+        Biscuit::from_with_limits(&serialized, DeserializationLimits::default()).unwrap();
+        assert_eq!(
+            Biscuit::from_with_limits(
+                &serialized,
+                DeserializationLimits { max_string_length: 100, ..Default::default() }
+            )
+            .unwrap_err(),
+            error::Token::Format(error::Format::DeserializationLimit(DeserializationLimit::StringTooLong))
+        );
+    }
+
+    #[test]
+    fn block_count_before_signature() {
+        use crate::crypto::ed25519;
+        use prost::Message;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let too_many_blocks = error::Token::Format(error::Format::DeserializationLimit(
+            DeserializationLimit::TooManyBlocks,
+        ));
+        let limits = || DeserializationLimits { max_blocks: 1, ..Default::default() };
+
+        let root = KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder(&root).build_with_rng(&mut rng).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, biscuit1.create_block())
+            .unwrap();
+
+        let mut proto = schema::Biscuit::decode(&biscuit2.to_vec().unwrap()[..]).unwrap();
+        proto.signature.z = KeyPair::new_with_rng(&mut rng).private().to_bytes().to_vec();
+        let mut forged = Vec::new();
+        proto.encode(&mut forged).unwrap();
+
+        assert!(matches!(
+            Biscuit::from(&forged).unwrap_err(),
+            error::Token::Format(error::Format::Signature(_))
+        ));
+        assert_eq!(Biscuit::from_with_limits(&forged, limits()).unwrap_err(), too_many_blocks);
+
+        let root = ed25519::KeyPair::new_with_rng(&mut rng);
+        let biscuit1 = Biscuit::builder_ed25519(&root).build_with_rng(&mut rng).unwrap();
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, biscuit1.create_block())
+            .unwrap();
+
+        let mut proto = schema::Ed25519Biscuit::decode(&biscuit2.to_vec().unwrap()[..]).unwrap();
+        proto.blocks[0].signature[0] ^= 1;
+        let mut forged = Vec::new();
+        proto.encode(&mut forged).unwrap();
+
+        assert!(matches!(
+            Biscuit::from(&forged).unwrap_err(),
+            error::Token::Format(error::Format::Signature(_))
+        ));
+        assert_eq!(Biscuit::from_with_limits(&forged, limits()).unwrap_err(), too_many_blocks);
+    }
+}
@@ -4,11 +4,16 @@
 //!
 //! - serialization of Biscuit blocks to Protobuf then `Vec<u8>`
 //! - serialization of a wrapper structure containing serialized blocks and the signature
-use super::crypto::{KeyPair, TokenSignature};
+//!
+//! The wrapper structure comes in two formats: [`SerializedBiscuit`], with aggregated
+//! signatures over Ristretto, and [`Ed25519SerializedBiscuit`], with a chain of Ed25519
+//! signatures. [`Container`] holds either of them.
+use super::crypto::{KeyPair, Signer, TokenSignature, LEGACY_SIGNATURE_VERSION};
 use crate::crypto::PublicKey;
 use curve25519_dalek::ristretto::CompressedRistretto;
 use prost::Message;
 use rand_core::{CryptoRng, RngCore};
+use subtle::ConstantTimeEq;
 
 use super::error;
 use super::token::Block;
@@ -19,8 +24,125 @@ pub mod schema;/* {
 }*/
 
 pub mod convert;
+pub mod ed25519;
+pub mod limits;
 
 use self::convert::*;
+pub use self::ed25519::Ed25519SerializedBiscuit;
+pub use self::limits::DeserializationLimits;
+
+/// serialized token, in one of the supported formats
+#[derive(Clone, Debug)]
+pub enum Container {
+    /// aggregated signatures over Ristretto
+    Aggregated(SerializedBiscuit),
+    /// chain of Ed25519 signatures
+    Ed25519(Box<Ed25519SerializedBiscuit>),
+}
+
+impl Container {
+    /// deserializes a token in either format and checks its signature
+    ///
+    /// tokens using the legacy signature scheme are rejected
+    pub fn from_slice(slice: &[u8]) -> Result<Self, error::Format> {
+        match Container::decode_ed25519(slice)? {
+            Some(data) => Ed25519SerializedBiscuit::from_proto(data).map(|c| Container::Ed25519(Box::new(c))),
+            None => SerializedBiscuit::from_slice(slice).map(Container::Aggregated),
+        }
+    }
+
+    /// deserializes a token in either format and checks its signature, after checking
+    /// its size and block count against the limits
+    pub fn from_slice_with_limits(
+        slice: &[u8],
+        limits: &DeserializationLimits,
+    ) -> Result<Self, error::Format> {
+        limits.check_size(slice)?;
+
+        match Container::decode_ed25519(slice)? {
+            Some(data) => {
+                limits.check_block_count(1 + data.blocks.len())?;
+                Ed25519SerializedBiscuit::from_proto(data).map(|c| Container::Ed25519(Box::new(c)))
+            }
+            None => {
+                let deser = SerializedBiscuit::decode(slice)?;
+                limits.check_block_count(1 + deser.blocks.len())?;
+                deser.verify()?;
+                Ok(Container::Aggregated(deser))
+            }
+        }
+    }
+
+    /// deserializes a token in either format and checks its signature, accepting
+    /// tokens using the legacy signature scheme
+    pub fn from_slice_with_legacy(slice: &[u8]) -> Result<Self, error::Format> {
+        match Container::decode_ed25519(slice)? {
+            Some(data) => Ed25519SerializedBiscuit::from_proto(data).map(|c| Container::Ed25519(Box::new(c))),
+            None => SerializedBiscuit::from_slice_with_legacy(slice).map(Container::Aggregated),
+        }
+    }
+
+    /// the field numbers of both formats do not overlap: a token in the aggregated
+    /// format decodes to an `Ed25519Biscuit` without a root key
+    fn decode_ed25519(slice: &[u8]) -> Result<Option<schema::Ed25519Biscuit>, error::Format> {
+        let data = schema::Ed25519Biscuit::decode(slice).map_err(|e| {
+            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+        })?;
+
+        if data.root_key.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
+    }
+
+    /// serialized authority block
+    pub fn authority(&self) -> &[u8] {
+        match self {
+            Container::Aggregated(c) => &c.authority,
+            Container::Ed25519(c) => &c.authority.data,
+        }
+    }
+
+    /// serialized blocks, after the authority block
+    pub fn blocks(&self) -> Vec<&[u8]> {
+        match self {
+            Container::Aggregated(c) => c.blocks.iter().map(|b| &b[..]).collect(),
+            Container::Ed25519(c) => c.blocks.iter().map(|b| &b.data[..]).collect(),
+        }
+    }
+
+    pub fn serialized_size(&self) -> usize {
+        match self {
+            Container::Aggregated(c) => c.serialized_size(),
+            Container::Ed25519(c) => c.serialized_size(),
+        }
+    }
+
+    /// serializes the token
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
+        match self {
+            Container::Aggregated(c) => c.to_vec(),
+            Container::Ed25519(c) => c.to_vec(),
+        }
+    }
+
+    /// adds a new block, serializes it and sign a new token
+    ///
+    /// the keypair is only used by the aggregated format: the Ed25519 format
+    /// generates the key of the next block and keeps it in the token
+    pub fn append<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        keypair: &KeyPair,
+        block: &Block,
+    ) -> Result<Self, error::Format> {
+        match self {
+            Container::Aggregated(c) => c.append(rng, keypair, block).map(Container::Aggregated),
+            Container::Ed25519(c) => c.append(rng, block).map(|c| Container::Ed25519(Box::new(c))),
+        }
+    }
+}
 
 /// Intermediate structure for token serialization
 ///
@@ -35,7 +157,47 @@ pub struct SerializedBiscuit {
 }
 
 impl SerializedBiscuit {
+    /// deserializes a token and checks its signature
+    ///
+    /// tokens using the legacy signature scheme are rejected
     pub fn from_slice(slice: &[u8]) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::decode(slice)?;
+
+        match deser.verify() {
+            Ok(()) => Ok(deser),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// deserializes a token and checks its signature, accepting tokens
+    /// using the legacy signature scheme
+    pub fn from_slice_with_legacy(slice: &[u8]) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::decode_with_legacy(slice)?;
+
+        match deser.verify() {
+            Ok(()) => Ok(deser),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// deserializes a token without checking its signature
+    ///
+    /// the result must be checked with [`SerializedBiscuit::verify`] or
+    /// [`SerializedBiscuit::verify_batch`] before use. Tokens using the legacy
+    /// signature scheme are rejected
+    pub fn decode(slice: &[u8]) -> Result<Self, error::Format> {
+        let deser = SerializedBiscuit::decode_with_legacy(slice)?;
+
+        if deser.signature.version == LEGACY_SIGNATURE_VERSION {
+            return Err(error::Format::LegacySignature);
+        }
+
+        Ok(deser)
+    }
+
+    /// deserializes a token without checking its signature, accepting tokens
+    /// using the legacy signature scheme
+    pub fn decode_with_legacy(slice: &[u8]) -> Result<Self, error::Format> {
         let data = schema::Biscuit::decode(slice).map_err(|e| {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
         })?;
@@ -59,19 +221,14 @@ impl SerializedBiscuit {
             }
         }
 
-        let signature = proto_sig_to_token_sig(data.signature)?;
+        let signature = proto_sig_to_token_sig(data.signature, data.version.unwrap_or(0))?;
 
-        let deser = SerializedBiscuit {
+        Ok(SerializedBiscuit {
             authority: data.authority,
             blocks: data.blocks,
             keys,
             signature,
-        };
-
-        match deser.verify() {
-            Ok(()) => Ok(deser),
-            Err(e) => Err(e),
-        }
+        })
     }
 
     /// serializes the token
@@ -85,6 +242,12 @@ impl SerializedBiscuit {
                 .map(|k| Vec::from(&k.0.compress().to_bytes()[..]))
                 .collect(),
             signature: token_sig_to_proto_sig(&self.signature),
+            // legacy tokens keep the original format
+            version: if self.signature.version == LEGACY_SIGNATURE_VERSION {
+                None
+            } else {
+                Some(self.signature.version)
+            },
         }
     }
 
@@ -103,10 +266,10 @@ impl SerializedBiscuit {
             .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
     }
 
-    /// creates a new token
+    /// creates a new token, signed by the root key
     pub fn new<T: RngCore + CryptoRng>(
         rng: &mut T,
-        keypair: &KeyPair,
+        root: &dyn Signer,
         authority: &Block,
     ) -> Result<Self, error::Format> {
         let mut v = Vec::new();
@@ -116,12 +279,13 @@ impl SerializedBiscuit {
                 error::Format::SerializationError(format!("serialization error: {:?}", e))
             })?;
 
-        let signature = TokenSignature::new(rng, keypair, &v);
+        let signature =
+            TokenSignature::new_with_signer(rng, root, &v).map_err(error::Format::Signature)?;
 
         Ok(SerializedBiscuit {
             authority: v,
             blocks: vec![],
-            keys: vec![keypair.public()],
+            keys: vec![root.public_key()],
             signature,
         })
     }
@@ -144,7 +308,7 @@ impl SerializedBiscuit {
         blocks.push(self.authority.clone());
         blocks.extend(self.blocks.iter().cloned());
 
-        let signature = self.signature.sign(rng, keypair, &v);
+        let signature = self.signature.sign(rng, &self.keys, &blocks, keypair, &v);
 
         let mut t = SerializedBiscuit {
             authority: self.authority.clone(),
@@ -174,11 +338,40 @@ impl SerializedBiscuit {
             .map_err(error::Format::Signature)
     }
 
+    /// checks the signatures of multiple deserialized tokens at once
+    ///
+    /// this is faster than calling [`SerializedBiscuit::verify`] on each token, but
+    /// an error does not indicate which token was invalid
+    pub fn verify_batch<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        tokens: &[&SerializedBiscuit],
+    ) -> Result<(), error::Format> {
+        let mut messages = Vec::with_capacity(tokens.len());
+        for token in tokens.iter() {
+            if token.keys.is_empty() {
+                return Err(error::Format::EmptyKeys);
+            }
+
+            let mut blocks = Vec::with_capacity(1 + token.blocks.len());
+            blocks.push(&token.authority[..]);
+            blocks.extend(token.blocks.iter().map(|b| &b[..]));
+            messages.push(blocks);
+        }
+
+        let signatures = tokens
+            .iter()
+            .zip(messages.iter())
+            .map(|(token, blocks)| (&token.signature, &token.keys[..], &blocks[..]))
+            .collect::<Vec<_>>();
+
+        TokenSignature::verify_batch(rng, &signatures).map_err(error::Format::Signature)
+    }
+
     pub fn check_root_key(&self, root: PublicKey) -> Result<(), error::Format> {
         if self.keys.is_empty() {
             return Err(error::Format::EmptyKeys);
         }
-        if self.keys[0] != root {
+        if !bool::from(self.keys[0].ct_eq(&root)) {
             return Err(error::Format::UnknownPublicKey);
         }
 
@@ -8,6 +8,28 @@ pub struct Biscuit {
     pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
     #[prost(message, required, tag="4")]
     pub signature: Signature,
+    #[prost(uint32, optional, tag="5")]
+    pub version: ::core::option::Option<u32>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Ed25519Biscuit {
+    #[prost(bytes="vec", required, tag="6")]
+    pub root_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, required, tag="7")]
+    pub authority: Ed25519Block,
+    #[prost(message, repeated, tag="8")]
+    pub blocks: ::prost::alloc::vec::Vec<Ed25519Block>,
+    #[prost(bytes="vec", required, tag="9")]
+    pub proof: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Ed25519Block {
+    #[prost(bytes="vec", required, tag="1")]
+    pub block: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes="vec", required, tag="2")]
+    pub next_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes="vec", required, tag="3")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SealedBiscuit {
@@ -17,6 +39,12 @@ pub struct SealedBiscuit {
     pub blocks: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
     #[prost(bytes="vec", required, tag="3")]
     pub signature: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint32, optional, tag="4")]
+    pub mode: ::core::option::Option<u32>,
+    #[prost(bytes="vec", optional, tag="5")]
+    pub salt: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(uint32, optional, tag="6")]
+    pub key_id: ::core::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Signature {
@@ -84,6 +112,10 @@ pub struct Id {
     pub date: ::core::option::Option<u64>,
     #[prost(bytes="vec", optional, tag="7")]
     pub bytes: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(bytes="vec", optional, tag="8")]
+    pub ip: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(message, optional, tag="9")]
+    pub network: ::core::option::Option<IpNetwork>,
 }
 /// Nested message and enum types in `ID`.
 pub mod id {
@@ -96,9 +128,20 @@ pub mod id {
         Str = 3,
         Date = 4,
         Bytes = 5,
+        /// since version 2
+        Ip = 6,
+        Network = 7,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IpNetwork {
+    /// 4 bytes for IPv4, 16 bytes for IPv6
+    #[prost(bytes="vec", required, tag="1")]
+    pub address: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint32, required, tag="2")]
+    pub prefix: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Constraint {
     #[prost(uint32, required, tag="1")]
     pub id: u32,
@@ -114,6 +157,8 @@ pub struct Constraint {
     pub symbol: ::core::option::Option<SymbolConstraint>,
     #[prost(message, optional, tag="7")]
     pub bytes: ::core::option::Option<BytesConstraint>,
+    #[prost(message, optional, tag="8")]
+    pub ip: ::core::option::Option<IpConstraint>,
 }
 /// Nested message and enum types in `Constraint`.
 pub mod constraint {
@@ -125,6 +170,8 @@ pub mod constraint {
         Date = 2,
         Symbol = 3,
         Bytes = 4,
+        /// since version 2
+        Ip = 5,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -176,6 +223,12 @@ pub struct StringConstraint {
     pub not_in_set: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
     #[prost(string, optional, tag="7")]
     pub regex: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag="8")]
+    pub contains: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag="9")]
+    pub under: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag="10")]
+    pub glob: ::core::option::Option<::prost::alloc::string::String>,
 }
 /// Nested message and enum types in `StringConstraint`.
 pub mod string_constraint {
@@ -188,6 +241,11 @@ pub mod string_constraint {
         In = 3,
         NotIn = 4,
         Regex = 5,
+        /// since version 1
+        Contains = 6,
+        /// since version 3
+        Under = 7,
+        Glob = 8,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -247,3 +305,27 @@ pub mod bytes_constraint {
         NotIn = 2,
     }
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct IpConstraint {
+    #[prost(enumeration="ip_constraint::Kind", required, tag="1")]
+    pub kind: i32,
+    #[prost(bytes="vec", optional, tag="2")]
+    pub equal: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(bytes="vec", repeated, tag="3")]
+    pub in_set: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    #[prost(bytes="vec", repeated, tag="4")]
+    pub not_in_set: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    #[prost(message, optional, tag="5")]
+    pub in_network: ::core::option::Option<IpNetwork>,
+}
+/// Nested message and enum types in `IpConstraint`.
+pub mod ip_constraint {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Kind {
+        Equal = 0,
+        In = 1,
+        NotIn = 2,
+        InNetwork = 3,
+    }
+}
@@ -28,6 +28,7 @@ use nom::{
 };
 use std::{
     convert::{TryFrom, TryInto},
+    net::IpAddr,
     str::FromStr,
     time::{Duration, SystemTime},
 };
@@ -57,7 +58,7 @@ pub fn rule(i: &str) -> IResult<&str, builder::Rule> {
     let (i, _) = space0(i)?;
     let (i, predicates) = separated_list1(
       preceded(space0, char(',')),
-      preceded(space0, predicate)
+      preceded(space0, body_predicate)
     )(i)?;
 
     let (i, constraints) = if let Ok((i, _)) =
@@ -71,6 +72,70 @@ pub fn rule(i: &str) -> IResult<&str, builder::Rule> {
     Ok((i, builder::Rule(head, predicates, constraints)))
 }
 
+/// facts, rules and caveats parsed from a Datalog source
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SourceResult {
+    pub facts: Vec<builder::Fact>,
+    pub rules: Vec<builder::Rule>,
+    pub caveats: Vec<builder::Caveat>,
+}
+
+/// parse a list of Datalog statements, each ending with `;`
+///
+/// caveats are prefixed with the `caveat` keyword, and `//` starts a comment
+/// that runs to the end of the line:
+///
+/// ```text
+/// // rights of the user
+/// right(#authority, "file1", #read);
+/// valid($0) <- resource(#ambient, $0) @ $0 in ["file1"];
+/// caveat check(#read) <- operation(#ambient, #read);
+/// ```
+pub fn source(i: &str) -> Result<SourceResult, error::Token> {
+    let mut result = SourceResult::default();
+    let mut i = skip_comments(i);
+
+    while !i.is_empty() {
+        let (remaining, _) = statement(i, &mut result).map_err(|_| error::Token::ParseError)?;
+        i = skip_comments(remaining);
+    }
+
+    Ok(result)
+}
+
+fn statement<'a>(i: &'a str, result: &mut SourceResult) -> IResult<&'a str, ()> {
+    let i = if let Ok((i, _)) = caveat_keyword(i) {
+        let (i, c) = caveat(i)?;
+        result.caveats.push(c);
+        i
+    } else if let Ok((i, r)) = rule(i) {
+        result.rules.push(r);
+        i
+    } else {
+        let (i, f) = fact(i)?;
+        result.facts.push(f);
+        i
+    };
+
+    value((), preceded(space0, char(';')))(i)
+}
+
+fn caveat_keyword(i: &str) -> IResult<&str, &str> {
+    let (i, keyword) = tag("caveat")(i)?;
+    let (i, _) = nom::character::complete::multispace1(i)?;
+    Ok((i, keyword))
+}
+
+fn skip_comments(mut i: &str) -> &str {
+    loop {
+        i = i.trim_start();
+        if !i.starts_with("//") {
+            return i;
+        }
+        i = i.find('\n').map(|pos| &i[pos..]).unwrap_or("");
+    }
+}
+
 impl TryFrom<&str> for builder::Fact {
     type Error = error::Token;
 
@@ -161,6 +226,17 @@ fn predicate(i: &str) -> IResult<&str, builder::Predicate> {
     ))
 }
 
+/// predicate of a rule body, that can be a builtin predicate prefixed with `@`
+fn body_predicate(i: &str) -> IResult<&str, builder::Predicate> {
+    let (i, builtin) = opt(char('@'))(i)?;
+    let (i, mut predicate) = predicate(i)?;
+    if builtin.is_some() {
+        predicate.name = format!("@{}", predicate.name);
+    }
+
+    Ok((i, predicate))
+}
+
 fn rule_head(i: &str) -> IResult<&str, builder::Predicate> {
     let (i, _) = space0(i)?;
     let (i, fact_name) = name(i)?;
@@ -199,6 +275,9 @@ enum Operator {
     In,
     NotIn,
     Matches,
+    Contains,
+    Under,
+    Glob,
 }
 
 fn operator(i: &str) -> IResult<&str, Operator> {
@@ -211,6 +290,9 @@ fn operator(i: &str) -> IResult<&str, Operator> {
         value(Operator::In, tag("in")),
         value(Operator::NotIn, tag("not in")),
         value(Operator::Matches, tag("matches")),
+        value(Operator::Contains, tag("contains")),
+        value(Operator::Under, tag("under")),
+        value(Operator::Glob, tag("glob")),
     ))(i)
 }
 
@@ -268,57 +350,76 @@ fn constraint_kind(i: &str) -> IResult<&str, builder::ConstraintKind> {
             map(parse_bytes, |s| {
                 builder::ConstraintKind::Bytes(datalog::BytesConstraint::Equal(s))
             }),
+            map(parse_ip, |ip| {
+                builder::ConstraintKind::Ip(datalog::IpConstraint::Equal(ip))
+            }),
         ))(i),
-        Operator::In => delimited(
-            char('['),
-            alt((
-                map(
-                    separated_list1(
-                        preceded(space0, char(',')),
-                        preceded(space0, parse_integer),
+        Operator::In => alt((
+            map(parse_network, |n| {
+                builder::ConstraintKind::Ip(datalog::IpConstraint::InNetwork(n))
+            }),
+            delimited(
+                char('['),
+                alt((
+                    map(
+                        separated_list1(
+                            preceded(space0, char(',')),
+                            preceded(space0, parse_integer),
+                        ),
+                        |mut h| {
+                            builder::ConstraintKind::Integer(datalog::IntConstraint::In(
+                                h.drain(..).collect(),
+                            ))
+                        },
                     ),
-                    |mut h| {
-                        builder::ConstraintKind::Integer(datalog::IntConstraint::In(
-                            h.drain(..).collect(),
-                        ))
-                    },
-                ),
-                map(
-                    separated_list1(
-                        preceded(space0, char(',')),
-                        preceded(space0, parse_string),
+                    map(
+                        separated_list1(
+                            preceded(space0, char(',')),
+                            preceded(space0, parse_string),
+                        ),
+                        |mut h| {
+                            builder::ConstraintKind::String(datalog::StrConstraint::In(
+                                h.drain(..).collect(),
+                            ))
+                        },
                     ),
-                    |mut h| {
-                        builder::ConstraintKind::String(datalog::StrConstraint::In(
-                            h.drain(..).collect(),
-                        ))
-                    },
-                ),
-                map(
-                    separated_list1(
-                        preceded(space0, char(',')),
-                        preceded(space0, parse_symbol),
+                    map(
+                        separated_list1(
+                            preceded(space0, char(',')),
+                            preceded(space0, parse_symbol),
+                        ),
+                        |mut h| {
+                            builder::ConstraintKind::Symbol(builder::SymbolConstraint::In(
+                                h.drain(..).map(|s| s.to_string()).collect(),
+                            ))
+                        },
                     ),
-                    |mut h| {
-                        builder::ConstraintKind::Symbol(builder::SymbolConstraint::In(
-                            h.drain(..).map(|s| s.to_string()).collect(),
-                        ))
-                    },
-                ),
-                map(
-                    separated_list1(
-                        preceded(space0, char(',')),
-                        preceded(space0, parse_bytes),
+                    map(
+                        separated_list1(
+                            preceded(space0, char(',')),
+                            preceded(space0, parse_bytes),
+                        ),
+                        |mut h| {
+                            builder::ConstraintKind::Bytes(datalog::BytesConstraint::In(
+                                h.drain(..).collect(),
+                            ))
+                        },
                     ),
-                    |mut h| {
-                        builder::ConstraintKind::Bytes(datalog::BytesConstraint::In(
-                            h.drain(..).collect(),
-                        ))
-                    },
-                ),
-            )),
-            preceded(space0, char(']')),
-        )(i),
+                    map(
+                        separated_list1(
+                            preceded(space0, char(',')),
+                            preceded(space0, parse_ip),
+                        ),
+                        |mut h| {
+                            builder::ConstraintKind::Ip(datalog::IpConstraint::In(
+                                h.drain(..).collect(),
+                            ))
+                        },
+                    ),
+                )),
+                preceded(space0, char(']')),
+            ),
+        ))(i),
         Operator::NotIn => delimited(
             char('['),
             alt((
@@ -366,6 +467,17 @@ fn constraint_kind(i: &str) -> IResult<&str, builder::ConstraintKind> {
                         ))
                     },
                 ),
+                map(
+                    separated_list1(
+                        preceded(space0, char(',')),
+                        preceded(space0, parse_ip),
+                    ),
+                    |mut h| {
+                        builder::ConstraintKind::Ip(datalog::IpConstraint::NotIn(
+                            h.drain(..).collect(),
+                        ))
+                    },
+                ),
             )),
             preceded(space0, char(']')),
         )(i),
@@ -391,6 +503,20 @@ fn constraint_kind(i: &str) -> IResult<&str, builder::ConstraintKind> {
                 |s| builder::ConstraintKind::String(datalog::StrConstraint::Regex(s)),
             ),
         ))(i),
+        // an empty string would match anything
+        Operator::Contains => map_opt(parse_string, |s| {
+            if s.is_empty() {
+                None
+            } else {
+                Some(builder::ConstraintKind::String(datalog::StrConstraint::Contains(s)))
+            }
+        })(i),
+        Operator::Under => map(parse_string, |s| {
+            builder::ConstraintKind::String(datalog::StrConstraint::Under(s))
+        })(i),
+        Operator::Glob => map(parse_string, |s| {
+            builder::ConstraintKind::String(datalog::StrConstraint::Glob(s))
+        })(i),
     }
 }
 
@@ -475,6 +601,29 @@ fn bytes(i: &str) -> IResult<&str, builder::Term> {
     parse_bytes(i).map(|(i, s)| (i, builder::Term::Bytes(s)))
 }
 
+fn is_address_char(c: char) -> bool {
+    c.is_ascii_hexdigit() || c == '.' || c == ':'
+}
+
+fn parse_ip(i: &str) -> IResult<&str, IpAddr> {
+    preceded(tag("ip:"), map_res(take_while1(is_address_char), str::parse))(i)
+}
+
+fn ip(i: &str) -> IResult<&str, builder::Term> {
+    parse_ip(i).map(|(i, ip)| (i, builder::Term::Ip(ip)))
+}
+
+fn parse_network(i: &str) -> IResult<&str, datalog::IpNetwork> {
+    preceded(
+        tag("cidr:"),
+        map_res(take_while1(|c| is_address_char(c) || c == '/'), str::parse),
+    )(i)
+}
+
+fn network(i: &str) -> IResult<&str, builder::Term> {
+    parse_network(i).map(|(i, n)| (i, builder::Term::Network(n)))
+}
+
 fn variable(i: &str) -> IResult<&str, builder::Term> {
     map(
         preceded(char('$'), name),
@@ -483,7 +632,7 @@ fn variable(i: &str) -> IResult<&str, builder::Term> {
 }
 
 fn term(i: &str) -> IResult<&str, builder::Term> {
-    preceded(space0, alt((symbol, string, date, variable, integer, bytes)))(i)
+    preceded(space0, alt((symbol, string, ip, network, date, variable, integer, bytes)))(i)
 }
 
 fn regex(i: &str) -> IResult<&str, String> {
@@ -508,6 +657,31 @@ mod tests {
     use crate::{datalog, token::builder};
     use std::collections::HashSet;
 
+    #[test]
+    fn source() {
+        let source = super::source(
+            r#"
+            // comment
+            right(#authority, "file1", #read);
+            valid($0) <- resource(#ambient, $0) @ $0 in ["file1"]; // other comment
+
+            caveat check(#read) <- operation(#ambient, #read)
+              || check(#write) <- operation(#ambient, #write);
+            caveat(#fact);
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(source.facts.len(), 2);
+        assert_eq!(source.facts[1].0.name, "caveat");
+        assert_eq!(source.rules.len(), 1);
+        assert_eq!(source.caveats.len(), 1);
+        assert_eq!(source.caveats[0].queries.len(), 2);
+
+        assert!(super::source("right(#authority, \"file1\", #read)").is_err());
+        assert!(super::source("right(#authority;").is_err());
+    }
+
     #[test]
     fn name() {
         assert_eq!(
@@ -548,6 +722,57 @@ mod tests {
         assert_eq!(super::variable("$1"), Ok(("", builder::variable("1"))));
     }
 
+    #[test]
+    fn ip() {
+        let v4: std::net::IpAddr = "10.0.0.1".parse().unwrap();
+        let v6: std::net::IpAddr = "2001:db8::1".parse().unwrap();
+        let network: builder::IpNetwork = "10.0.0.0/8".parse().unwrap();
+
+        assert_eq!(super::term("ip:10.0.0.1"), Ok(("", builder::ip(v4))));
+        assert_eq!(super::term("ip:2001:db8::1"), Ok(("", builder::ip(v6))));
+        assert_eq!(super::term("cidr:10.0.0.0/8"), Ok(("", builder::network(network))));
+        assert!(super::term("ip:10.0.0.256").is_err());
+        assert!(super::term("cidr:10.0.0.0/40").is_err());
+
+        assert_eq!(
+            super::constraint("$0 in cidr:10.0.0.0/8"),
+            Ok((
+                "",
+                builder::Constraint {
+                    id: "0".to_string(),
+                    kind: builder::ConstraintKind::Ip(builder::IpConstraint::InNetwork(network)),
+                }
+            ))
+        );
+
+        assert_eq!(
+            super::constraint("$0 == ip:10.0.0.1"),
+            Ok((
+                "",
+                builder::Constraint {
+                    id: "0".to_string(),
+                    kind: builder::ConstraintKind::Ip(builder::IpConstraint::Equal(v4)),
+                }
+            ))
+        );
+
+        assert_eq!(
+            super::constraint("$0 not in [ip:10.0.0.1, ip:2001:db8::1]"),
+            Ok((
+                "",
+                builder::Constraint {
+                    id: "0".to_string(),
+                    kind: builder::ConstraintKind::Ip(builder::IpConstraint::NotIn(
+                        [v4, v6].iter().cloned().collect()
+                    )),
+                }
+            ))
+        );
+
+        let fact = super::fact("office(cidr:10.0.0.0/8, ip:10.0.0.1)").unwrap().1;
+        assert_eq!(fact.to_string(), "office(cidr:10.0.0.0/8, ip:10.0.0.1)");
+    }
+
     #[test]
     fn constraint() {
         assert_eq!(
@@ -699,6 +924,36 @@ mod tests {
             ))
         );
 
+        // only a leading `*` is a wildcard
+        assert_eq!(
+            super::constraint("$0 matches \"*abc*\""),
+            Ok((
+                "",
+                builder::Constraint {
+                    id: "0".to_string(),
+                    kind: builder::ConstraintKind::String(datalog::StrConstraint::Suffix(
+                        "abc*".to_string()
+                    )),
+                }
+            ))
+        );
+
+        assert_eq!(
+            super::constraint("$0 contains \"abc\""),
+            Ok((
+                "",
+                builder::Constraint {
+                    id: "0".to_string(),
+                    kind: builder::ConstraintKind::String(datalog::StrConstraint::Contains(
+                        "abc".to_string()
+                    )),
+                }
+            ))
+        );
+
+        // an empty `contains` would match any string
+        assert!(super::constraint("$0 contains \"\"").is_err());
+
         assert_eq!(
             super::constraint("$0 matches /abc[0-9]+/"),
             Ok((
@@ -822,6 +1077,30 @@ mod tests {
         );
     }
 
+    #[test]
+    fn builtin_predicate() {
+        assert_eq!(
+            super::rule("allowed($0) <- @ip_in_cidr($0, \"10.0.0.0/8\"), source_ip(#ambient, $0)"),
+            Ok((
+                "",
+                builder::rule(
+                    "allowed",
+                    &[builder::variable("0")],
+                    &[
+                        builder::pred("@ip_in_cidr", &[builder::variable("0"), builder::string("10.0.0.0/8")]),
+                        builder::pred("source_ip", &[builder::s("ambient"), builder::variable("0")]),
+                    ]
+                )
+            ))
+        );
+
+        let rule = super::rule("weekend($0) <- time(#ambient, $0), @weekday($0, $1) @ $1 == \"sunday\"")
+            .unwrap()
+            .1;
+        assert_eq!(rule.1[1].name, "@weekday");
+        assert_eq!(rule.2.len(), 1);
+    }
+
     #[test]
     fn constrained_rule() {
         assert_eq!(
@@ -1,17 +1,18 @@
 //! helper functions and structure to create tokens and blocks
 use super::{Biscuit, Block};
-use crate::crypto::KeyPair;
+use crate::crypto::{ed25519, PublicKey, Signer};
 use crate::datalog::{
     self, SymbolTable, ID,
 };
 use crate::error;
 use rand_core::{CryptoRng, RngCore};
-use std::{fmt, convert::{TryInto, TryFrom}, time::{SystemTime, Duration, UNIX_EPOCH}, collections::HashSet};
+use std::{fmt, convert::{TryInto, TryFrom}, time::{SystemTime, Duration, UNIX_EPOCH}, collections::HashSet, net::IpAddr};
 
 // reexport those because the builder uses the same definitions
-pub use crate::datalog::{IntConstraint, StrConstraint, BytesConstraint};
+pub use crate::datalog::{IntConstraint, StrConstraint, BytesConstraint, IpConstraint, IpNetwork};
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockBuilder {
     pub index: u32,
     pub facts: Vec<Fact>,
@@ -74,15 +75,17 @@ impl BlockBuilder {
             symbols: symbols.symbols.split_off(symbols_start),
         };
 
-        Block {
+        let mut block = Block {
             index: self.index,
             symbols: new_syms,
             facts,
             rules,
             caveats,
             context: self.context,
-            version: super::MAX_SCHEMA_VERSION,
-        }
+            version: super::MIN_SCHEMA_VERSION,
+        };
+        block.version = block.required_version();
+        block
     }
 
     pub fn check_right(&mut self, right: &str) {
@@ -133,6 +136,22 @@ impl BlockBuilder {
         let _ = self.add_caveat(caveat);
     }
 
+    /// restricts the resource to `path` and the paths below it, comparing whole
+    /// segments: `/a/b` allows `/a/b/c` but not `/a/bc`, unlike [`resource_prefix`](`Self::resource_prefix`)
+    pub fn resource_under(&mut self, path: &str) {
+        let caveat = constrained_rule(
+            "under",
+            &[var("resource")],
+            &[pred("resource", &[s("ambient"), var("resource")])],
+            &[Constraint {
+                id: "resource".to_string(),
+                kind: ConstraintKind::String(datalog::StrConstraint::Under(path.to_string())),
+            }],
+        );
+
+        let _ = self.add_caveat(caveat);
+    }
+
     pub fn resource_suffix(&mut self, suffix: &str) {
         let caveat = constrained_rule(
             "suffix",
@@ -164,11 +183,18 @@ impl BlockBuilder {
     pub fn revocation_id(&mut self, id: i64) {
         let _ = self.add_fact(fact("revocation_id", &[int(id)]));
     }
+
+    /// pins the key that must sign proofs of possession, with a `proof_key(hex:..)` fact
+    ///
+    /// only the first key pinned in a token is used, see [`Verifier::verify_possession`](`crate::token::verifier::Verifier::verify_possession`)
+    pub fn proof_key(&mut self, key: &PublicKey) {
+        let _ = self.add_fact(fact("proof_key", &[bytes(&key.to_bytes())]));
+    }
 }
 
 #[derive(Clone)]
 pub struct BiscuitBuilder<'a> {
-    root: &'a KeyPair,
+    root: RootKeyPair<'a>,
     pub symbols_start: usize,
     pub symbols: SymbolTable,
     pub facts: Vec<datalog::Fact>,
@@ -179,7 +205,22 @@ pub struct BiscuitBuilder<'a> {
 
 impl<'a> BiscuitBuilder<'a> {
     pub fn new(
-        root: &'a KeyPair,
+        root: &'a dyn Signer,
+        base_symbols: SymbolTable,
+    ) -> BiscuitBuilder<'a> {
+        Self::with_root(RootKeyPair::Aggregated(root), base_symbols)
+    }
+
+    /// creates a builder for a token in the Ed25519 format
+    pub fn new_ed25519(
+        root: &'a ed25519::KeyPair,
+        base_symbols: SymbolTable,
+    ) -> BiscuitBuilder<'a> {
+        Self::with_root(RootKeyPair::Ed25519(root), base_symbols)
+    }
+
+    fn with_root(
+        root: RootKeyPair<'a>,
         base_symbols: SymbolTable,
     ) -> BiscuitBuilder<'a> {
         BiscuitBuilder {
@@ -227,6 +268,14 @@ impl<'a> BiscuitBuilder<'a> {
         self.context = Some(context);
     }
 
+    /// pins the key that must sign proofs of possession in the authority block, so
+    /// that the token is bound to its holder from the start
+    ///
+    /// the holder signs requests with [`KeyPair::sign_possession`](`crate::crypto::KeyPair::sign_possession`)
+    pub fn proof_key(&mut self, key: &PublicKey) {
+        let _ = self.add_authority_fact(fact("proof_key", &[bytes(&key.to_bytes())]));
+    }
+
     pub fn build(self) -> Result<Biscuit, error::Token> {
         self.build_with_rng(&mut rand::rngs::OsRng)
     }
@@ -234,28 +283,43 @@ impl<'a> BiscuitBuilder<'a> {
     pub fn build_with_rng<R: RngCore + CryptoRng>(mut self, rng: &'a mut R) -> Result<Biscuit, error::Token> {
         let new_syms = SymbolTable { symbols: self.symbols.symbols.split_off(self.symbols_start) };
 
-        let authority_block = Block {
+        let mut authority_block = Block {
             index: 0,
             symbols: new_syms,
             facts: self.facts,
             rules: self.rules,
             caveats: self.caveats,
             context: self.context,
-            version: super::MAX_SCHEMA_VERSION,
+            version: super::MIN_SCHEMA_VERSION,
         };
+        authority_block.version = authority_block.required_version();
 
-        Biscuit::new_with_rng(rng, self.root, self.symbols, authority_block)
+        match self.root {
+            RootKeyPair::Aggregated(root) => Biscuit::new_with_rng(rng, root, self.symbols, authority_block),
+            RootKeyPair::Ed25519(root) => Biscuit::new_ed25519_with_rng(rng, root, self.symbols, authority_block),
+        }
     }
 }
 
+/// root key of the token, selecting the serialization format
+#[derive(Clone, Copy)]
+enum RootKeyPair<'a> {
+    Aggregated(&'a dyn Signer),
+    Ed25519(&'a ed25519::KeyPair),
+}
+
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum Term {
     Symbol(String),
     Variable(String),
     Integer(i64),
     Str(String),
     Date(u64),
-    Bytes(Vec<u8>),
+    Bytes(#[cfg_attr(feature = "serde", serde(with = "super::json::hex_bytes"))] Vec<u8>),
+    Ip(IpAddr),
+    Network(IpNetwork),
 }
 
 impl Term {
@@ -267,6 +331,8 @@ impl Term {
             Term::Str(s) => ID::Str(s.clone()),
             Term::Date(d) => ID::Date(*d),
             Term::Bytes(s) => ID::Bytes(s.clone()),
+            Term::Ip(ip) => ID::Ip(*ip),
+            Term::Network(n) => ID::Network(*n),
         }
     }
 
@@ -278,6 +344,8 @@ impl Term {
         ID::Str(s) => Term::Str(s.clone()),
         ID::Date(d) => Term::Date(*d),
         ID::Bytes(s) => Term::Bytes(s.clone()),
+        ID::Ip(ip) => Term::Ip(*ip),
+        ID::Network(n) => Term::Network(*n),
       }
     }
 }
@@ -291,6 +359,8 @@ impl From<&Term> for Term {
             Term::Str(ref s) => Term::Str(s.clone()),
             Term::Date(ref d) => Term::Date(*d),
             Term::Bytes(ref s) => Term::Bytes(s.clone()),
+            Term::Ip(ref ip) => Term::Ip(*ip),
+            Term::Network(ref n) => Term::Network(*n),
         }
     }
 }
@@ -313,12 +383,15 @@ impl fmt::Display for Term {
                 write!(f, "{:?}", t)
             }
             Term::Bytes(s) => write!(f, "hex:{}", hex::encode(s)),
+            Term::Ip(ip) => write!(f, "ip:{}", ip),
+            Term::Network(n) => write!(f, "cidr:{}", n),
         }
 
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Predicate {
     pub name: String,
     pub ids: Vec<Term>,
@@ -376,6 +449,8 @@ impl fmt::Display for Predicate {
 
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Fact(pub Predicate);
 
 impl Fact {
@@ -403,6 +478,7 @@ impl fmt::Display for Fact {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Constraint {
     pub id: String,
     pub kind: ConstraintKind,
@@ -448,6 +524,9 @@ impl fmt::Display for Constraint {
             ConstraintKind::String(StrConstraint::Regex(i)) => write!(f, "${} matches /{}/", self.id, i),
             ConstraintKind::String(StrConstraint::In(i)) => write!(f, "${} in {:?}", self.id, i),
             ConstraintKind::String(StrConstraint::NotIn(i)) => write!(f, "${} not in {:?}", self.id, i),
+            ConstraintKind::String(StrConstraint::Contains(i)) => write!(f, "${} contains {}", self.id, i),
+            ConstraintKind::String(StrConstraint::Under(i)) => write!(f, "${} under {}", self.id, i),
+            ConstraintKind::String(StrConstraint::Glob(i)) => write!(f, "${} glob {}", self.id, i),
             ConstraintKind::Date(DateConstraint::Before(date)) => {
               //let date = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(*i as i64, 0), Utc);
               let date: chrono::DateTime<chrono::Utc> = (*date).into();
@@ -471,18 +550,31 @@ impl fmt::Display for Constraint {
                 write!(f, "${} not in {:?}", self.id, i.iter()
                        .map(|s| format!("hex:{}", hex::encode(s))).collect::<HashSet<_>>())
             },
+            ConstraintKind::Ip(IpConstraint::Equal(ip)) => write!(f, "${} == ip:{}", self.id, ip),
+            ConstraintKind::Ip(IpConstraint::In(i)) => {
+                write!(f, "${} in {:?}", self.id, i.iter()
+                       .map(|ip| format!("ip:{}", ip)).collect::<HashSet<_>>())
+            },
+            ConstraintKind::Ip(IpConstraint::NotIn(i)) => {
+                write!(f, "${} not in {:?}", self.id, i.iter()
+                       .map(|ip| format!("ip:{}", ip)).collect::<HashSet<_>>())
+            },
+            ConstraintKind::Ip(IpConstraint::InNetwork(n)) => write!(f, "${} in cidr:{}", self.id, n),
         }
     }
 }
 
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum ConstraintKind {
     Integer(datalog::IntConstraint),
     String(datalog::StrConstraint),
     Date(DateConstraint),
     Symbol(SymbolConstraint),
     Bytes(datalog::BytesConstraint),
+    Ip(datalog::IpConstraint),
 }
 
 impl ConstraintKind {
@@ -491,6 +583,7 @@ impl ConstraintKind {
         ConstraintKind::Integer(i) => datalog::ConstraintKind::Int(i.clone()),
         ConstraintKind::String(s) => datalog::ConstraintKind::Str(s.clone()),
         ConstraintKind::Bytes(s) => datalog::ConstraintKind::Bytes(s.clone()),
+        ConstraintKind::Ip(c) => datalog::ConstraintKind::Ip(c.clone()),
         ConstraintKind::Date(DateConstraint::Before(date)) => {
           let dur = date.duration_since(UNIX_EPOCH).expect("date should be after Unix Epoch");
           datalog::ConstraintKind::Date(datalog::DateConstraint::Before(dur.as_secs()))
@@ -531,23 +624,30 @@ impl ConstraintKind {
           ConstraintKind::Symbol(SymbolConstraint::NotIn(hset))
         },
         datalog::ConstraintKind::Bytes(s) => ConstraintKind::Bytes(s.clone()),
+        datalog::ConstraintKind::Ip(c) => ConstraintKind::Ip(c.clone()),
       }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum DateConstraint {
-    Before(SystemTime),
-    After(SystemTime),
+    Before(#[cfg_attr(feature = "serde", serde(with = "super::json::date"))] SystemTime),
+    After(#[cfg_attr(feature = "serde", serde(with = "super::json::date"))] SystemTime),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum SymbolConstraint {
     In(HashSet<String>),
     NotIn(HashSet<String>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "super::json::RuleJson", into = "super::json::RuleJson"))]
 pub struct Rule(
     pub Predicate,
     pub Vec<Predicate>,
@@ -614,6 +714,7 @@ impl fmt::Display for Rule {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Caveat {
     pub queries: Vec<Rule>,
 }
@@ -756,3 +857,13 @@ pub fn variable(s: &str) -> Term {
 pub fn bytes(s: &[u8]) -> Term {
     Term::Bytes(s.to_vec())
 }
+
+/// creates an IP address
+pub fn ip(ip: IpAddr) -> Term {
+    Term::Ip(ip)
+}
+
+/// creates an IP network, like `10.0.0.0/8`
+pub fn network(n: IpNetwork) -> Term {
+    Term::Network(n)
+}
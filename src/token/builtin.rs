@@ -0,0 +1,57 @@
+//! builtin predicates implemented in Rust
+//!
+//! Some checks are impractical to write in Datalog. A verifier can register
+//! functions with [`Verifier::add_builtin`](`crate::token::verifier::Verifier::add_builtin`),
+//! that rules and caveats call as predicates prefixed with `@`:
+//!
+//! ```text
+//! caveat1($day) <- time(#ambient, $time), @weekday($time, $day) @ $day in ["monday", "tuesday"]
+//! ```
+//!
+//! Builtin predicates are evaluated after the other predicates of the rule body,
+//! so their arguments are bound when possible. The function receives the terms
+//! of the predicate, with variables for arguments that are not bound, and returns
+//! the terms of each matching combination: a check returns its arguments or nothing,
+//! and `@weekday($time, $day)` would return `[$time, "monday"]`. Returned terms that
+//! contain variables, or that do not have the predicate's arity, are ignored.
+//!
+//! Facts cannot provide builtin predicates, and verification fails with
+//! [`error::Logic::UnknownBuiltin`](`crate::error::Logic::UnknownBuiltin`) if a rule or
+//! caveat uses a builtin predicate that was not registered.
+use super::builder::{Caveat, Rule, Term};
+use crate::datalog;
+use std::sync::Arc;
+
+/// function evaluating a builtin predicate
+pub type Builtin = Arc<dyn Fn(&[Term]) -> Vec<Vec<Term>> + Send + Sync>;
+
+/// prefix of the builtin predicate names
+pub const BUILTIN_PREFIX: char = '@';
+
+pub(crate) fn is_builtin(name: &str) -> bool {
+    name.starts_with(BUILTIN_PREFIX)
+}
+
+/// moves the builtin predicates at the end of the rule body
+pub(crate) fn builtins_last(mut rule: Rule) -> Rule {
+    rule.1.sort_by_key(|p| is_builtin(&p.name));
+    rule
+}
+
+/// moves the builtin predicates at the end of each query of the caveat
+pub(crate) fn caveat_builtins_last(caveat: Caveat) -> Caveat {
+    Caveat {
+        queries: caveat.queries.into_iter().map(builtins_last).collect(),
+    }
+}
+
+/// moves the builtin predicates at the end of a rule body that was already converted
+pub(crate) fn datalog_builtins_last(rule: &mut datalog::Rule, symbols: &datalog::SymbolTable) {
+    rule.body.sort_by_key(|p| {
+        symbols
+            .symbols
+            .get(p.name as usize)
+            .map(|name| is_builtin(name))
+            .unwrap_or(false)
+    });
+}
@@ -0,0 +1,276 @@
+//! JSON representation of tokens, available with the `serde` feature
+//!
+//! [`TokenJson`] describes the logical content of a token, and is built from
+//! the [`builder`](super::builder) types, which can also be serialized on their own
+//! to display or construct blocks:
+//!
+//! - terms are tagged with their type: `{"symbol": "read"}`, `{"variable": "0"}`,
+//!   `{"integer": 1}`, `{"str": "file1"}`, `{"date": 1608542592}` (seconds since
+//!   the Unix epoch), `{"bytes": "0a0b"}` (hexadecimal)
+//! - predicates and facts are `{"name": "right", "ids": [..]}`
+//! - rules are `{"head": .., "body": [..], "constraints": [..]}`
+//! - constraints are `{"id": "0", "kind": {"integer": {"lower": 1}}}`, with dates
+//!   in seconds since the Unix epoch and bytes in hexadecimal
+//! - caveats are `{"queries": [..]}`
+//!
+//! ```json
+//! {
+//!   "symbols": ["authority", "ambient", "resource", "operation", "right", "current_time", "revocation_id", "read"],
+//!   "authority": {
+//!     "index": 0,
+//!     "version": 0,
+//!     "symbols": ["read"],
+//!     "context": null,
+//!     "public_key": "0a..",
+//!     "facts": [{"name": "right", "ids": [{"symbol": "authority"}, {"str": "file1"}, {"symbol": "read"}]}],
+//!     "rules": [],
+//!     "caveats": []
+//!   },
+//!   "blocks": []
+//! }
+//! ```
+use super::builder::{self, Caveat, Constraint, Fact, Predicate, Rule};
+use super::{Biscuit, Block};
+use crate::format::Container;
+use serde::{Deserialize, Serialize};
+
+/// logical content of a token
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenJson {
+    /// symbol table of the token, including the default symbols
+    pub symbols: Vec<String>,
+    pub authority: BlockJson,
+    pub blocks: Vec<BlockJson>,
+}
+
+/// logical content of a block
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockJson {
+    pub index: u32,
+    /// format version used to generate this block
+    pub version: u32,
+    /// symbols introduced by this block
+    pub symbols: Vec<String>,
+    pub context: Option<String>,
+    /// public key that signed this block, in hexadecimal
+    ///
+    /// this is `None` for sealed tokens
+    pub public_key: Option<String>,
+    pub facts: Vec<Fact>,
+    pub rules: Vec<Rule>,
+    pub caveats: Vec<Caveat>,
+}
+
+impl TokenJson {
+    /// converts the content of a token
+    pub fn from_token(token: &Biscuit) -> Self {
+        let keys = public_keys(token);
+        let block = |index: usize, block: &Block| BlockJson::from_block(block, token, keys.get(index).cloned());
+
+        TokenJson {
+            symbols: token.symbols.symbols.clone(),
+            authority: block(0, &token.authority),
+            blocks: token
+                .blocks
+                .iter()
+                .enumerate()
+                .map(|(i, b)| block(i + 1, b))
+                .collect(),
+        }
+    }
+}
+
+impl BlockJson {
+    fn from_block(block: &Block, token: &Biscuit, public_key: Option<String>) -> Self {
+        let symbols = &token.symbols;
+
+        BlockJson {
+            index: block.index,
+            version: block.version,
+            symbols: block.symbols.symbols.clone(),
+            context: block.context.clone(),
+            public_key,
+            facts: block.facts.iter().map(|f| Fact::convert_from(f, symbols)).collect(),
+            rules: block.rules.iter().map(|r| Rule::convert_from(r, symbols)).collect(),
+            caveats: block.caveats.iter().map(|c| Caveat::convert_from(c, symbols)).collect(),
+        }
+    }
+
+    /// creates a block builder with the facts, rules, caveats and context of this block
+    pub fn to_builder(&self) -> builder::BlockBuilder {
+        builder::BlockBuilder {
+            index: self.index,
+            facts: self.facts.clone(),
+            rules: self.rules.clone(),
+            caveats: self.caveats.clone(),
+            context: self.context.clone(),
+        }
+    }
+}
+
+/// keys that signed each block, starting with the authority block
+fn public_keys(token: &Biscuit) -> Vec<String> {
+    match token.container.as_ref() {
+        None => vec![],
+        Some(Container::Aggregated(c)) => c.keys.iter().map(|k| k.to_hex()).collect(),
+        // each block is signed by the next key of the block before it
+        Some(Container::Ed25519(c)) => std::iter::once(&c.root)
+            .chain(std::iter::once(&c.authority).chain(c.blocks.iter()).map(|b| &b.next_key))
+            .take(1 + c.blocks.len())
+            .map(|k| k.to_hex())
+            .collect(),
+    }
+}
+
+/// object representation of [`Rule`]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RuleJson {
+    head: Predicate,
+    body: Vec<Predicate>,
+    constraints: Vec<Constraint>,
+}
+
+impl From<Rule> for RuleJson {
+    fn from(r: Rule) -> Self {
+        RuleJson {
+            head: r.0,
+            body: r.1,
+            constraints: r.2,
+        }
+    }
+}
+
+impl From<RuleJson> for Rule {
+    fn from(r: RuleJson) -> Self {
+        Rule(r.head, r.body, r.constraints)
+    }
+}
+
+/// dates as seconds since the Unix epoch
+pub(crate) mod date {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    pub fn serialize<S: Serializer>(date: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let secs = date
+            .duration_since(UNIX_EPOCH)
+            .map_err(serde::ser::Error::custom)?
+            .as_secs();
+        serializer.serialize_u64(secs)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        u64::deserialize(deserializer).map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+}
+
+/// byte arrays as hexadecimal strings
+pub(crate) mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// sets of byte arrays as lists of hexadecimal strings
+pub(crate) mod hex_bytes_set {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::HashSet;
+
+    pub fn serialize<S: Serializer>(set: &HashSet<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(set.iter().map(hex::encode))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HashSet<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|s| hex::decode(s).map_err(serde::de::Error::custom))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use crate::token::builder::*;
+    use rand::prelude::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn json() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.set_context("admin".to_string());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.expiration_date(UNIX_EPOCH + Duration::from_secs(1608542592));
+        block2.add_fact(fact("key", &[bytes(&[0xab, 0xcd])])).unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2.clone()).unwrap();
+
+        let token = TokenJson::from_token(&biscuit2);
+        assert_eq!(token.authority.context, Some("admin".to_string()));
+        assert_eq!(token.authority.public_key, Some(root.public().to_hex()));
+        assert_eq!(token.blocks[0].public_key, Some(keypair2.public().to_hex()));
+        assert_eq!(token.blocks[0].to_builder().caveats, block2.caveats);
+
+        let value = serde_json::to_value(&token).unwrap();
+        assert_eq!(
+            value["authority"]["facts"][0],
+            serde_json::json!({
+                "name": "right",
+                "ids": [{"symbol": "authority"}, {"str": "file1"}, {"symbol": "read"}]
+            })
+        );
+        assert_eq!(
+            value["blocks"][0]["facts"][0]["ids"][0],
+            serde_json::json!({"bytes": "abcd"})
+        );
+        assert_eq!(
+            value["blocks"][0]["caveats"][0]["queries"][0]["constraints"][0]["kind"],
+            serde_json::json!({"date": {"before": 1608542592}})
+        );
+
+        let token2: TokenJson = serde_json::from_value(value).unwrap();
+        assert_eq!(token, token2);
+
+        let json = serde_json::to_string(&block2).unwrap();
+        let block3: BlockBuilder = serde_json::from_str(&json).unwrap();
+        assert_eq!(block3.facts, block2.facts);
+        assert_eq!(block3.caveats, block2.caveats);
+    }
+
+    #[test]
+    fn ed25519_public_keys() {
+        use crate::crypto::ed25519;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = ed25519::KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder_ed25519(&root);
+        builder.add_right("file1", "read");
+        let mut token = builder.build_with_rng(&mut rng).unwrap();
+        for _ in 0..2 {
+            let keypair = KeyPair::new_with_rng(&mut rng);
+            token = token.append_with_rng(&mut rng, &keypair, token.create_block()).unwrap();
+        }
+
+        let container = token.ed25519_container().unwrap();
+        let json = TokenJson::from_token(&token);
+        assert_eq!(json.authority.public_key, Some(root.public().to_hex()));
+        assert_eq!(json.blocks[0].public_key, Some(container.authority.next_key.to_hex()));
+        assert_eq!(json.blocks[1].public_key, Some(container.blocks[0].next_key.to_hex()));
+        assert_ne!(json.blocks[0].public_key, json.blocks[1].public_key);
+    }
+}
@@ -0,0 +1,223 @@
+//! tokens with blocks decoded on demand
+//!
+//! [`LazyBiscuit`] checks the signature of a token, but keeps its blocks serialized
+//! until they are accessed. Decoded blocks are cached, and reused when the token
+//! is fully decoded with [`LazyBiscuit::decode`].
+use super::{default_symbol_table, Biscuit, Block};
+use crate::crypto::{self, PublicKey};
+use crate::datalog::SymbolTable;
+use crate::error;
+use crate::format::{convert::proto_block_to_token_block, schema, Container, DeserializationLimits};
+use once_cell::sync::OnceCell;
+use prost::Message;
+
+/// token with a checked signature, and blocks decoded on demand
+#[derive(Clone, Debug)]
+pub struct LazyBiscuit {
+    container: Container,
+    symbols: SymbolTable,
+    authority: OnceCell<Block>,
+    blocks: Vec<OnceCell<Block>>,
+    limits: Option<DeserializationLimits>,
+}
+
+impl LazyBiscuit {
+    /// deserializes a token and validates its signature, without decoding the blocks
+    pub fn from(slice: &[u8]) -> Result<Self, error::Token> {
+        LazyBiscuit::from_with_symbols(slice, default_symbol_table())
+    }
+
+    /// deserializes a token and validates its signature, without decoding the blocks, with a custom symbol table
+    pub fn from_with_symbols(slice: &[u8], symbols: SymbolTable) -> Result<Self, error::Token> {
+        let container = Container::from_slice(slice).map_err(error::Token::Format)?;
+
+        Ok(LazyBiscuit::from_container(container, symbols, None))
+    }
+
+    /// deserializes a token and validates its signature, without decoding the blocks
+    ///
+    /// the token is checked against the limits before its signature, and each
+    /// block is checked before its conversion
+    pub fn from_with_limits(slice: &[u8], limits: DeserializationLimits) -> Result<Self, error::Token> {
+        let container =
+            Container::from_slice_with_limits(slice, &limits).map_err(error::Token::Format)?;
+
+        Ok(LazyBiscuit::from_container(container, default_symbol_table(), Some(limits)))
+    }
+
+    pub(crate) fn from_container(
+        container: Container,
+        symbols: SymbolTable,
+        limits: Option<DeserializationLimits>,
+    ) -> Self {
+        let blocks = container.blocks().iter().map(|_| OnceCell::new()).collect();
+
+        LazyBiscuit {
+            container,
+            symbols,
+            authority: OnceCell::new(),
+            blocks,
+            limits,
+        }
+    }
+
+    /// tests that the token uses this public key as root
+    pub fn check_root_key(&self, root: PublicKey) -> Result<(), error::Token> {
+        match &self.container {
+            Container::Aggregated(c) => c.check_root_key(root).map_err(error::Token::Format),
+            Container::Ed25519(_) => Err(error::Token::Format(error::Format::UnknownPublicKey)),
+        }
+    }
+
+    /// tests that the token uses this Ed25519 public key as root
+    pub fn check_root_key_ed25519(&self, root: crypto::ed25519::PublicKey) -> Result<(), error::Token> {
+        match &self.container {
+            Container::Ed25519(c) => c.check_root_key(root).map_err(error::Token::Format),
+            Container::Aggregated(_) => Err(error::Token::Format(error::Format::UnknownPublicKey)),
+        }
+    }
+
+    /// number of blocks, including the authority block
+    pub fn block_count(&self) -> usize {
+        1 + self.blocks.len()
+    }
+
+    /// decodes the authority block
+    pub fn authority(&self) -> Result<&Block, error::Token> {
+        self.authority
+            .get_or_try_init(|| decode_block(self.container.authority(), 0, self.limits.as_ref()))
+    }
+
+    /// decodes the block at this index, the authority block being at index 0
+    ///
+    /// returns `Ok(None)` if there is no block at this index
+    pub fn block(&self, index: usize) -> Result<Option<&Block>, error::Token> {
+        if index == 0 {
+            return self.authority().map(Some);
+        }
+
+        match self.blocks.get(index - 1) {
+            None => Ok(None),
+            Some(cell) => cell
+                .get_or_try_init(|| {
+                    decode_block(self.container.blocks()[index - 1], index as u32, self.limits.as_ref())
+                })
+                .map(Some),
+        }
+    }
+
+    /// decodes the remaining blocks and creates a [`Biscuit`]
+    pub fn decode(self) -> Result<Biscuit, error::Token> {
+        let LazyBiscuit {
+            container,
+            mut symbols,
+            authority,
+            blocks: cells,
+            limits,
+        } = self;
+
+        let authority = match authority.into_inner() {
+            Some(block) => block,
+            None => decode_block(container.authority(), 0, limits.as_ref())?,
+        };
+
+        let mut blocks = Vec::with_capacity(cells.len());
+        for (i, (cell, data)) in cells.into_iter().zip(container.blocks()).enumerate() {
+            let block = match cell.into_inner() {
+                Some(block) => block,
+                None => decode_block(data, 1 + i as u32, limits.as_ref())?,
+            };
+            blocks.push(block);
+        }
+
+        symbols
+            .symbols
+            .extend(authority.symbols.symbols.iter().cloned());
+
+        for block in blocks.iter() {
+            symbols
+                .symbols
+                .extend(block.symbols.symbols.iter().cloned());
+        }
+
+        Ok(Biscuit {
+            authority,
+            blocks,
+            symbols,
+            container: Some(container),
+            proof_key: None,
+        })
+    }
+}
+
+fn decode_block(
+    data: &[u8],
+    index: u32,
+    limits: Option<&DeserializationLimits>,
+) -> Result<Block, error::Token> {
+    let block = schema::Block::decode(data).map_err(|e| {
+        let kind = if index == 0 { "authority block" } else { "block" };
+        error::Token::Format(error::Format::BlockDeserializationError(format!(
+            "error deserializing {}: {:?}",
+            kind, e
+        )))
+    })?;
+
+    if let Some(limits) = limits {
+        limits.check_block(&block).map_err(error::Token::Format)?;
+    }
+
+    let block: Block = proto_block_to_token_block(&block).map_err(error::Token::Format)?;
+
+    if index == 0 {
+        if block.index != 0 {
+            return Err(error::Token::InvalidAuthorityIndex(block.index));
+        }
+    } else if block.index != index {
+        return Err(error::Token::InvalidBlockIndex(error::InvalidBlockIndex {
+            expected: index,
+            found: block.index,
+        }));
+    }
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::KeyPair;
+    use rand::prelude::*;
+
+    #[test]
+    fn lazy_decoding() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.set_context("authority context".to_string());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.check_operation("read");
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+
+        let lazy = LazyBiscuit::from(&serialized).unwrap();
+        lazy.check_root_key(root.public()).unwrap();
+        assert_eq!(lazy.block_count(), 2);
+        assert!(lazy.authority.get().is_none());
+        assert!(lazy.blocks[0].get().is_none());
+
+        assert_eq!(lazy.authority().unwrap().context, Some("authority context".to_string()));
+        assert!(lazy.blocks[0].get().is_none());
+
+        assert_eq!(lazy.block(1).unwrap().unwrap().index, 1);
+        assert!(lazy.block(2).unwrap().is_none());
+
+        let biscuit3 = lazy.decode().unwrap();
+        assert_eq!(biscuit3.print(), biscuit2.print());
+    }
+}
@@ -1,12 +1,14 @@
 //! main structures to interact with Biscuit tokens
-use super::crypto::{KeyPair, PublicKey};
-use super::datalog::{Fact, Rule, Caveat, SymbolTable, World, ID};
+use super::crypto::{self, KeyPair, PublicKey, Signer};
+use super::datalog::{self, Fact, Rule, Caveat, SymbolTable, World, ID};
 use super::error;
-use super::format::SerializedBiscuit;
+use super::format::{Container, DeserializationLimits, Ed25519SerializedBiscuit, SerializedBiscuit};
 use builder::{BiscuitBuilder, BlockBuilder};
 use prost::Message;
 use rand_core::{CryptoRng, RngCore};
 use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
 #[cfg(test)]
 use std::collections::HashMap;
 
@@ -14,11 +16,28 @@ use crate::format::{convert::proto_block_to_token_block, schema};
 use verifier::Verifier;
 
 pub mod builder;
+pub mod builtin;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod lazy;
+pub mod provider;
+pub mod revocation;
 pub mod sealed;
 pub mod verifier;
 
+/// minimum supported version of the serialization format
+pub const MIN_SCHEMA_VERSION: u32 = 0;
+
 /// maximum supported version of the serialization format
-pub const MAX_SCHEMA_VERSION: u32 = 0;
+///
+/// each block declares the minimal version needed to read its content, so blocks
+/// that do not use new features can still be read by older implementations:
+/// - version 0: original format
+/// - version 1: adds the `contains` string constraint (`$0 contains "abc"`)
+/// - version 2: adds IP address and network terms (`ip:10.0.0.1`, `cidr:10.0.0.0/8`)
+///   and their constraints
+/// - version 3: adds the path constraints (`$0 under "/files"`, `$0 glob "/files/*/read"`)
+pub const MAX_SCHEMA_VERSION: u32 = 3;
 
 /// some symbols are predefined and available in every implementation, to avoid
 /// transmitting them with every token
@@ -35,6 +54,30 @@ pub fn default_symbol_table() -> SymbolTable {
     syms
 }
 
+/// extracts a token from the value of an `Authorization` header using the `Bearer` scheme
+///
+/// the scheme is matched case insensitively. Returns `None` for other schemes
+/// or if the token is missing
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let header = header.trim();
+    let separator = header.find(char::is_whitespace)?;
+    let (scheme, token) = header.split_at(separator);
+    let token = token.trim_start();
+
+    if !scheme.eq_ignore_ascii_case("bearer") || token.is_empty() || token.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some(token)
+}
+
+fn invalid_base64(e: base64::DecodeError) -> error::Token {
+    error::Token::Format(error::Format::DeserializationError(format!(
+        "deserialization error: invalid base64: {}",
+        e
+    )))
+}
+
 /// This structure represents a valid Biscuit token
 ///
 /// It contains multiple `Block` elements, the associated symbol table,
@@ -72,17 +115,20 @@ pub struct Biscuit {
     pub(crate) authority: Block,
     pub(crate) blocks: Vec<Block>,
     pub(crate) symbols: SymbolTable,
-    container: Option<SerializedBiscuit>,
+    container: Option<Container>,
+    /// private key pinned by [`Biscuit::append_with_proof_key`], kept to sign proofs of possession
+    proof_key: Option<Arc<KeyPair>>,
 }
 
 impl Biscuit {
     /// creates a new token
     ///
-    /// the public part of the root keypair must be used for verification
+    /// the public part of the root keypair must be used for verification. The root
+    /// can be a [`KeyPair`] or any other [`Signer`]
     ///
     /// The block is an authority block: its index must be 0 and all of its facts must have the authority tag
     pub fn new(
-        root: &KeyPair,
+        root: &dyn Signer,
         symbols: SymbolTable,
         authority: Block,
     ) -> Result<Biscuit, error::Token> {
@@ -91,15 +137,57 @@ impl Biscuit {
 
     /// creates a new token, using a provided CSPRNG
     ///
-    /// the public part of the root keypair must be used for verification
+    /// the public part of the root keypair must be used for verification. The root
+    /// can be a [`KeyPair`] or any other [`Signer`]
     ///
     /// The block is an authority block: its index must be 0 and all of its facts must have the authority tag
     pub fn new_with_rng<T: RngCore + CryptoRng>(
         rng: &mut T,
-        root: &KeyPair,
+        root: &dyn Signer,
+        mut symbols: SymbolTable,
+        authority: Block,
+    ) -> Result<Biscuit, error::Token> {
+        Biscuit::check_authority(&mut symbols, &authority)?;
+
+        let container =
+            SerializedBiscuit::new(rng, root, &authority).map_err(error::Token::Format)?;
+
+        Ok(Biscuit {
+            authority,
+            blocks: vec![],
+            symbols,
+            container: Some(Container::Aggregated(container)),
+            proof_key: None,
+        })
+    }
+
+    /// creates a new token in the Ed25519 format, using a provided CSPRNG
+    ///
+    /// the public part of the root keypair must be used for verification, with
+    /// [`Biscuit::verify_ed25519`]
+    ///
+    /// The block is an authority block: its index must be 0 and all of its facts must have the authority tag
+    pub fn new_ed25519_with_rng<T: RngCore + CryptoRng>(
+        rng: &mut T,
+        root: &crypto::ed25519::KeyPair,
         mut symbols: SymbolTable,
         authority: Block,
     ) -> Result<Biscuit, error::Token> {
+        Biscuit::check_authority(&mut symbols, &authority)?;
+
+        let container =
+            Ed25519SerializedBiscuit::new(rng, root, &authority).map_err(error::Token::Format)?;
+
+        Ok(Biscuit {
+            authority,
+            blocks: vec![],
+            symbols,
+            container: Some(Container::Ed25519(Box::new(container))),
+            proof_key: None,
+        })
+    }
+
+    fn check_authority(symbols: &mut SymbolTable, authority: &Block) -> Result<(), error::Token> {
         let h1 = symbols.symbols.iter().collect::<HashSet<_>>();
         let h2 = authority.symbols.symbols.iter().collect::<HashSet<_>>();
 
@@ -115,95 +203,106 @@ impl Biscuit {
             .symbols
             .extend(authority.symbols.symbols.iter().cloned());
 
-        let blocks = vec![];
-
-        let container =
-            SerializedBiscuit::new(rng, root, &authority).map_err(error::Token::Format)?;
-
-        Ok(Biscuit {
-            authority,
-            blocks,
-            symbols,
-            container: Some(container),
-        })
+        Ok(())
     }
 
     /// deserializes a token and validates the signature using the root public key
+    ///
+    /// both the aggregated and the Ed25519 formats are accepted. To check the signature
+    /// without decoding all the blocks, use [`LazyBiscuit`](`lazy::LazyBiscuit`)
     pub fn from(slice: &[u8]) -> Result<Self, error::Token> {
       Biscuit::from_with_symbols(slice, default_symbol_table())
     }
 
-    /// deserializes a token and validates the signature using the root public key, with a custom symbol table
-    pub fn from_with_symbols(slice: &[u8], mut symbols: SymbolTable) -> Result<Self, error::Token> {
-        let container = SerializedBiscuit::from_slice(slice).map_err(error::Token::Format)?;
-
-        let authority: Block = schema::Block::decode(&container.authority[..])
-            .map_err(|e| {
-                error::Token::Format(error::Format::BlockDeserializationError(format!(
-                    "error deserializing authority block: {:?}",
-                    e
-                )))
-            })
-            .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+    /// deserializes a token and validates the signature using the root public key,
+    /// rejecting tokens that exceed the limits
+    ///
+    /// the limits are checked before the signature and the conversion of each block
+    pub fn from_with_limits(slice: &[u8], limits: DeserializationLimits) -> Result<Self, error::Token> {
+        lazy::LazyBiscuit::from_with_limits(slice, limits)?.decode()
+    }
 
-        if authority.index != 0 {
-            return Err(error::Token::InvalidAuthorityIndex(authority.index));
-        }
+    /// deserializes a token and validates the signature using the root public key, with a custom symbol table
+    pub fn from_with_symbols(slice: &[u8], symbols: SymbolTable) -> Result<Self, error::Token> {
+        let container = Container::from_slice(slice).map_err(error::Token::Format)?;
 
-        let mut blocks = vec![];
+        Biscuit::from_container(container, symbols)
+    }
 
-        let mut index = 1;
-        for block in container.blocks.iter() {
-            let deser: Block = schema::Block::decode(&block[..])
-                .map_err(|e| {
-                    error::Token::Format(error::Format::BlockDeserializationError(format!(
-                        "error deserializing block: {:?}",
-                        e
-                    )))
-                })
-                .and_then(|b| proto_block_to_token_block(&b).map_err(error::Token::Format))?;
+    /// deserializes a token and validates the signature using the root public key
+    ///
+    /// unlike [`Biscuit::from`], this accepts tokens using the legacy signature
+    /// scheme ([`LEGACY_SIGNATURE_VERSION`](`crate::crypto::LEGACY_SIGNATURE_VERSION`))
+    pub fn from_legacy(slice: &[u8]) -> Result<Self, error::Token> {
+        Biscuit::from_legacy_with_symbols(slice, default_symbol_table())
+    }
 
-            if deser.index != index {
-                return Err(error::Token::InvalidBlockIndex(error::InvalidBlockIndex {
-                    expected: index,
-                    found: deser.index,
-                }));
-            }
-            blocks.push(deser);
+    /// deserializes a token and validates the signature using the root public key, with a custom symbol table
+    ///
+    /// unlike [`Biscuit::from_with_symbols`], this accepts tokens using the legacy signature
+    /// scheme ([`LEGACY_SIGNATURE_VERSION`](`crate::crypto::LEGACY_SIGNATURE_VERSION`))
+    pub fn from_legacy_with_symbols(slice: &[u8], symbols: SymbolTable) -> Result<Self, error::Token> {
+        let container = Container::from_slice_with_legacy(slice).map_err(error::Token::Format)?;
 
-            index += 1;
-        }
+        Biscuit::from_container(container, symbols)
+    }
 
-        symbols
-            .symbols
-            .extend(authority.symbols.symbols.iter().cloned());
+    /// deserializes a token encoded in URL safe base64 and validates the signature using the root public key
+    ///
+    /// padding is accepted but not required
+    pub fn from_base64(s: &str) -> Result<Self, error::Token> {
+        Biscuit::from(&crypto::encoding::from_base64(s).map_err(invalid_base64)?)
+    }
 
-        for block in blocks.iter() {
-            symbols
-                .symbols
-                .extend(block.symbols.symbols.iter().cloned());
-        }
+    /// deserializes a token from the value of an `Authorization: Bearer` header
+    pub fn from_bearer(header: &str) -> Result<Self, error::Token> {
+        let token = bearer_token(header).ok_or_else(|| {
+            error::Token::Format(error::Format::DeserializationError(
+                "deserialization error: missing bearer token".to_string(),
+            ))
+        })?;
 
-        let container = Some(container);
+        Biscuit::from_base64(token)
+    }
 
-        Ok(Biscuit {
-            authority,
-            blocks,
-            symbols,
-            container,
-        })
+    fn from_container(container: Container, symbols: SymbolTable) -> Result<Self, error::Token> {
+        lazy::LazyBiscuit::from_container(container, symbols, None).decode()
     }
 
     /// deserializes a sealed token and checks its signature with the secret, using a custom symbol table
+    ///
+    /// both tokens created by [`Biscuit::seal`] and [`Biscuit::seal_encrypted`] are accepted
     pub fn from_sealed(slice: &[u8], secret: &[u8]) -> Result<Self, error::Token> {
       Biscuit::from_sealed_with_symbols(slice, secret, default_symbol_table())
     }
 
     /// deserializes a sealed token and checks its signature with the secret
-    pub fn from_sealed_with_symbols(slice: &[u8], secret: &[u8], mut symbols: SymbolTable) -> Result<Self, error::Token> {
+    pub fn from_sealed_with_symbols(slice: &[u8], secret: &[u8], symbols: SymbolTable) -> Result<Self, error::Token> {
         let container =
             sealed::SealedBiscuit::from_slice(slice, secret).map_err(error::Token::Format)?;
 
+        Biscuit::from_sealed_container(container, symbols)
+    }
+
+    /// deserializes a sealed token and checks its signature with the keyring
+    ///
+    /// the token is checked with the key matching its key id, if this key has
+    /// not expired. Tokens without a key id are checked with the current key
+    pub fn from_sealed_with_keyring(slice: &[u8], keyring: &sealed::SealingKeyring) -> Result<Self, error::Token> {
+        let container = sealed::SealedBiscuit::from_slice_with_keyring(slice, keyring, SystemTime::now())
+            .map_err(error::Token::Format)?;
+
+        Biscuit::from_sealed_container(container, default_symbol_table())
+    }
+
+    /// deserializes a sealed token encoded in URL safe base64 and checks its signature with the secret
+    ///
+    /// padding is accepted but not required
+    pub fn from_sealed_base64(s: &str, secret: &[u8]) -> Result<Self, error::Token> {
+        Biscuit::from_sealed(&crypto::encoding::from_base64(s).map_err(invalid_base64)?, secret)
+    }
+
+    fn from_sealed_container(container: sealed::SealedBiscuit, mut symbols: SymbolTable) -> Result<Self, error::Token> {
         let authority: Block = schema::Block::decode(&container.authority[..])
             .map_err(|e| {
                 error::Token::Format(error::Format::BlockDeserializationError(format!(
@@ -258,6 +357,7 @@ impl Biscuit {
             blocks,
             symbols,
             container,
+            proof_key: None,
         })
     }
 
@@ -269,6 +369,11 @@ impl Biscuit {
         }
     }
 
+    /// serializes the token in URL safe base64, without padding
+    pub fn to_base64(&self) -> Result<String, error::Token> {
+        self.to_vec().map(|v| crypto::encoding::to_base64(&v))
+    }
+
     /// serializes the token
     pub fn serialized_size(&self) -> Result<usize, error::Token> {
         match self.container.as_ref() {
@@ -292,18 +397,103 @@ impl Biscuit {
         sealed.to_vec().map_err(error::Token::Format)
     }
 
+    /// serializes a sealed version of the token in URL safe base64, without padding
+    pub fn seal_base64(&self, secret: &[u8]) -> Result<String, error::Token> {
+        self.seal(secret).map(|v| crypto::encoding::to_base64(&v))
+    }
+
+    /// serializes a sealed version of the token, with blocks encrypted by a key
+    /// derived from the secret
+    ///
+    /// unlike [`Biscuit::seal`], the content of the token cannot be read without the secret
+    pub fn seal_encrypted(&self, secret: &[u8]) -> Result<Vec<u8>, error::Token> {
+        self.seal_encrypted_with_rng(&mut rand::rngs::OsRng, secret)
+    }
+
+    /// serializes an encrypted sealed version of the token, using the provided CSPRNG
+    pub fn seal_encrypted_with_rng<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        secret: &[u8],
+    ) -> Result<Vec<u8>, error::Token> {
+        let sealed =
+            sealed::SealedBiscuit::from_token(self, secret).map_err(error::Token::Format)?;
+        sealed
+            .to_encrypted_vec(rng, secret)
+            .map_err(error::Token::Format)
+    }
+
+    /// serializes a sealed version of the token, with the current key of the keyring
+    pub fn seal_with_keyring(&self, keyring: &sealed::SealingKeyring) -> Result<Vec<u8>, error::Token> {
+        let (sealed, _) = self.sealed_with_keyring(keyring)?;
+        sealed.to_vec().map_err(error::Token::Format)
+    }
+
+    /// serializes an encrypted sealed version of the token, with the current key of the keyring
+    pub fn seal_encrypted_with_keyring(&self, keyring: &sealed::SealingKeyring) -> Result<Vec<u8>, error::Token> {
+        self.seal_encrypted_with_keyring_with_rng(&mut rand::rngs::OsRng, keyring)
+    }
+
+    /// serializes an encrypted sealed version of the token, with the current key of the
+    /// keyring, using the provided CSPRNG
+    pub fn seal_encrypted_with_keyring_with_rng<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        keyring: &sealed::SealingKeyring,
+    ) -> Result<Vec<u8>, error::Token> {
+        let (sealed, secret) = self.sealed_with_keyring(keyring)?;
+        sealed
+            .to_encrypted_vec(rng, secret)
+            .map_err(error::Token::Format)
+    }
+
+    fn sealed_with_keyring<'a>(
+        &self,
+        keyring: &'a sealed::SealingKeyring,
+    ) -> Result<(sealed::SealedBiscuit, &'a [u8]), error::Token> {
+        let (id, secret) = keyring
+            .current_secret(SystemTime::now())
+            .map_err(error::Token::Format)?;
+        let mut sealed =
+            sealed::SealedBiscuit::from_token(self, secret).map_err(error::Token::Format)?;
+        sealed.key_id = Some(id);
+        Ok((sealed, secret))
+    }
+
     /// returns the internal representation of the token
+    ///
+    /// this returns `None` for tokens in the Ed25519 format, see [`Biscuit::ed25519_container`]
     pub fn container(&self) -> Option<&SerializedBiscuit> {
-        self.container.as_ref()
+        match self.container.as_ref() {
+            Some(Container::Aggregated(c)) => Some(c),
+            _ => None,
+        }
+    }
+
+    /// returns the internal representation of a token in the Ed25519 format
+    pub fn ed25519_container(&self) -> Option<&Ed25519SerializedBiscuit> {
+        match self.container.as_ref() {
+            Some(Container::Ed25519(c)) => Some(c),
+            _ => None,
+        }
     }
 
     /// tests that the token uses this public key as root
     pub fn check_root_key(&self, root: PublicKey) -> Result<(), error::Token> {
-        self.container
-            .as_ref()
-            .map(|c| c.check_root_key(root).map_err(error::Token::Format))
-            .unwrap_or(Err(error::Token::Sealed))?;
-        Ok(())
+        match self.container.as_ref() {
+            None => Err(error::Token::Sealed),
+            Some(Container::Aggregated(c)) => c.check_root_key(root).map_err(error::Token::Format),
+            Some(Container::Ed25519(_)) => Err(error::Token::Format(error::Format::UnknownPublicKey)),
+        }
+    }
+
+    /// tests that the token uses this Ed25519 public key as root
+    pub fn check_root_key_ed25519(&self, root: crypto::ed25519::PublicKey) -> Result<(), error::Token> {
+        match self.container.as_ref() {
+            None => Err(error::Token::Sealed),
+            Some(Container::Ed25519(c)) => c.check_root_key(root).map_err(error::Token::Format),
+            Some(Container::Aggregated(_)) => Err(error::Token::Format(error::Format::UnknownPublicKey)),
+        }
     }
 
     /// creates a verifier from this token
@@ -314,6 +504,14 @@ impl Biscuit {
         Verifier::from_token(self).map_err(error::Token::FailedLogic)
     }
 
+    /// creates a verifier from a token in the Ed25519 format
+    ///
+    /// this will also call [`Biscuit::check_root_key_ed25519`]
+    pub fn verify_ed25519(&self, root: crypto::ed25519::PublicKey) -> Result<Verifier, error::Token> {
+        self.check_root_key_ed25519(root)?;
+        Verifier::from_token(self).map_err(error::Token::FailedLogic)
+    }
+
     /// creates a verifier from this token
     pub fn verify_sealed(&self) -> Result<Verifier, error::Token> {
         if self.container.is_some() {
@@ -340,7 +538,7 @@ impl Biscuit {
         }
 
         for rule in self.authority.rules.iter().cloned() {
-            world.rules.push(rule);
+            world.add_rule(rule);
         }
 
         for (i, block) in self.blocks.iter().enumerate() {
@@ -368,7 +566,7 @@ impl Biscuit {
                         symbols.print_rule(&rule),
                     ));
                 }
-                world.rules.push(rule);
+                world.add_rule(rule);
             }
         }
 
@@ -388,6 +586,29 @@ impl Biscuit {
         result
     }
 
+    /// returns the revocation ids (integer arguments of `revocation_id` facts) of each block
+    pub(crate) fn revocation_ids(&self) -> Vec<Vec<i64>> {
+        let revocation_id = match self.symbols.get("revocation_id") {
+            Some(s) => s,
+            None => return vec![vec![]; 1 + self.blocks.len()],
+        };
+
+        std::iter::once(&self.authority)
+            .chain(self.blocks.iter())
+            .map(|block| {
+                block
+                    .facts
+                    .iter()
+                    .filter(|f| f.predicate.name == revocation_id)
+                    .filter_map(|f| match f.predicate.ids.as_slice() {
+                        [ID::Integer(i)] => Some(*i),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
     /// checks the caveats of a token, in the context of the request it comes with
     ///
     /// the verifier provides ambient facts (that must carry the "ambient" tag) like
@@ -420,7 +641,7 @@ impl Biscuit {
         }
 
         for rule in ambient_rules.iter().cloned() {
-            world.rules.push(rule);
+            world.add_rule(rule);
         }
 
         world.run().map_err(error::Token::RunLimit)?;
@@ -511,19 +732,34 @@ impl Biscuit {
     ///
     /// call [`builder::BiscuitBuilder::build`] to create the token
     pub fn builder<'a>(
-        root: &'a KeyPair,
+        root: &'a dyn Signer,
     ) -> BiscuitBuilder<'a> {
         Biscuit::builder_with_symbols(root, default_symbol_table())
     }
 
     /// create the first block's builder, sing a provided symbol table
     pub fn builder_with_symbols<'a>(
-        root: &'a KeyPair,
+        root: &'a dyn Signer,
         symbols: SymbolTable,
     ) -> BiscuitBuilder<'a> {
         BiscuitBuilder::new(root, symbols)
     }
 
+    /// create the first block's builder, for a token in the Ed25519 format
+    pub fn builder_ed25519(
+        root: &crypto::ed25519::KeyPair,
+    ) -> BiscuitBuilder<'_> {
+        Biscuit::builder_ed25519_with_symbols(root, default_symbol_table())
+    }
+
+    /// create the first block's builder, for a token in the Ed25519 format, using a provided symbol table
+    pub fn builder_ed25519_with_symbols(
+        root: &crypto::ed25519::KeyPair,
+        symbols: SymbolTable,
+    ) -> BiscuitBuilder<'_> {
+        BiscuitBuilder::new_ed25519(root, symbols)
+    }
+
     /// creates a new block builder
     pub fn create_block(&self) -> BlockBuilder {
         BlockBuilder::new((1 + self.blocks.len()) as u32)
@@ -533,6 +769,9 @@ impl Biscuit {
     ///
     /// since the public key is integrated into the token, the keypair can be
     /// discarded right after calling this function
+    ///
+    /// tokens in the Ed25519 format do not use the keypair: the key signing
+    /// the next block is generated and stored in the token
     pub fn append(
         &self,
         keypair: &KeyPair,
@@ -592,9 +831,72 @@ impl Biscuit {
             blocks,
             symbols,
             container: Some(container),
+            proof_key: None,
         })
     }
 
+    /// adds a new block to the token, pinning the block's public key with a
+    /// `proof_key` fact, and keeps the keypair to sign proofs of possession with
+    /// [`Biscuit::sign_request`]
+    ///
+    /// only the first key pinned in a token is used, so this fails with
+    /// [`ProofKeyAlreadyPinned`](`error::Token::ProofKeyAlreadyPinned`) if one is
+    /// already pinned. It is not supported by tokens in the Ed25519 format
+    pub fn append_with_proof_key(
+        &self,
+        keypair: KeyPair,
+        mut block_builder: BlockBuilder,
+    ) -> Result<Self, error::Token> {
+        if let Some(Container::Ed25519(_)) = self.container.as_ref() {
+            return Err(error::Token::MissingProofKey);
+        }
+        if self.proof_key().is_some() {
+            return Err(error::Token::ProofKeyAlreadyPinned);
+        }
+
+        block_builder.proof_key(&keypair.public());
+        let mut token = self.append(&keypair, block_builder)?;
+        token.proof_key = Some(Arc::new(keypair));
+        Ok(token)
+    }
+
+    /// signs a request or a challenge with the pinned private key
+    ///
+    /// the verifier checks it with [`Verifier::verify_possession`]. This requires
+    /// the token to be returned by [`Biscuit::append_with_proof_key`]
+    pub fn sign_request(&self, request: &[u8]) -> Result<crypto::Signature, error::Token> {
+        self.sign_request_with_rng(&mut rand::rngs::OsRng, request)
+    }
+
+    /// signs a request or a challenge with the pinned private key
+    pub fn sign_request_with_rng<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        request: &[u8],
+    ) -> Result<crypto::Signature, error::Token> {
+        match self.proof_key.as_ref() {
+            Some(keypair) => Ok(keypair.sign_possession(rng, request)),
+            None => Err(error::Token::MissingProofKey),
+        }
+    }
+
+    /// key pinned by the first `proof_key(hex:..)` fact of the token
+    ///
+    /// later blocks cannot replace it, so whoever appends a block to a stolen
+    /// token cannot sign proofs of possession for it
+    pub(crate) fn proof_key(&self) -> Option<PublicKey> {
+        let proof_key = self.symbols.get("proof_key")?;
+
+        std::iter::once(&self.authority)
+            .chain(self.blocks.iter())
+            .flat_map(|block| block.facts.iter())
+            .filter(|f| f.predicate.name == proof_key)
+            .find_map(|f| match f.predicate.ids.as_slice() {
+                [ID::Bytes(key)] => PublicKey::from_bytes(key),
+                _ => None,
+            })
+    }
+
     /// returns the list of context elements of each block
     ///
     /// the context is a free form text field in which application specific data
@@ -680,7 +982,8 @@ pub struct Block {
     /// caveats that the token and ambient data must validate
     pub caveats: Vec<Caveat>,
     /// contextual information that can be looked up before the verification
-    /// (as an example, a user id to query rights into a database)
+    /// (as an example, a user id to query rights into a database), or used to
+    /// set up a [`FactProvider`](`provider::FactProvider`) queried during the verification
     pub context: Option<String>,
     /// format version used to generate this block
     pub version: u32,
@@ -698,10 +1001,34 @@ impl Block {
             rules: vec![],
             caveats: vec![],
             context: None,
-            version: MAX_SCHEMA_VERSION,
+            version: MIN_SCHEMA_VERSION,
         }
     }
 
+    /// minimal format version needed to read this block
+    pub fn required_version(&self) -> u32 {
+        let rules = || {
+            self.rules
+                .iter()
+                .chain(self.caveats.iter().flat_map(|c| c.queries.iter()))
+        };
+
+        let ids = self
+            .facts
+            .iter()
+            .map(|f| &f.predicate)
+            .chain(rules().flat_map(|r| std::iter::once(&r.head).chain(r.body.iter())))
+            .flat_map(|p| p.ids.iter())
+            .map(id_version);
+
+        rules()
+            .flat_map(|r| r.constraints.iter())
+            .map(constraint_version)
+            .chain(ids)
+            .max()
+            .unwrap_or(MIN_SCHEMA_VERSION)
+    }
+
     pub fn symbol_add(&mut self, s: &str) -> ID {
         self.symbols.add(s)
     }
@@ -711,6 +1038,23 @@ impl Block {
     }
 }
 
+fn constraint_version(constraint: &datalog::Constraint) -> u32 {
+    match &constraint.kind {
+        datalog::ConstraintKind::Str(datalog::StrConstraint::Contains(_)) => 1,
+        datalog::ConstraintKind::Ip(_) => 2,
+        datalog::ConstraintKind::Str(datalog::StrConstraint::Under(_))
+        | datalog::ConstraintKind::Str(datalog::StrConstraint::Glob(_)) => 3,
+        _ => MIN_SCHEMA_VERSION,
+    }
+}
+
+fn id_version(id: &datalog::ID) -> u32 {
+    match id {
+        datalog::ID::Ip(_) | datalog::ID::Network(_) => 2,
+        _ => MIN_SCHEMA_VERSION,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::builder::{fact, pred, rule, s, var, int};
@@ -997,68 +1341,742 @@ mod tests {
     }
 
     #[test]
-    fn sealed_token() {
+    fn revocation_store() {
+        use super::revocation::{BloomRevocationStore, RevocationStore};
+        use std::sync::Arc;
+
         let mut rng: StdRng = SeedableRng::seed_from_u64(0);
         let root = KeyPair::new_with_rng(&mut rng);
-        let mut builder = Biscuit::builder(&root);
-
-        builder.add_right("/folder1/file1", "read");
-        builder.add_right("/folder1/file1", "write");
-        builder.add_right("/folder1/file2", "read");
-        builder.add_right("/folder1/file2", "write");
-        builder.add_right("/folder2/file3", "read");
 
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.add_authority_fact(fact("revocation_id", &[int(1)])).unwrap();
         let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
 
-        println!("biscuit1 (authority): {}", biscuit1.print());
-
         let mut block2 = biscuit1.create_block();
-
-        block2.resource_prefix("/folder1/");
-        block2.check_right("read");
+        block2.revocation_id(1234);
 
         let keypair2 = KeyPair::new_with_rng(&mut rng);
         let biscuit2 = biscuit1
             .append_with_rng(&mut rng, &keypair2, block2)
             .unwrap();
 
-        //println!("biscuit2:\n{:#?}", biscuit2);
-        //panic!();
+        let revoked: HashSet<i64> = [5, 1234].iter().cloned().collect();
+
+        {
+            let mut verifier = biscuit1.verify(root.public()).unwrap();
+            verifier.set_revocation_store(Arc::new(revoked.clone()));
+            verifier.add_resource("file1");
+            verifier.add_operation("read");
+
+            assert_eq!(verifier.verify(), Ok(()));
+        }
+
         {
             let mut verifier = biscuit2.verify(root.public()).unwrap();
-            verifier.add_resource("/folder1/file1");
+            verifier.set_revocation_store(Arc::new(revoked.clone()));
+            verifier.add_resource("file1");
             verifier.add_operation("read");
 
-            let res = verifier.verify();
-            println!("res1: {:?}", res);
-            res.unwrap();
+            assert_eq!(verifier.verify(), Err(Token::Revoked { scope: None, block_id: 1 }));
         }
 
-        let _serialized = biscuit2.to_vec().unwrap();
-        //println!("biscuit2 serialized ({} bytes):\n{}", serialized.len(), serialized.to_hex(16));
+        {
+            let store = BloomRevocationStore::from_ids(revoked.clone(), revoked.iter().cloned(), 0.01);
+            assert!(store.is_revoked(1234).unwrap());
 
-        let secret = b"secret key";
-        let sealed = biscuit2.seal(&secret[..]).unwrap();
-        //println!("biscuit2 sealed ({} bytes):\n{}", sealed.len(), sealed.to_hex(16));
+            let mut verifier = Verifier::new().unwrap();
+            verifier.set_revocation_store(Arc::new(store));
+            verifier.add_token(&biscuit2).unwrap();
+            let revoked_authority: HashSet<i64> = [1].iter().cloned().collect();
+            let mut verifier2 = verifier.clone();
 
-        let biscuit3 = Biscuit::from_sealed(&sealed, &secret[..]).unwrap();
+            assert_eq!(verifier.verify(), Err(Token::Revoked { scope: None, block_id: 1 }));
+
+            verifier2.set_revocation_store(Arc::new(revoked_authority));
+            assert_eq!(verifier2.verify(), Err(Token::Revoked { scope: None, block_id: 0 }));
+        }
 
         {
-            let mut verifier = biscuit3.verify_sealed().unwrap();
-            verifier.add_resource("/folder1/file1");
-            verifier.add_operation("read");
+            let mut verifier = Verifier::new().unwrap();
+            verifier.set_revocation_store(Arc::new(revoked.clone()));
+            verifier.add_scoped_token("user", &biscuit1).unwrap();
+            verifier.add_scoped_token("service", &biscuit2).unwrap();
 
-            let res = verifier.verify();
-            println!("res1: {:?}", res);
-            res.unwrap();
+            assert_eq!(
+                verifier.verify(),
+                Err(Token::Revoked { scope: Some("service".to_string()), block_id: 1 })
+            );
         }
     }
 
     #[test]
-    fn verif_no_blocks() {
-      use crate::token::builder::*;
+    fn proof_of_possession() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
 
-      let mut rng: StdRng = SeedableRng::seed_from_u64(1234);
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.sign_request(&b"GET /file1"[..]).unwrap_err(), Token::MissingProofKey);
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let public2 = keypair2.public();
+        let biscuit2 = biscuit1
+            .append_with_proof_key(keypair2, biscuit1.create_block())
+            .unwrap();
+
+        let proof = biscuit2.sign_request_with_rng(&mut rng, &b"GET /file1"[..]).unwrap();
+        assert_eq!(public2.verify_possession(&b"GET /file1"[..], &proof), Ok(()));
+
+        let verifier = biscuit2.verify(root.public()).unwrap();
+        assert_eq!(verifier.verify_possession(&b"GET /file1"[..], &proof), Ok(()));
+        assert_eq!(
+            verifier.verify_possession(&b"GET /file2"[..], &proof),
+            Err(Token::InvalidPossessionProof)
+        );
+
+        // a proof from the previous holder does not match the last block
+        let stolen = root.sign_possession(&mut rng, &b"GET /file1"[..]);
+        assert_eq!(
+            verifier.verify_possession(&b"GET /file1"[..], &stolen),
+            Err(Token::InvalidPossessionProof)
+        );
+
+        // the key is not kept across a regular append, but stays pinned
+        let keypair3 = KeyPair::new_with_rng(&mut rng);
+        let biscuit3 = biscuit2
+            .append_with_rng(&mut rng, &keypair3, biscuit2.create_block())
+            .unwrap();
+        assert_eq!(biscuit3.sign_request(&b"GET /file1"[..]).unwrap_err(), Token::MissingProofKey);
+
+        let verifier = biscuit3.verify(root.public()).unwrap();
+        assert_eq!(verifier.verify_possession(&b"GET /file1"[..], &proof), Ok(()));
+
+        // someone holding a stolen token cannot pin their own key
+        let attacker = KeyPair::new_with_rng(&mut rng);
+        assert_eq!(
+            biscuit2
+                .append_with_proof_key(KeyPair::new_with_rng(&mut rng), biscuit2.create_block())
+                .unwrap_err(),
+            Token::ProofKeyAlreadyPinned
+        );
+
+        let mut block = biscuit2.create_block();
+        block.proof_key(&attacker.public());
+        let stolen = biscuit2.append_with_rng(&mut rng, &attacker, block).unwrap();
+        let stolen = Biscuit::from(&stolen.to_vec().unwrap()).unwrap();
+        let forged = attacker.sign_possession(&mut rng, &b"GET /file1"[..]);
+
+        let verifier = stolen.verify(root.public()).unwrap();
+        assert_eq!(
+            verifier.verify_possession(&b"GET /file1"[..], &forged),
+            Err(Token::InvalidPossessionProof)
+        );
+        assert_eq!(verifier.verify_possession(&b"GET /file1"[..], &proof), Ok(()));
+
+        let verifier = Verifier::new().unwrap();
+        assert_eq!(
+            verifier.verify_possession(&b"GET /file1"[..], &proof),
+            Err(Token::MissingProofKey)
+        );
+    }
+
+    #[test]
+    fn authority_proof_key() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let client = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        builder.proof_key(&client.public());
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let proof = client.sign_possession(&mut rng, &b"GET /file1"[..]);
+        let verifier = biscuit1.verify(root.public()).unwrap();
+        assert_eq!(verifier.verify_possession(&b"GET /file1"[..], &proof), Ok(()));
+
+        // the token cannot be rebound, even by an append with a proof key
+        let attacker = KeyPair::new_with_rng(&mut rng);
+        assert_eq!(
+            biscuit1.append_with_proof_key(attacker, biscuit1.create_block()).unwrap_err(),
+            Token::ProofKeyAlreadyPinned
+        );
+
+        let attacker = KeyPair::new_with_rng(&mut rng);
+        let mut block = biscuit1.create_block();
+        block.proof_key(&attacker.public());
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &attacker, block).unwrap();
+        let forged = biscuit2.sign_request_with_rng(&mut rng, &b"GET /file1"[..]);
+        assert_eq!(forged.unwrap_err(), Token::MissingProofKey);
+
+        let forged = attacker.sign_possession(&mut rng, &b"GET /file1"[..]);
+        let verifier = biscuit2.verify(root.public()).unwrap();
+        assert_eq!(
+            verifier.verify_possession(&b"GET /file1"[..], &forged),
+            Err(Token::InvalidPossessionProof)
+        );
+    }
+
+    #[test]
+    fn ed25519_format() {
+        use crate::crypto::ed25519;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = ed25519::KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder_ed25519(&root);
+        builder.add_right("file1", "read");
+        builder.add_right("file2", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        let serialized1 = biscuit1.to_vec().unwrap();
+
+        // the format is detected when deserializing
+        let biscuit1_deser = Biscuit::from(&serialized1).unwrap();
+        assert!(biscuit1_deser.container().is_none());
+        assert!(biscuit1_deser.ed25519_container().is_some());
+
+        let mut block2 = biscuit1_deser.create_block();
+        block2.check_resource("file1");
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1_deser.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+        let serialized2 = biscuit2.to_vec().unwrap();
+        let biscuit2_deser = Biscuit::from(&serialized2).unwrap();
+        assert_eq!(biscuit2_deser.ed25519_container().unwrap().blocks.len(), 1);
+
+        assert_eq!(
+            biscuit2_deser.check_root_key(KeyPair::new_with_rng(&mut rng).public()),
+            Err(Token::Format(Format::UnknownPublicKey))
+        );
+        assert_eq!(
+            biscuit2_deser.check_root_key_ed25519(ed25519::KeyPair::new_with_rng(&mut rng).public()),
+            Err(Token::Format(Format::UnknownPublicKey))
+        );
+
+        let mut verifier = biscuit2_deser.verify_ed25519(root.public()).unwrap();
+        verifier.add_resource("file1");
+        verifier.add_operation("read");
+        verifier.add_caveat("right(#right) <- right(#authority, \"file1\", #read)").unwrap();
+        assert_eq!(verifier.verify(), Ok(()));
+
+        let mut verifier = biscuit2_deser.verify_ed25519(root.public()).unwrap();
+        verifier.add_resource("file2");
+        verifier.add_operation("read");
+        assert!(verifier.verify().is_err());
+
+        // removing the last block does not match the proof
+        let mut container = biscuit2_deser.ed25519_container().unwrap().clone();
+        container.blocks.pop();
+        assert_eq!(
+            Biscuit::from(&container.to_vec().unwrap()).unwrap_err(),
+            Token::Format(Format::Signature(Signature::InvalidSignature))
+        );
+
+        // modifying a block invalidates the signature chain
+        let mut container = biscuit2_deser.ed25519_container().unwrap().clone();
+        container.authority.data = container.blocks[0].data.clone();
+        assert_eq!(
+            Biscuit::from(&container.to_vec().unwrap()).unwrap_err(),
+            Token::Format(Format::Signature(Signature::InvalidSignature))
+        );
+
+        // tokens in the aggregated format are still detected
+        let root2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit3 = Biscuit::builder(&root2).build_with_rng(&mut rng).unwrap();
+        let biscuit3_deser = Biscuit::from(&biscuit3.to_vec().unwrap()).unwrap();
+        assert!(biscuit3_deser.container().is_some());
+        biscuit3_deser.check_root_key(root2.public()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remote_signer() {
+        use crate::crypto::{self, CryptoRngCore};
+        use std::io::{Read, Write};
+        use std::os::unix::net::UnixStream;
+        use std::sync::Mutex;
+
+        // signs the messages received on the socket, the private key
+        // never leaves this thread
+        fn serve(mut stream: UnixStream, keypair: KeyPair) {
+            let mut rng: StdRng = SeedableRng::seed_from_u64(1);
+            stream.write_all(&keypair.public().to_bytes()).unwrap();
+
+            let mut len = [0u8; 4];
+            while stream.read_exact(&mut len).is_ok() {
+                let mut message = vec![0u8; u32::from_be_bytes(len) as usize];
+                stream.read_exact(&mut message).unwrap();
+
+                let signature = Signer::sign(&keypair, &mut rng, &message).unwrap();
+                stream.write_all(&signature.to_bytes()).unwrap();
+            }
+        }
+
+        struct RemoteSigner {
+            stream: Mutex<UnixStream>,
+            public: PublicKey,
+        }
+
+        impl RemoteSigner {
+            fn connect(mut stream: UnixStream) -> Self {
+                let mut public = [0u8; 32];
+                stream.read_exact(&mut public).unwrap();
+
+                RemoteSigner {
+                    stream: Mutex::new(stream),
+                    public: PublicKey::from_bytes(&public).unwrap(),
+                }
+            }
+        }
+
+        impl Signer for RemoteSigner {
+            fn public_key(&self) -> PublicKey {
+                self.public
+            }
+
+            fn sign(&self, _rng: &mut dyn CryptoRngCore, message: &[u8]) -> Result<crypto::Signature, Signature> {
+                let mut stream = self.stream.lock().unwrap();
+                let io = |e: std::io::Error| Signature::Signer(e.to_string());
+
+                stream.write_all(&(message.len() as u32).to_be_bytes()).map_err(io)?;
+                stream.write_all(message).map_err(io)?;
+
+                let mut signature = [0u8; 64];
+                stream.read_exact(&mut signature).map_err(io)?;
+                crypto::Signature::from_bytes(&signature).ok_or(Signature::InvalidFormat)
+            }
+        }
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let root_public = root.public();
+
+        let (client, server) = UnixStream::pair().unwrap();
+        let handle = std::thread::spawn(move || serve(server, root));
+        let signer = RemoteSigner::connect(client);
+
+        let mut builder = Biscuit::builder(&signer);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let biscuit1_deser = Biscuit::from(&biscuit1.to_vec().unwrap()).unwrap();
+        let mut verifier = biscuit1_deser.verify(root_public).unwrap();
+        verifier.add_resource("file1");
+        verifier.add_operation("read");
+        verifier.add_caveat("right(#right) <- right(#authority, \"file1\", #read)").unwrap();
+        assert_eq!(verifier.verify(), Ok(()));
+
+        // the signing service is not available anymore
+        drop(signer.stream.lock().unwrap().shutdown(std::net::Shutdown::Both));
+        handle.join().unwrap();
+
+        let res = Biscuit::builder(&signer).build_with_rng(&mut rng);
+        match res {
+            Err(Token::Format(Format::Signature(Signature::Signer(_)))) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn legacy_signature() {
+        use crate::crypto::{TokenSignature, LEGACY_SIGNATURE_VERSION};
+        use crate::format::convert::token_block_to_proto_block;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        // recreate the same token with the legacy signature scheme
+        let mut authority = Vec::new();
+        token_block_to_proto_block(&biscuit1.authority).encode(&mut authority).unwrap();
+        let signature = TokenSignature::new_with_version(&mut rng, LEGACY_SIGNATURE_VERSION, &root, &authority);
+        let legacy = SerializedBiscuit {
+            authority,
+            blocks: vec![],
+            keys: vec![root.public()],
+            signature,
+        }.to_vec().unwrap();
+
+        assert_eq!(
+            Biscuit::from(&legacy).unwrap_err(),
+            Token::Format(Format::LegacySignature)
+        );
+
+        // decoding without the legacy opt-in, then checking signatures in a batch
+        let decoded = SerializedBiscuit::decode(&legacy);
+        assert_eq!(
+            decoded.and_then(|t| SerializedBiscuit::verify_batch(&mut rng, &[&t])),
+            Err(Format::LegacySignature)
+        );
+        let decoded = SerializedBiscuit::decode_with_legacy(&legacy).unwrap();
+        assert_eq!(SerializedBiscuit::verify_batch(&mut rng, &[&decoded]), Ok(()));
+
+        let biscuit2 = Biscuit::from_legacy(&legacy).unwrap();
+        let mut block2 = biscuit2.create_block();
+        block2.check_operation("read");
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit3 = biscuit2.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+
+        // appending to a legacy token keeps the legacy scheme
+        let serialized3 = biscuit3.to_vec().unwrap();
+        assert_eq!(
+            Biscuit::from(&serialized3).unwrap_err(),
+            Token::Format(Format::LegacySignature)
+        );
+        let biscuit3 = Biscuit::from_legacy(&serialized3).unwrap();
+
+        let mut verifier = biscuit3.verify(root.public()).unwrap();
+        verifier.add_resource("file1");
+        verifier.add_operation("read");
+        verifier.add_caveat("right(#right) <- right(#authority, \"file1\", #read)").unwrap();
+        assert_eq!(verifier.verify(), Ok(()));
+
+        // new tokens are accepted by both functions
+        let serialized1 = biscuit1.to_vec().unwrap();
+        Biscuit::from(&serialized1).unwrap();
+        Biscuit::from_legacy(&serialized1).unwrap();
+    }
+
+    #[test]
+    fn sealed_token() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+
+        builder.add_right("/folder1/file1", "read");
+        builder.add_right("/folder1/file1", "write");
+        builder.add_right("/folder1/file2", "read");
+        builder.add_right("/folder1/file2", "write");
+        builder.add_right("/folder2/file3", "read");
+
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        println!("biscuit1 (authority): {}", biscuit1.print());
+
+        let mut block2 = biscuit1.create_block();
+
+        block2.resource_prefix("/folder1/");
+        block2.check_right("read");
+
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1
+            .append_with_rng(&mut rng, &keypair2, block2)
+            .unwrap();
+
+        //println!("biscuit2:\n{:#?}", biscuit2);
+        //panic!();
+        {
+            let mut verifier = biscuit2.verify(root.public()).unwrap();
+            verifier.add_resource("/folder1/file1");
+            verifier.add_operation("read");
+
+            let res = verifier.verify();
+            println!("res1: {:?}", res);
+            res.unwrap();
+        }
+
+        let _serialized = biscuit2.to_vec().unwrap();
+        //println!("biscuit2 serialized ({} bytes):\n{}", serialized.len(), serialized.to_hex(16));
+
+        let secret = b"secret key";
+        let sealed = biscuit2.seal(&secret[..]).unwrap();
+        //println!("biscuit2 sealed ({} bytes):\n{}", sealed.len(), sealed.to_hex(16));
+
+        let biscuit3 = Biscuit::from_sealed(&sealed, &secret[..]).unwrap();
+
+        {
+            let mut verifier = biscuit3.verify_sealed().unwrap();
+            verifier.add_resource("/folder1/file1");
+            verifier.add_operation("read");
+
+            let res = verifier.verify();
+            println!("res1: {:?}", res);
+            res.unwrap();
+        }
+    }
+
+    #[test]
+    fn schema_versions() {
+        use crate::format::convert::{proto_block_to_token_block, token_block_to_proto_block};
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("/folder1/file1", "read");
+        builder.add_right("/folder2/file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.authority.version, 0);
+
+        let mut block2 = biscuit1.create_block();
+        block2.add_caveat("contains($0) <- resource(#ambient, $0) @ $0 contains \"folder1\"").unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+        assert_eq!(biscuit2.blocks[0].version, 1);
+
+        let biscuit2 = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+        assert_eq!(biscuit2.authority.version, 0);
+        assert_eq!(biscuit2.blocks[0].version, 1);
+
+        for (resource, valid) in [("/folder1/file1", true), ("/folder2/file1", false)].iter() {
+            let mut verifier = biscuit2.verify(root.public()).unwrap();
+            verifier.add_resource(resource);
+            verifier.add_operation("read");
+            assert_eq!(verifier.verify().is_ok(), *valid);
+        }
+
+        // a block must not declare a lower version than its content needs
+        let mut proto = token_block_to_proto_block(&biscuit2.blocks[0]);
+        proto.version = Some(0);
+        assert!(proto_block_to_token_block(&proto).is_err());
+
+        proto.version = Some(4);
+        assert_eq!(
+            proto_block_to_token_block(&proto).unwrap_err(),
+            error::Format::Version { maximum: 3, actual: 4 }
+        );
+    }
+
+    #[test]
+    fn client_ip() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut block2 = biscuit1.create_block();
+        block2.add_fact("office(cidr:10.0.0.0/8)").unwrap();
+        block2
+            .add_caveat("office_network($0) <- client_ip(#ambient, $0) @ $0 in cidr:10.0.0.0/8")
+            .unwrap();
+        block2
+            .add_caveat("not_blocked($0) <- client_ip(#ambient, $0) @ $0 not in [ip:10.0.0.66]")
+            .unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+        assert_eq!(biscuit2.blocks[0].version, 2);
+
+        let biscuit2 = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+        assert_eq!(biscuit2.authority.version, 0);
+        assert_eq!(biscuit2.blocks[0].version, 2);
+
+        let limits = verifier::VerifierLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        // IPv4-mapped addresses are reported by dual stack sockets
+        let clients = [
+            ("10.1.2.3", true),
+            ("10.0.0.66", false),
+            ("192.168.0.1", false),
+            ("::1", false),
+            ("::ffff:10.1.2.3", true),
+            ("::ffff:10.0.0.66", false),
+        ];
+        for (client, valid) in clients.iter() {
+            let mut verifier = biscuit2.verify(root.public()).unwrap();
+            verifier.add_resource("file1");
+            verifier.add_operation("read");
+            verifier.add_client_ip(client.parse().unwrap());
+            let res = verifier.verify_with_limits(limits.clone());
+            println!("{}: {:?}", client, res);
+            assert_eq!(res.is_ok(), *valid);
+        }
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_client_ip("10.1.2.3".parse().unwrap());
+        let res = verifier
+            .query_with_limits("trusted(#ok) <- client_ip(#ambient, ip:10.1.2.3), office(cidr:10.0.0.0/8)", limits.clone())
+            .unwrap();
+        assert_eq!(res, vec![fact("trusted", &[s("ok")])]);
+
+        let res = verifier
+            .query_with_limits("network($0) <- office($0)", limits)
+            .unwrap();
+        assert_eq!(
+            res,
+            vec![fact("network", &[builder::network("10.0.0.0/8".parse().unwrap())])]
+        );
+    }
+
+    #[test]
+    fn resource_under() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder
+            .add_authority_rule("right(#authority, $0, #read) <- resource(#ambient, $0) @ $0 glob \"/files/*/read\"")
+            .unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+        assert_eq!(biscuit1.authority.version, 3);
+
+        let mut block2 = biscuit1.create_block();
+        block2.resource_under("/files/team");
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+        assert_eq!(biscuit2.blocks[0].version, 3);
+
+        let biscuit2 = Biscuit::from(&biscuit2.to_vec().unwrap()).unwrap();
+        println!("{}", biscuit2.print());
+
+        let limits = verifier::VerifierLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let cases = [
+            ("/files/team/read", true),
+            ("/files/teams/read", false),
+            ("/files/team", false),
+            ("/files/other/read", false),
+        ];
+        for (resource, valid) in cases.iter() {
+            let mut verifier = biscuit2.verify(root.public()).unwrap();
+            verifier.add_resource(resource);
+            verifier.add_operation("read");
+            verifier.add_caveat("read_right($0) <- resource(#ambient, $0), right(#authority, $0, #read)").unwrap();
+            let res = verifier.verify_with_limits(limits.clone());
+            println!("{}: {:?}", resource, res);
+            assert_eq!(res.is_ok(), *valid);
+        }
+    }
+
+    #[test]
+    fn encrypted_sealed_token() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+
+        builder.add_right("/folder1/internal_user_1234", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let secret = b"secret key";
+        let clear = biscuit1.seal(&secret[..]).unwrap();
+        let sealed = biscuit1.seal_encrypted_with_rng(&mut rng, &secret[..]).unwrap();
+
+        let needle = &b"internal_user_1234"[..];
+        assert!(clear.windows(needle.len()).any(|w| w == needle));
+        assert!(!sealed.windows(needle.len()).any(|w| w == needle));
+
+        let biscuit2 = Biscuit::from_sealed(&sealed, &secret[..]).unwrap();
+        {
+            let mut verifier = biscuit2.verify_sealed().unwrap();
+            verifier.add_resource("/folder1/internal_user_1234");
+            verifier.add_operation("read");
+            verifier.verify().unwrap();
+        }
+
+        assert_eq!(
+            Biscuit::from_sealed(&sealed, &b"other secret"[..]).unwrap_err(),
+            Token::Format(error::Format::SealedSignature)
+        );
+
+        let mut tampered = sealed.clone();
+        let last = tampered.len() - 40;
+        tampered[last] ^= 1;
+        assert!(Biscuit::from_sealed(&tampered, &secret[..]).is_err());
+    }
+
+    #[test]
+    fn sealing_keyring() {
+        use super::sealed::{SealedBiscuit, SealingKeyring};
+        use std::time::Duration;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut keyring = SealingKeyring::new(1, &b"secret 1"[..]);
+        let legacy = biscuit1.seal(&b"secret 1"[..]).unwrap();
+        let sealed1 = biscuit1.seal_with_keyring(&keyring).unwrap();
+        let encrypted1 = biscuit1.seal_encrypted_with_keyring_with_rng(&mut rng, &keyring).unwrap();
+
+        let now = SystemTime::now();
+        keyring.rotate(2, &b"secret 2"[..], now + Duration::from_secs(3600));
+        let sealed2 = biscuit1.seal_with_keyring(&keyring).unwrap();
+        assert_eq!(SealedBiscuit::from_slice(&sealed2, &b"secret 2"[..]).unwrap().key_id, Some(2));
+
+        // tokens sealed with the previous key are accepted until it expires
+        for token in [&sealed1, &encrypted1].iter() {
+            Biscuit::from_sealed_with_keyring(token, &keyring).unwrap();
+            assert_eq!(
+                SealedBiscuit::from_slice_with_keyring(token, &keyring, now + Duration::from_secs(7200)).unwrap_err(),
+                error::Format::UnknownSealingKey(Some(1))
+            );
+        }
+        Biscuit::from_sealed_with_keyring(&sealed2, &keyring).unwrap();
+
+        // tokens without a key id are checked with the current key
+        assert_eq!(
+            Biscuit::from_sealed_with_keyring(&legacy, &keyring).unwrap_err(),
+            Token::Format(error::Format::SealedSignature)
+        );
+
+        keyring.remove_expired(now + Duration::from_secs(7200));
+        assert_eq!(keyring.keys().len(), 1);
+        assert_eq!(keyring.current().unwrap().id, 2);
+        assert_eq!(
+            Biscuit::from_sealed_with_keyring(&sealed1, &keyring).unwrap_err(),
+            Token::Format(error::Format::UnknownSealingKey(Some(1)))
+        );
+    }
+
+    #[test]
+    fn base64_encoding() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let mut builder = Biscuit::builder(&root);
+        builder.add_right("file1", "read");
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let encoded = biscuit1.to_base64().unwrap();
+        assert!(!encoded.contains(&['=', '+', '/'][..]));
+        assert_eq!(Biscuit::from_base64(&encoded).unwrap().print(), biscuit1.print());
+
+        let padded = base64::encode_config(biscuit1.to_vec().unwrap(), base64::URL_SAFE);
+        Biscuit::from_base64(&padded).unwrap();
+        // the standard alphabet is rejected: [0xfb, 0xff] is "+/8" instead of "-_8"
+        assert!(matches!(
+            Biscuit::from_base64("+/8="),
+            Err(Token::Format(error::Format::DeserializationError(e))) if e.contains("invalid base64")
+        ));
+        assert!(matches!(
+            Biscuit::from_base64("-_8="),
+            Err(Token::Format(error::Format::DeserializationError(e))) if !e.contains("invalid base64")
+        ));
+        assert!(Biscuit::from_base64(&format!("{}===", encoded)).is_err());
+
+        let sealed = biscuit1.seal_base64(&b"secret"[..]).unwrap();
+        Biscuit::from_sealed_base64(&sealed, &b"secret"[..]).unwrap();
+        assert_eq!(
+            Biscuit::from_sealed_base64(&sealed, &b"other secret"[..]).unwrap_err(),
+            Token::Format(error::Format::SealedSignature)
+        );
+
+        assert_eq!(bearer_token(&format!("Bearer {}", encoded)), Some(&encoded[..]));
+        assert_eq!(bearer_token(&format!("  bearer   {} ", encoded)), Some(&encoded[..]));
+        assert_eq!(bearer_token(&format!("Basic {}", encoded)), None);
+        assert_eq!(bearer_token("Bearer"), None);
+        assert_eq!(bearer_token("Bearer a b"), None);
+        Biscuit::from_bearer(&format!("Bearer {}", encoded)).unwrap();
+        assert!(Biscuit::from_bearer(&encoded).is_err());
+    }
+
+    #[test]
+    fn verif_no_blocks() {
+      use crate::token::builder::*;
+
+      let mut rng: StdRng = SeedableRng::seed_from_u64(1234);
       let root = KeyPair::new_with_rng(&mut rng);
 
       let mut builder = Biscuit::builder(&root);
@@ -1141,6 +2159,285 @@ mod tests {
         }
     }
 
+    #[test]
+    fn verifier_template() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(#authority, \"alice\")").unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut template = verifier::VerifierTemplate::new();
+        template.add_fact("owner(\"alice\", \"file1\")").unwrap();
+        template.add_fact("owner(\"bob\", \"file2\")").unwrap();
+        template
+            .add_rule("can_read($file) <- user(#authority, $user), owner($user, $file)")
+            .unwrap();
+        template
+            .add_caveat("allowed($file) <- can_read($file), resource(#ambient, $file)")
+            .unwrap();
+
+        let mut verifier = template.verifier_for(&biscuit1, root.public()).unwrap();
+        verifier.add_resource("file1");
+        verifier.verify().unwrap();
+
+        // facts of the previous request are not visible
+        let mut verifier = template.verifier_for(&biscuit1, root.public()).unwrap();
+        verifier.add_resource("file2");
+        let res = verifier.verify();
+        println!("res: {:?}", res);
+        assert!(res.is_err());
+
+        let mut verifier = template.verifier();
+        verifier.add_fact("user(#authority, \"bob\")").unwrap();
+        verifier.add_resource("file2");
+        verifier.verify().unwrap();
+
+        let other_root = KeyPair::new_with_rng(&mut rng);
+        assert!(template.verifier_for(&biscuit1, other_root.public()).is_err());
+    }
+
+    #[test]
+    fn scoped_tokens() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("right(#authority, \"file1\", #read)").unwrap();
+        let user = builder.build_with_rng(&mut rng).unwrap();
+        let mut block = user.create_block();
+        // would succeed with the facts of the service token
+        block.add_caveat("caveat1($0) <- resource(#ambient, $0), right(#authority, $0, #write)").unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let user2 = user.append_with_rng(&mut rng, &keypair2, block).unwrap();
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("service(#authority, \"billing\")").unwrap();
+        builder.add_authority_fact("right(#authority, \"file1\", #write)").unwrap();
+        let service = builder.build_with_rng(&mut rng).unwrap();
+
+        let limits = || verifier::VerifierLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_scoped_token("user", &user).unwrap();
+        verifier.add_scoped_token("service", &service).unwrap();
+        verifier.add_resource("file1");
+        verifier.add_caveat(
+            "allowed($0) <- resource(#ambient, $0), right(#user, #authority, $0, #read), service(#service, #authority, \"billing\")"
+        ).unwrap();
+        verifier.verify_with_limits(limits()).unwrap();
+
+        let res = verifier.query_with_limits(
+            "data($0) <- right(#service, #authority, $0, #write)",
+            limits(),
+        ).unwrap();
+        assert_eq!(res, vec![fact("data", &[builder::string("file1")])]);
+
+        let mut verifier = Verifier::new().unwrap();
+        verifier.add_scoped_token("user", &user2).unwrap();
+        verifier.add_scoped_token("service", &service).unwrap();
+        verifier.add_resource("file1");
+        let res = verifier.verify_with_limits(limits());
+        println!("res: {:?}", res);
+        assert_eq!(res,
+            Err(Token::FailedLogic(Logic::FailedCaveats(vec![
+                FailedCaveat::Token(FailedTokenCaveat {
+                    scope: "user".to_string(),
+                    block_id: 1,
+                    caveat_id: 0,
+                    rule: String::from("caveat1($0) <- resource(#ambient, $0), right(#authority, $0, #write)"),
+                }),
+            ]))));
+
+        assert_eq!(verifier.add_scoped_token("user", &user), Err(Logic::InvalidScope("user".to_string())));
+        assert_eq!(verifier.add_scoped_token("ambient", &user), Err(Logic::InvalidScope("ambient".to_string())));
+        assert_eq!(verifier.add_token(&user), Err(Logic::VerifierNotEmpty));
+
+        let mut verifier = user.verify(root.public()).unwrap();
+        assert_eq!(verifier.add_scoped_token("service", &service), Err(Logic::VerifierNotEmpty));
+    }
+
+    #[test]
+    fn fact_providers() {
+        use super::provider::{FactProvider, MemoryFactProvider};
+        use std::sync::Arc;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(#authority, \"alice\")").unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let members = Arc::new(MemoryFactProvider::new(vec![
+            fact("member", &[builder::string("alice"), builder::string("admins")]),
+            fact("member", &[builder::string("bob"), builder::string("devs")]),
+            fact("role", &[builder::string("admins"), s("superuser")]),
+        ]));
+
+        let limits = || verifier::VerifierLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let mut verifier = biscuit1.verify(root.public()).unwrap();
+        verifier.add_fact_provider("member", members.clone());
+        verifier.add_fact_provider("role", members.clone());
+        verifier.add_rule("group($group) <- user(#authority, $user), member($user, $group)").unwrap();
+        verifier.add_caveat("allowed($group) <- group($group), resource(#ambient, $group)").unwrap();
+        verifier.add_caveat("allowed($group) <- user(#authority, $user), member($user, $group)").unwrap();
+        verifier.add_resource("admins");
+        verifier.verify_with_limits(limits()).unwrap();
+        // member("alice", $group) is looked up once, then cached
+        assert_eq!(members.lookups(), 1);
+
+        // symbols returned by providers are added to the verifier
+        let res = verifier.query_with_limits(
+            "roles($role) <- group($group), role($group, $role)",
+            limits(),
+        ).unwrap();
+        assert_eq!(res, vec![fact("roles", &[s("superuser")])]);
+
+        let mut verifier = biscuit1.verify(root.public()).unwrap();
+        verifier.add_fact_provider("member", members.clone());
+        verifier.add_resource("devs");
+        verifier.add_caveat("allowed($group) <- user(#authority, $user), member($user, $group), resource(#ambient, $group)").unwrap();
+        assert!(verifier.verify_with_limits(limits()).is_err());
+        assert_eq!(members.lookups(), 4);
+
+        struct FailingProvider;
+        impl FactProvider for FailingProvider {
+            fn facts(&self, _predicate: &builder::Predicate) -> Result<Vec<builder::Fact>, Token> {
+                Err(Token::FactProvider("unavailable".to_string()))
+            }
+        }
+
+        let mut verifier = biscuit1.verify(root.public()).unwrap();
+        verifier.add_fact_provider("member", Arc::new(FailingProvider));
+        verifier.add_caveat("allowed($group) <- user(#authority, $user), member($user, $group)").unwrap();
+        assert_eq!(
+            verifier.verify_with_limits(limits()),
+            Err(Token::FactProvider("unavailable".to_string()))
+        );
+    }
+
+    #[test]
+    fn fact_provider_unbound_query() {
+        use super::provider::{require_bound, FactProvider, MemoryFactProvider};
+        use std::sync::Arc;
+
+        // only answers queries for a known user
+        struct UserMembers(MemoryFactProvider);
+        impl FactProvider for UserMembers {
+            fn facts(&self, predicate: &builder::Predicate) -> Result<Vec<builder::Fact>, Token> {
+                require_bound(predicate, &[0])?;
+                self.0.facts(predicate)
+            }
+        }
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_fact("user(#authority, \"alice\")").unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        // the token holder adds a rule enumerating all the members
+        let mut block2 = biscuit1.create_block();
+        block2.add_rule("everyone($user, $group) <- member($user, $group)").unwrap();
+        let keypair2 = KeyPair::new_with_rng(&mut rng);
+        let biscuit2 = biscuit1.append_with_rng(&mut rng, &keypair2, block2).unwrap();
+
+        let limits = || verifier::VerifierLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let members = Arc::new(UserMembers(MemoryFactProvider::new(vec![
+            fact("member", &[builder::string("alice"), builder::string("admins")]),
+        ])));
+
+        let mut verifier = biscuit1.verify(root.public()).unwrap();
+        verifier.add_fact_provider("member", members.clone());
+        verifier.add_caveat("allowed($group) <- user(#authority, $user), member($user, $group)").unwrap();
+        verifier.verify_with_limits(limits()).unwrap();
+        assert_eq!(members.0.lookups(), 1);
+
+        let mut verifier = biscuit2.verify(root.public()).unwrap();
+        verifier.add_fact_provider("member", members.clone());
+        verifier.add_caveat("allowed($group) <- user(#authority, $user), member($user, $group)").unwrap();
+        assert_eq!(
+            verifier.verify_with_limits(limits()),
+            Err(Token::FactProvider("term 0 of member must be bound".to_string()))
+        );
+        // only the bound query reached the backing store
+        assert_eq!(members.0.lookups(), 2);
+    }
+
+    #[test]
+    fn builtins() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_caveat("caveat1($0) <- count(#ambient, $0), @even($0)").unwrap();
+        // the builtin is evaluated after the other predicates
+        builder.add_authority_caveat("caveat2($1) <- @double($0, $1), count(#ambient, $0) @ $1 == 8").unwrap();
+        let biscuit1 = builder.build_with_rng(&mut rng).unwrap();
+
+        let mut builder = Biscuit::builder(&root);
+        builder.add_authority_caveat("caveat1($0) <- count(#ambient, $0), @unknown($0)").unwrap();
+        let biscuit2 = builder.build_with_rng(&mut rng).unwrap();
+
+        let limits = || verifier::VerifierLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let verifier_with = |token: &Biscuit, count: i64| {
+            let mut verifier = token.verify(root.public()).unwrap();
+            verifier.add_builtin("even", |ids| match ids {
+                [builder::Term::Integer(i)] if i % 2 == 0 => vec![ids.to_vec()],
+                _ => vec![],
+            });
+            verifier.add_builtin("double", |ids| match ids {
+                [builder::Term::Integer(i), _] => vec![vec![int(*i), int(2 * i)]],
+                _ => vec![],
+            });
+            verifier.add_fact(fact("count", &[s("ambient"), int(count)])).unwrap();
+            verifier
+        };
+
+        verifier_with(&biscuit1, 4).verify_with_limits(limits()).unwrap();
+
+        let res = verifier_with(&biscuit1, 3).verify_with_limits(limits());
+        println!("res: {:?}", res);
+        assert_eq!(res,
+            Err(Token::FailedLogic(Logic::FailedCaveats(vec![
+                FailedCaveat::Block(FailedBlockCaveat {
+                    block_id: 0,
+                    caveat_id: 0,
+                    rule: String::from("caveat1($0) <- count(#ambient, $0), @even($0)"),
+                }),
+                FailedCaveat::Block(FailedBlockCaveat {
+                    block_id: 0,
+                    caveat_id: 1,
+                    rule: String::from("caveat2($1) <- count(#ambient, $0), @double($0, $1) @ $1 == 8"),
+                }),
+            ]))));
+
+        // facts cannot provide builtin predicates
+        let mut verifier = verifier_with(&biscuit1, 3);
+        verifier.add_fact(fact("@even", &[int(3)])).unwrap();
+        assert!(verifier.verify_with_limits(limits()).is_err());
+
+        let res = verifier_with(&biscuit2, 4).verify_with_limits(limits());
+        assert_eq!(res, Err(Token::FailedLogic(Logic::UnknownBuiltin("@unknown".to_string()))));
+    }
+
     #[test]
     fn caveat_head_name() {
         let mut rng: StdRng = SeedableRng::seed_from_u64(0);
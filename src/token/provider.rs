@@ -0,0 +1,96 @@
+//! fact providers queried by the verifier during evaluation
+//!
+//! Some data is too large to be loaded in a [`Verifier`](`crate::token::verifier::Verifier`)
+//! before each verification, like permissions stored in a database. A [`FactProvider`]
+//! registered with [`Verifier::add_fact_provider`](`crate::token::verifier::Verifier::add_fact_provider`)
+//! for a predicate name is asked for the facts of that predicate when a rule or caveat
+//! needs them, with the terms already bound by the rule: in
+//! `allowed($group) <- user(#authority, $user), member($user, $group)`, once
+//! `$user` is found, the provider of `member` receives `member("alice", $group)`.
+//!
+//! Results are cached for the duration of a verification. Providers can be created
+//! for each request, to look up data referenced by the token's
+//! [`context`](`crate::token::Biscuit::context`) for example.
+//!
+//! Providers are queried by every rule and caveat, including those of the token's
+//! blocks, which are written by the token holder: a block containing
+//! `x($user, $group) <- member($user, $group)` sends `member($user, $group)`, with
+//! no bound term, to the provider on every verification. Providers backed by
+//! expensive sources should reject such queries with [`require_bound`].
+use super::builder::{Fact, Predicate, Term};
+use crate::error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// a source of facts for a predicate name
+pub trait FactProvider {
+    /// returns the facts matching the predicate
+    ///
+    /// terms that are not bound yet are variables. Returned facts that do not
+    /// match the predicate, or that contain variables, are ignored.
+    ///
+    /// An error fails the verification, which is how a provider refuses a query,
+    /// like one with unbound terms coming from a rule of the token
+    fn facts(&self, predicate: &Predicate) -> Result<Vec<Fact>, error::Token>;
+}
+
+/// checks that the terms at the given positions of a query are bound
+///
+/// returns an [`error::Token::FactProvider`] error otherwise, to be returned
+/// by [`FactProvider::facts`]
+pub fn require_bound(predicate: &Predicate, positions: &[usize]) -> Result<(), error::Token> {
+    for position in positions {
+        match predicate.ids.get(*position) {
+            Some(Term::Variable(_)) | None => {
+                return Err(error::Token::FactProvider(format!(
+                    "term {} of {} must be bound",
+                    position, predicate.name
+                )))
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// facts stored in memory, to test policies relying on providers
+#[derive(Debug, Default)]
+pub struct MemoryFactProvider {
+    facts: Vec<Fact>,
+    lookups: AtomicUsize,
+}
+
+impl MemoryFactProvider {
+    pub fn new(facts: Vec<Fact>) -> Self {
+        MemoryFactProvider {
+            facts,
+            lookups: AtomicUsize::new(0),
+        }
+    }
+
+    /// number of times the provider was queried
+    pub fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::Relaxed)
+    }
+}
+
+impl FactProvider for MemoryFactProvider {
+    fn facts(&self, predicate: &Predicate) -> Result<Vec<Fact>, error::Token> {
+        self.lookups.fetch_add(1, Ordering::Relaxed);
+
+        Ok(self
+            .facts
+            .iter()
+            .filter(|f| {
+                f.0.name == predicate.name
+                    && f.0.ids.len() == predicate.ids.len()
+                    && f.0
+                        .ids
+                        .iter()
+                        .zip(predicate.ids.iter())
+                        .all(|(id, query)| matches!(query, Term::Variable(_)) || id == query)
+            })
+            .cloned()
+            .collect())
+    }
+}
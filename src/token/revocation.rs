@@ -0,0 +1,243 @@
+//! revocation stores queried by the verifier
+//!
+//! Blocks can carry `revocation_id(<integer>)` facts (see
+//! [`BlockBuilder::revocation_id`](`crate::token::builder::BlockBuilder::revocation_id`)).
+//! A [`RevocationStore`] registered with
+//! [`Verifier::set_revocation_store`](`crate::token::verifier::Verifier::set_revocation_store`)
+//! is asked about each of those identifiers during verification, and the token
+//! is rejected with [`error::Token::Revoked`] if any of them was revoked.
+use crate::error;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::Mutex,
+};
+
+/// a list of revoked token identifiers
+pub trait RevocationStore {
+    /// returns true if this revocation id was revoked
+    fn is_revoked(&self, id: i64) -> Result<bool, error::Token>;
+}
+
+/// in memory revocation list
+impl RevocationStore for HashSet<i64> {
+    fn is_revoked(&self, id: i64) -> Result<bool, error::Token> {
+        Ok(self.contains(&id))
+    }
+}
+
+/// revocation list stored in a file, that is not loaded in memory
+///
+/// The file contains the revoked ids as 8 bytes big endian integers,
+/// sorted in increasing order. Lookups are done by binary search
+/// directly in the file.
+pub struct SortedFileRevocationStore {
+    file: Mutex<File>,
+    count: u64,
+}
+
+impl SortedFileRevocationStore {
+    /// opens an existing revocation list
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, error::Token> {
+        let file = File::open(path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+
+        if len % 8 != 0 {
+            return Err(error::Token::RevocationStore(format!(
+                "invalid revocation list size: {} bytes",
+                len
+            )));
+        }
+
+        Ok(SortedFileRevocationStore {
+            file: Mutex::new(file),
+            count: len / 8,
+        })
+    }
+
+    /// writes a revocation list in the format expected by [`SortedFileRevocationStore::open`]
+    pub fn write<P: AsRef<Path>, I: IntoIterator<Item = i64>>(
+        path: P,
+        ids: I,
+    ) -> Result<(), error::Token> {
+        let mut ids = ids.into_iter().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut data = Vec::with_capacity(ids.len() * 8);
+        for id in ids {
+            data.extend_from_slice(&id.to_be_bytes());
+        }
+
+        std::fs::write(path, data).map_err(io_error)
+    }
+
+    /// number of revoked ids in the list
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    fn read_at(file: &mut File, index: u64) -> Result<i64, error::Token> {
+        let mut buf = [0u8; 8];
+        file.seek(SeekFrom::Start(index * 8)).map_err(io_error)?;
+        file.read_exact(&mut buf).map_err(io_error)?;
+        Ok(i64::from_be_bytes(buf))
+    }
+}
+
+impl RevocationStore for SortedFileRevocationStore {
+    fn is_revoked(&self, id: i64) -> Result<bool, error::Token> {
+        let mut file = self.file.lock().map_err(|_| error::Token::InternalError)?;
+
+        let mut low = 0u64;
+        let mut high = self.count;
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let value = Self::read_at(&mut file, middle)?;
+
+            if value == id {
+                return Ok(true);
+            } else if value < id {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Bloom filter placed in front of another revocation store
+///
+/// Most tokens are not revoked: the filter answers negatively without
+/// querying the inner store, which is only consulted on (possibly false) positives.
+pub struct BloomRevocationStore<S> {
+    inner: S,
+    bits: Vec<u64>,
+    bit_count: u64,
+    hash_count: u32,
+}
+
+impl<S: RevocationStore> BloomRevocationStore<S> {
+    /// creates an empty filter sized for `expected_items` ids, with the
+    /// target false positive rate
+    pub fn new(inner: S, expected_items: usize, false_positive_rate: f64) -> Self {
+        let n = (expected_items.max(1)) as f64;
+        let p = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let bit_count = ((-n * p.ln()) / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let hash_count = ((bit_count as f64 / n) * ln2).round().max(1.0) as u32;
+
+        BloomRevocationStore {
+            inner,
+            bits: vec![0u64; bit_count.div_ceil(64) as usize],
+            bit_count,
+            hash_count,
+        }
+    }
+
+    /// creates a filter containing all of the ids, in front of the inner store
+    pub fn from_ids<I: IntoIterator<Item = i64>>(inner: S, ids: I, false_positive_rate: f64) -> Self {
+        let ids = ids.into_iter().collect::<Vec<_>>();
+        let mut store = Self::new(inner, ids.len(), false_positive_rate);
+        for id in ids {
+            store.insert(id);
+        }
+        store
+    }
+
+    /// adds an id to the filter
+    ///
+    /// the id must also be present in the inner store
+    pub fn insert(&mut self, id: i64) {
+        for index in self.indexes(id) {
+            self.bits[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    /// returns true if the id might be revoked, false if it is certainly not
+    pub fn may_contain(&self, id: i64) -> bool {
+        self.indexes(id)
+            .all(|index| self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn indexes(&self, id: i64) -> impl Iterator<Item = u64> {
+        // double hashing: h1 + i * h2
+        let hash = Sha256::digest(&id.to_be_bytes());
+        let mut h1 = [0u8; 8];
+        let mut h2 = [0u8; 8];
+        h1.copy_from_slice(&hash[0..8]);
+        h2.copy_from_slice(&hash[8..16]);
+        let h1 = u64::from_le_bytes(h1);
+        let h2 = u64::from_le_bytes(h2) | 1;
+        let bit_count = self.bit_count;
+
+        (0..self.hash_count as u64)
+            .map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % bit_count)
+    }
+}
+
+impl<S: RevocationStore> RevocationStore for BloomRevocationStore<S> {
+    fn is_revoked(&self, id: i64) -> Result<bool, error::Token> {
+        if !self.may_contain(id) {
+            return Ok(false);
+        }
+
+        self.inner.is_revoked(id)
+    }
+}
+
+fn io_error(e: std::io::Error) -> error::Token {
+    error::Token::RevocationStore(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stores() {
+        let revoked = [12i64, -5, 1234, 0, 99999];
+
+        let set = revoked.iter().cloned().collect::<HashSet<i64>>();
+        assert!(set.is_revoked(1234).unwrap());
+        assert!(!set.is_revoked(1235).unwrap());
+
+        let path = std::env::temp_dir().join(format!("biscuit-revocation-{}.bin", std::process::id()));
+        SortedFileRevocationStore::write(&path, revoked.iter().cloned()).unwrap();
+        let file_store = SortedFileRevocationStore::open(&path).unwrap();
+        assert_eq!(file_store.len(), 5);
+
+        for id in revoked.iter() {
+            assert!(file_store.is_revoked(*id).unwrap(), "{} should be revoked", id);
+        }
+        for id in [-6i64, 1, 13, 1233, 100000, i64::MAX, i64::MIN].iter() {
+            assert!(!file_store.is_revoked(*id).unwrap(), "{} should not be revoked", id);
+        }
+
+        let bloom = BloomRevocationStore::from_ids(file_store, revoked.iter().cloned(), 0.01);
+        for id in revoked.iter() {
+            assert!(bloom.may_contain(*id));
+            assert!(bloom.is_revoked(*id).unwrap());
+        }
+        // false positives are resolved by the inner store
+        for id in 100..1000 {
+            assert!(!bloom.is_revoked(id).unwrap());
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,19 +1,61 @@
 //! structures to handle sealed tokens (using symmetric cryptography, not attenuable)
+//!
+//! Sealed tokens come in two modes:
+//!
+//! - [`SealMode::Hmac`]: the blocks are in clear text and authenticated by a HMAC
+//! - [`SealMode::Encrypted`]: the token sealed in HMAC mode is encrypted with
+//!   ChaCha20-Poly1305, using a key derived from the secret and a random salt
+//!
+//! Tokens can carry the id of the key that sealed them, so that a [`SealingKeyring`]
+//! can rotate secrets without invalidating the tokens sealed with the previous ones.
 use super::Biscuit;
 use crate::error;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
 use hmac::{Hmac, Mac, NewMac};
+use rand_core::{CryptoRng, RngCore};
 use sha2::Sha256;
+use std::time::SystemTime;
+use zeroize::Zeroizing;
 
 use crate::format::{convert::token_block_to_proto_block, schema};
 use crate::prost::Message;
 
 type HmacSha256 = Hmac<Sha256>;
 
+const ENCRYPTION_INFO: &[u8] = b"biscuit sealed encryption v1";
+const SALT_LENGTH: usize = 32;
+
+/// protection of the blocks of a sealed token
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SealMode {
+    /// blocks in clear text, authenticated by a HMAC
+    Hmac,
+    /// blocks encrypted and authenticated by ChaCha20-Poly1305
+    Encrypted,
+}
+
+impl SealMode {
+    fn from_proto(mode: Option<u32>) -> Result<Self, error::Format> {
+        match mode {
+            None | Some(0) => Ok(SealMode::Hmac),
+            Some(1) => Ok(SealMode::Encrypted),
+            Some(m) => Err(error::Format::DeserializationError(format!(
+                "deserialization error: unknown seal mode {}",
+                m
+            ))),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct SealedBiscuit {
     pub authority: Vec<u8>,
     pub blocks: Vec<Vec<u8>>,
     pub signature: Vec<u8>,
+    /// id of the sealing key, not covered by the signature
+    pub key_id: Option<u32>,
 }
 
 impl SealedBiscuit {
@@ -55,18 +97,63 @@ impl SealedBiscuit {
             authority,
             blocks,
             signature,
+            key_id: None,
         })
     }
 
+    /// deserializes a sealed token in either mode, and checks it with the secret
     pub fn from_slice(slice: &[u8], secret: &[u8]) -> Result<Self, error::Format> {
-        let proto: schema::SealedBiscuit = schema::SealedBiscuit::decode(slice).map_err(|e| {
+        let proto = Self::decode(slice)?;
+
+        match SealMode::from_proto(proto.mode)? {
+            SealMode::Hmac => Self::from_proto(proto, secret),
+            SealMode::Encrypted => {
+                let plaintext = Self::decrypt(&proto, secret)?;
+                let inner = Self::decode(&plaintext)?;
+
+                // the encrypted payload must be a token sealed in HMAC mode
+                if SealMode::from_proto(inner.mode)? != SealMode::Hmac {
+                    return Err(error::Format::DeserializationError(
+                        "deserialization error: nested encrypted token".to_string(),
+                    ));
+                }
+
+                Self::from_proto(inner, secret)
+            }
+        }
+    }
+
+    /// deserializes a sealed token in either mode, and checks it with the keyring
+    ///
+    /// tokens without a key id are checked with the current key
+    pub fn from_slice_with_keyring(
+        slice: &[u8],
+        keyring: &SealingKeyring,
+        now: SystemTime,
+    ) -> Result<Self, error::Format> {
+        let key_id = Self::decode(slice)?.key_id;
+
+        let secret = match key_id {
+            Some(id) => keyring.secret(id, now),
+            None => keyring.secret(keyring.current, now),
+        }
+        .ok_or(error::Format::UnknownSealingKey(key_id))?;
+
+        Self::from_slice(slice, secret)
+    }
+
+    fn decode(slice: &[u8]) -> Result<schema::SealedBiscuit, error::Format> {
+        schema::SealedBiscuit::decode(slice).map_err(|e| {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
-        })?;
+        })
+    }
 
+    fn from_proto(proto: schema::SealedBiscuit, secret: &[u8]) -> Result<Self, error::Format> {
         let deser = SealedBiscuit {
             authority: proto.authority,
             blocks: proto.blocks,
             signature: proto.signature,
+            key_id: proto.key_id,
         };
 
         let mut mac = HmacSha256::new_varkey(secret).unwrap();
@@ -81,27 +168,185 @@ impl SealedBiscuit {
         Ok(deser)
     }
 
+    /// serializes the token in HMAC mode
     pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
-        let proto = schema::SealedBiscuit {
-            authority: self.authority.clone(),
-            blocks: self.blocks.clone(),
-            signature: self.signature.clone(),
-        };
+        encode(&self.to_proto())
+    }
 
-        let mut v = Vec::new();
-        proto
-            .encode(&mut v)
-            .map(|_| v)
-            .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
+    /// serializes the token in encrypted mode
+    ///
+    /// the secret must be the one used to create this token with [`SealedBiscuit::from_token`]
+    pub fn to_encrypted_vec<T: RngCore + CryptoRng>(
+        &self,
+        rng: &mut T,
+        secret: &[u8],
+    ) -> Result<Vec<u8>, error::Format> {
+        let plaintext = Zeroizing::new(self.to_vec()?);
+
+        let mut salt = vec![0u8; SALT_LENGTH];
+        rng.fill_bytes(&mut salt);
+
+        // the key is derived from a random salt for each token, so the nonce can be constant
+        let ciphertext = cipher(&salt, secret)?
+            .encrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: &plaintext,
+                    aad: ENCRYPTION_INFO,
+                },
+            )
+            .map_err(|_| {
+                error::Format::SerializationError("serialization error: encryption failed".to_string())
+            })?;
+
+        encode(&schema::SealedBiscuit {
+            authority: ciphertext,
+            blocks: vec![],
+            signature: vec![],
+            mode: Some(1),
+            salt: Some(salt),
+            key_id: self.key_id,
+        })
     }
 
     pub fn serialized_size(&self) -> usize {
-        let proto = schema::SealedBiscuit {
+        self.to_proto().encoded_len()
+    }
+
+    fn to_proto(&self) -> schema::SealedBiscuit {
+        schema::SealedBiscuit {
             authority: self.authority.clone(),
             blocks: self.blocks.clone(),
             signature: self.signature.clone(),
+            mode: None,
+            salt: None,
+            key_id: self.key_id,
+        }
+    }
+
+    fn decrypt(proto: &schema::SealedBiscuit, secret: &[u8]) -> Result<Zeroizing<Vec<u8>>, error::Format> {
+        let salt = match proto.salt.as_ref() {
+            Some(salt) if salt.len() == SALT_LENGTH => salt,
+            _ => {
+                return Err(error::Format::DeserializationError(
+                    "deserialization error: invalid salt".to_string(),
+                ))
+            }
+        };
+
+        cipher(salt, secret)?
+            .decrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: &proto.authority,
+                    aad: ENCRYPTION_INFO,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| error::Format::SealedSignature)
+    }
+}
+
+/// secret used to seal tokens, identified by a key id
+pub struct SealingKey {
+    pub id: u32,
+    secret: Zeroizing<Vec<u8>>,
+    /// after this date, the key cannot seal or unseal tokens
+    pub expires_at: Option<SystemTime>,
+}
+
+impl SealingKey {
+    fn is_active(&self, now: SystemTime) -> bool {
+        self.expires_at.map(|date| now < date).unwrap_or(true)
+    }
+}
+
+/// set of secrets for sealed tokens
+///
+/// tokens are sealed with the current key and carry its id. They can be unsealed
+/// with any key of the keyring that has not expired
+pub struct SealingKeyring {
+    keys: Vec<SealingKey>,
+    current: u32,
+}
+
+impl SealingKeyring {
+    /// creates a keyring, with the key used to seal tokens
+    pub fn new(id: u32, secret: &[u8]) -> Self {
+        let mut keyring = SealingKeyring {
+            keys: vec![],
+            current: id,
         };
+        keyring.add_key(id, secret, None);
+        keyring
+    }
 
-        proto.encoded_len()
+    /// adds a key that can unseal tokens until its expiration date
+    ///
+    /// this replaces any key with the same id
+    pub fn add_key(&mut self, id: u32, secret: &[u8], expires_at: Option<SystemTime>) {
+        self.keys.retain(|k| k.id != id);
+        self.keys.push(SealingKey {
+            id,
+            secret: Zeroizing::new(secret.to_vec()),
+            expires_at,
+        });
     }
+
+    /// makes a new key the current one
+    ///
+    /// the previous key can still unseal tokens until `previous_expires_at`
+    pub fn rotate(&mut self, id: u32, secret: &[u8], previous_expires_at: SystemTime) {
+        let current = self.current;
+        if let Some(key) = self.keys.iter_mut().find(|k| k.id == current) {
+            key.expires_at = Some(previous_expires_at);
+        }
+
+        self.add_key(id, secret, None);
+        self.current = id;
+    }
+
+    /// the key used to seal tokens
+    pub fn current(&self) -> Option<&SealingKey> {
+        self.keys.iter().find(|k| k.id == self.current)
+    }
+
+    pub fn keys(&self) -> &[SealingKey] {
+        &self.keys
+    }
+
+    /// removes the expired keys
+    pub fn remove_expired(&mut self, now: SystemTime) {
+        self.keys.retain(|k| k.is_active(now));
+    }
+
+    pub(crate) fn current_secret(&self, now: SystemTime) -> Result<(u32, &[u8]), error::Format> {
+        self.secret(self.current, now)
+            .map(|secret| (self.current, secret))
+            .ok_or(error::Format::UnknownSealingKey(Some(self.current)))
+    }
+
+    fn secret(&self, id: u32, now: SystemTime) -> Option<&[u8]> {
+        self.keys
+            .iter()
+            .find(|k| k.id == id && k.is_active(now))
+            .map(|k| &k.secret[..])
+    }
+}
+
+fn cipher(salt: &[u8], secret: &[u8]) -> Result<ChaCha20Poly1305, error::Format> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Hkdf::<Sha256>::new(Some(salt), secret)
+        .expand(ENCRYPTION_INFO, &mut key[..])
+        .map_err(|_| error::Format::InvalidKey("could not derive the encryption key".to_string()))?;
+
+    Ok(ChaCha20Poly1305::new(&Key::from(*key)))
+}
+
+fn encode(proto: &schema::SealedBiscuit) -> Result<Vec<u8>, error::Format> {
+    let mut v = Vec::new();
+    proto
+        .encode(&mut v)
+        .map(|_| v)
+        .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
 }
@@ -1,12 +1,16 @@
 //! Verifier structure and associated functions
 use super::builder::{
-    constrained_rule, date, fact, pred, s, string, Constraint, ConstraintKind, Fact,
-    IntConstraint, Rule, Caveat, var,
+    constrained_rule, date, fact, ip, pred, s, string, Constraint, ConstraintKind, Fact,
+    IntConstraint, Predicate, Rule, Caveat, Term, var,
 };
+use super::builtin::{self, builtins_last, caveat_builtins_last, Builtin};
+use super::provider::FactProvider;
+use super::revocation::RevocationStore;
 use super::Biscuit;
+use crate::crypto::{self, PublicKey};
 use crate::datalog;
 use crate::error;
-use std::{convert::TryInto, time::{SystemTime, Duration}, default::Default};
+use std::{collections::HashMap, convert::TryInto, time::{SystemTime, Duration}, default::Default, net::IpAddr, sync::{Arc, Mutex}};
 
 /// used to check authorization policies on a token
 ///
@@ -15,22 +19,58 @@ use std::{convert::TryInto, time::{SystemTime, Duration}, default::Default};
 pub struct Verifier {
     world: datalog::World,
     symbols: datalog::SymbolTable,
-    caveats: Vec<Caveat>,
+    caveats: Vec<datalog::Caveat>,
     token_caveats: Vec<Vec<datalog::Caveat>>,
+    /// regular expressions of the verifier and token caveats
+    regexes: datalog::CompiledRegexes,
+    token_revocation_ids: Vec<Vec<i64>>,
+    token_proof_key: Option<PublicKey>,
+    revocation_store: Option<Arc<dyn RevocationStore + Send + Sync>>,
+    fact_providers: HashMap<datalog::Symbol, Arc<dyn FactProvider + Send + Sync>>,
+    builtins: HashMap<datalog::Symbol, Builtin>,
     has_token: bool,
+    scoped_tokens: Vec<ScopedToken>,
+}
+
+/// token loaded with [`Verifier::add_scoped_token`]
+#[derive(Clone)]
+struct ScopedToken {
+    scope: String,
+    symbol: datalog::Symbol,
+    /// facts and rules of the token
+    world: datalog::World,
+    caveats: Vec<Vec<datalog::Caveat>>,
+    revocation_ids: Vec<Vec<i64>>,
 }
 
 impl Verifier {
     pub(crate) fn from_token(token: &Biscuit) -> Result<Self, error::Logic> {
-        let world = token.generate_world(&token.symbols)?;
+        let mut world = token.generate_world(&token.symbols)?;
         let symbols = token.symbols.clone();
+        let mut token_caveats = token.caveats();
+
+        let queries = token_caveats.iter_mut().flatten().flat_map(|c| c.queries.iter_mut());
+        for rule in world.rules.iter_mut().chain(queries) {
+            builtin::datalog_builtins_last(rule, &symbols);
+        }
+        let mut regexes = datalog::CompiledRegexes::new();
+        for caveat in token_caveats.iter().flatten() {
+            regexes.add_caveat(caveat);
+        }
 
         Ok(Verifier {
             world,
             symbols,
             caveats: vec![],
-            token_caveats: token.caveats(),
+            token_caveats,
+            regexes,
+            token_revocation_ids: token.revocation_ids(),
+            token_proof_key: token.proof_key(),
+            revocation_store: None,
+            fact_providers: HashMap::new(),
+            builtins: HashMap::new(),
             has_token: true,
+            scoped_tokens: vec![],
         })
     }
 
@@ -40,9 +80,8 @@ impl Verifier {
     /// * there is no token (unauthenticated case)
     /// * there is a lot of data to load in the verifier on each check
     ///
-    /// In the latter case, we can create an empty verifier, load it
-    /// with the facts, rules and caveats, and each time a token must be checked,
-    /// clone the verifier and load the token with [`Verifier::add_token`]
+    /// In the latter case, prefer a [`VerifierTemplate`], that shares its facts
+    /// and rules with the verifiers it creates instead of copying them
     pub fn new() -> Result<Self, error::Logic> {
         let world = datalog::World::new();
         let symbols = super::default_symbol_table();
@@ -52,18 +91,75 @@ impl Verifier {
             symbols,
             caveats: vec![],
             token_caveats: vec![],
+            regexes: datalog::CompiledRegexes::new(),
+            token_revocation_ids: vec![],
+            token_proof_key: None,
+            revocation_store: None,
+            fact_providers: HashMap::new(),
+            builtins: HashMap::new(),
             has_token: false,
+            scoped_tokens: vec![],
         })
     }
 
     /// Loads a token's facts, rules and caveats in a verifier
     pub fn add_token(&mut self, token: &Biscuit) -> Result<(), error::Logic> {
-        if self.has_token {
+        if self.has_token || !self.scoped_tokens.is_empty() {
             return Err(error::Logic::VerifierNotEmpty);
         } else {
             self.has_token = true;
         }
 
+        let (world, token_caveats) = self.load_token(token)?;
+        self.world.facts.extend(world.facts);
+        for rule in world.rules {
+            self.world.add_rule(rule);
+        }
+
+        self.token_caveats = token_caveats;
+        self.token_revocation_ids = token.revocation_ids();
+        self.token_proof_key = token.proof_key();
+        Ok(())
+    }
+
+    /// Loads a token in a verifier, along with other tokens, under a scope
+    ///
+    /// the facts and rules of a scoped token are evaluated with the verifier's facts
+    /// and rules, but not with those of other tokens, and its caveats are checked
+    /// over that result. Failed caveats are reported as [`error::FailedCaveat::Token`].
+    ///
+    /// The facts of the token, and the facts generated from them, are then added to
+    /// the verifier with the scope symbol as first term, so that the verifier's
+    /// rules and caveats can refer to a specific token: with the `user` scope,
+    /// `right(#authority, "file1", #read)` becomes `right(#user, #authority, "file1", #read)`.
+    /// The scope symbol should not be used by other facts of the verifier.
+    ///
+    /// Scoped tokens cannot be mixed with a token loaded by [`Verifier::add_token`],
+    /// and the scope cannot be `authority` or `ambient`
+    pub fn add_scoped_token(&mut self, scope: &str, token: &Biscuit) -> Result<(), error::Logic> {
+        if self.has_token {
+            return Err(error::Logic::VerifierNotEmpty);
+        }
+
+        if scope == "authority" || scope == "ambient" || self.scoped_tokens.iter().any(|t| t.scope == scope) {
+            return Err(error::Logic::InvalidScope(scope.to_string()));
+        }
+
+        let (world, caveats) = self.load_token(token)?;
+        let symbol = self.symbols.insert(scope);
+        self.scoped_tokens.push(ScopedToken {
+            scope: scope.to_string(),
+            symbol,
+            world,
+            caveats,
+            revocation_ids: token.revocation_ids(),
+        });
+        Ok(())
+    }
+
+    /// converts a token's facts, rules and caveats to the verifier's symbol table
+    fn load_token(&mut self, token: &Biscuit) -> Result<(datalog::World, Vec<Vec<datalog::Caveat>>), error::Logic> {
+        let mut world = datalog::World::new();
         let authority_index = self.symbols.get("authority").unwrap();
         let ambient_index = self.symbols.get("ambient").unwrap();
 
@@ -75,12 +171,12 @@ impl Verifier {
             }
 
             let fact = Fact::convert_from(&fact, &token.symbols).convert(&mut self.symbols);
-            self.world.facts.insert(fact);
+            world.facts.insert(fact);
         }
 
         for rule in token.authority.rules.iter().cloned() {
-            let rule = Rule::convert_from(&rule, &token.symbols).convert(&mut self.symbols);
-            self.world.rules.push(rule);
+            let rule = builtins_last(Rule::convert_from(&rule, &token.symbols)).convert(&mut self.symbols);
+            world.add_rule(rule);
         }
 
         for (i, block) in token.blocks.iter().enumerate() {
@@ -96,7 +192,7 @@ impl Verifier {
                 }
 
                 let fact = Fact::convert_from(&fact, &token.symbols).convert(&mut self.symbols);
-                self.world.facts.insert(fact);
+                world.facts.insert(fact);
             }
 
             for rule in block.rules.iter().cloned() {
@@ -110,26 +206,44 @@ impl Verifier {
                     ));
                 }
 
-                let rule = Rule::convert_from(&rule, &token.symbols).convert(&mut self.symbols);
-                self.world.rules.push(rule);
+                let rule = builtins_last(Rule::convert_from(&rule, &token.symbols)).convert(&mut self.symbols);
+                world.add_rule(rule);
             }
         }
 
         let mut token_caveats: Vec<Vec<datalog::Caveat>> = Vec::new();
         let caveats = token.authority.caveats.iter()
-            .map(|c| Caveat::convert_from(&c, &token.symbols).convert(&mut self.symbols))
+            .map(|c| caveat_builtins_last(Caveat::convert_from(&c, &token.symbols)).convert(&mut self.symbols))
             .collect();
         token_caveats.push(caveats);
 
         for block in token.blocks.iter() {
             let caveats = block.caveats.iter()
-                .map(|c| Caveat::convert_from(&c, &token.symbols).convert(&mut self.symbols))
+                .map(|c| caveat_builtins_last(Caveat::convert_from(&c, &token.symbols)).convert(&mut self.symbols))
                 .collect();
             token_caveats.push(caveats);
         }
 
-        self.token_caveats = token_caveats;
-        Ok(())
+        for caveat in token_caveats.iter().flatten() {
+            self.regexes.add_caveat(caveat);
+        }
+        Ok((world, token_caveats))
+    }
+
+    /// checks a proof of possession created by [`Biscuit::sign_request`]
+    ///
+    /// the proof must be a signature of the request by the first key pinned in the
+    /// token with a `proof_key` fact (see [`Biscuit::append_with_proof_key`]). Keys
+    /// pinned by later blocks are ignored, so appending a block to a stolen token
+    /// does not allow signing proofs. A token stolen before a key was pinned is not
+    /// protected: pin the key in the authority block with
+    /// [`BiscuitBuilder::proof_key`](`crate::token::builder::BiscuitBuilder::proof_key`)
+    /// to bind the token from its creation
+    pub fn verify_possession(&self, request: &[u8], proof: &crypto::Signature) -> Result<(), error::Token> {
+        let key = self.token_proof_key.ok_or(error::Token::MissingProofKey)?;
+
+        key.verify_possession(request, proof)
+            .map_err(|_| error::Token::InvalidPossessionProof)
     }
 
     /// add a fact to the verifier
@@ -142,7 +256,7 @@ impl Verifier {
     /// add a rule to the verifier
     pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token> {
         let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
-        self.world.rules.push(rule.convert(&mut self.symbols));
+        self.world.add_rule(builtins_last(rule).convert(&mut self.symbols));
         Ok(())
     }
 
@@ -163,8 +277,15 @@ impl Verifier {
         limits: VerifierLimits
     ) -> Result<Vec<Fact>, error::Token> {
         let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
-        self.world.run_with_limits(limits.into()).map_err(error::Token::RunLimit)?;
-        let mut res = self.world.query_rule(rule.convert(&mut self.symbols));
+        let rule = builtins_last(rule).convert(&mut self.symbols);
+        self.check_builtins()?;
+        if let Some(p) = rule.body.iter().find(|p| self.is_unknown_builtin(p.name)) {
+            return Err(error::Token::FailedLogic(error::Logic::UnknownBuiltin(self.symbols.print_symbol(p.name))));
+        }
+
+        let lookup = self.start_lookup();
+        let res = self.run(&limits).map(|_| self.world.query_rule(rule));
+        let mut res = self.end_lookup(lookup, res)?;
 
         Ok(res
            .drain(..)
@@ -173,8 +294,13 @@ impl Verifier {
     }
 
     /// add a caveat to the verifier
+    ///
+    /// the caveat is converted and its regular expressions compiled here, instead
+    /// of on each call to [`Verifier::verify`]
     pub fn add_caveat<R: TryInto<Caveat>>(&mut self, caveat: R) -> Result<(), error::Token> {
         let caveat = caveat.try_into().map_err(|_| error::Token::ParseError)?;
+        let caveat = caveat_builtins_last(caveat).convert(&mut self.symbols);
+        self.regexes.add_caveat(&caveat);
         self.caveats.push(caveat);
         Ok(())
     }
@@ -189,6 +315,15 @@ impl Verifier {
         self.world.facts.insert(fact.convert(&mut self.symbols));
     }
 
+    /// adds a fact with the address of the client: `client_ip(#ambient, ip:10.0.0.1)`
+    ///
+    /// IPv4-mapped IPv6 addresses, like `::ffff:10.0.0.1`, are converted to IPv4
+    pub fn add_client_ip(&mut self, client_ip: IpAddr) {
+        let client_ip = datalog::canonical_ip(client_ip);
+        let fact = fact("client_ip", &[s("ambient"), ip(client_ip)]);
+        self.world.facts.insert(fact.convert(&mut self.symbols));
+    }
+
     /// adds a fact with the current time
     pub fn set_time(&mut self) {
         let fact = fact("time", &[s("ambient"), date(&SystemTime::now())]);
@@ -208,6 +343,34 @@ impl Verifier {
         let _ = self.add_caveat(caveat);
     }
 
+    /// sets the revocation store queried during [`Verifier::verify`]
+    ///
+    /// each `revocation_id(<integer>)` fact provided by the token's blocks is
+    /// checked against the store. Unlike [`Verifier::revocation_check`], the list
+    /// of revoked ids is not loaded in the Datalog engine
+    pub fn set_revocation_store(&mut self, store: Arc<dyn RevocationStore + Send + Sync>) {
+        self.revocation_store = Some(store);
+    }
+
+    /// registers a provider for the facts with this predicate name
+    ///
+    /// the provider is queried during evaluation, see [`provider`](`super::provider`)
+    pub fn add_fact_provider(&mut self, name: &str, provider: Arc<dyn FactProvider + Send + Sync>) {
+        let name = self.symbols.insert(name);
+        self.fact_providers.insert(name, provider);
+    }
+
+    /// registers a builtin predicate, called as `@name(..)` in rules and caveats
+    ///
+    /// see [`builtin`](`super::builtin`)
+    pub fn add_builtin<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[Term]) -> Vec<Vec<Term>> + Send + Sync + 'static,
+    {
+        let name = self.symbols.insert(&format!("{}{}", builtin::BUILTIN_PREFIX, name));
+        self.builtins.insert(name, Arc::new(f));
+    }
+
     /// checks all the caveats
     ///
     /// on error, this can return a list of all the failed caveats
@@ -221,31 +384,53 @@ impl Verifier {
     ///
     /// this method can specify custom runtime limits
     pub fn verify_with_limits(&mut self, limits: VerifierLimits) -> Result<(), error::Token> {
+        let lookup = self.start_lookup();
+        let res = self.check_caveats(limits);
+        self.end_lookup(lookup, res)
+    }
+
+    fn check_caveats(&mut self, limits: VerifierLimits) -> Result<(), error::Token> {
         let start = SystemTime::now();
+        self.check_builtins()?;
 
         //FIXME: should check for the presence of any other symbol in the token
         if self.symbols.get("authority").is_none() || self.symbols.get("ambient").is_none() {
             return Err(error::Token::MissingSymbols);
         }
 
-        self.world.run_with_limits(limits.clone().into()).map_err(error::Token::RunLimit)?;
+        if let Some(store) = self.revocation_store.as_ref() {
+            let scoped_ids = self.scoped_tokens.iter().map(|t| (Some(&t.scope), &t.revocation_ids));
+            for (scope, ids) in std::iter::once((None, &self.token_revocation_ids)).chain(scoped_ids) {
+                for (i, block_ids) in ids.iter().enumerate() {
+                    for id in block_ids.iter() {
+                        if store.is_revoked(*id)? {
+                            return Err(error::Token::Revoked {
+                                scope: scope.cloned(),
+                                block_id: i as u32,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        let token_errors = self.run(&limits)?;
 
         let time_limit = start + limits.max_time;
 
         let mut errors = vec![];
         for (i, caveat) in self.caveats.iter().enumerate() {
-            let c = caveat.convert(&mut self.symbols);
             let mut successful = false;
 
             for query in caveat.queries.iter() {
-                let res = self.world.query_rule(query.convert(&mut self.symbols));
+                let res = self.world.query_match(query, &self.regexes);
 
                 let now = SystemTime::now();
                 if now >= time_limit {
                     return Err(error::Token::RunLimit(error::RunLimit::Timeout));
                 }
 
-                if !res.is_empty() {
+                if res {
                     successful = true;
                     break;
                 }
@@ -254,7 +439,7 @@ impl Verifier {
             if !successful {
                 errors.push(error::FailedCaveat::Verifier(error::FailedVerifierCaveat {
                     caveat_id: i as u32,
-                    rule: self.symbols.print_caveat(&c),
+                    rule: self.symbols.print_caveat(caveat),
                 }));
             }
         }
@@ -264,14 +449,14 @@ impl Verifier {
                 let mut successful = false;
 
                 for query in caveat.queries.iter() {
-                    let res = self.world.query_rule(query.clone());
+                    let res = self.world.query_match(query, &self.regexes);
 
                     let now = SystemTime::now();
                     if now >= time_limit {
                         return Err(error::Token::RunLimit(error::RunLimit::Timeout));
                     }
 
-                    if !res.is_empty() {
+                    if res {
                         successful = true;
                         break;
                     }
@@ -287,6 +472,8 @@ impl Verifier {
             }
         }
 
+        errors.extend(token_errors);
+
         if !errors.is_empty() {
             Err(error::Token::FailedLogic(error::Logic::FailedCaveats(
                 errors,
@@ -296,25 +483,139 @@ impl Verifier {
         }
     }
 
+    fn is_unknown_builtin(&self, name: datalog::Symbol) -> bool {
+        !self.builtins.contains_key(&name) && builtin::is_builtin(&self.symbols.print_symbol(name))
+    }
+
+    /// checks that the builtin predicates used by rules and caveats are registered
+    fn check_builtins(&self) -> Result<(), error::Token> {
+        let scoped_rules = self.scoped_tokens.iter().flat_map(|t| t.world.rules.iter());
+        let caveats = self.caveats.iter()
+            .chain(self.token_caveats.iter().flatten())
+            .chain(self.scoped_tokens.iter().flat_map(|t| t.caveats.iter().flatten()));
+        let rules = self.world.all_rules()
+            .chain(scoped_rules)
+            .chain(caveats.flat_map(|c| c.queries.iter()));
+
+        for rule in rules {
+            if let Some(p) = rule.body.iter().find(|p| self.is_unknown_builtin(p.name)) {
+                return Err(error::Token::FailedLogic(error::Logic::UnknownBuiltin(
+                    self.symbols.print_symbol(p.name),
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// makes the fact providers and builtins available to the Datalog engine,
+    /// until [`Verifier::end_lookup`]
+    fn start_lookup(&mut self) -> Option<Arc<ProviderLookup>> {
+        if self.fact_providers.is_empty() && self.builtins.is_empty() {
+            return None;
+        }
+
+        let lookup = Arc::new(ProviderLookup {
+            providers: self.fact_providers.clone(),
+            builtins: self.builtins.clone(),
+            symbols: Mutex::new(self.symbols.clone()),
+            cache: Mutex::new(HashMap::new()),
+            error: Mutex::new(None),
+        });
+        self.world.lookup = Some(datalog::Lookup(lookup.clone()));
+        Some(lookup)
+    }
+
+    /// removes the fact providers, keeping the symbols introduced by their facts
+    fn end_lookup<T>(&mut self, lookup: Option<Arc<ProviderLookup>>, res: Result<T, error::Token>) -> Result<T, error::Token> {
+        if let Some(lookup) = lookup {
+            self.world.lookup = None;
+            self.symbols = std::mem::take(&mut *lookup.symbols.lock().unwrap());
+            if let Some(e) = lookup.error.lock().unwrap().take() {
+                return Err(e);
+            }
+        }
+
+        res
+    }
+
+    /// runs the Datalog engine, then evaluates the scoped tokens and adds their
+    /// facts to the verifier
+    ///
+    /// returns the failed caveats of the scoped tokens
+    fn run(&mut self, limits: &VerifierLimits) -> Result<Vec<error::FailedCaveat>, error::Token> {
+        self.world.run_with_limits(limits.clone().into()).map_err(error::Token::RunLimit)?;
+        if self.scoped_tokens.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // the scoped tokens are evaluated on top of the verifier's world, without copying it
+        let base = Arc::new(std::mem::take(&mut self.world));
+        let res = self.run_scoped_tokens(&base, limits);
+        self.world = Arc::try_unwrap(base).unwrap_or_else(|base| (*base).clone());
+        let (facts, errors) = res?;
+
+        self.world.facts.extend(facts);
+        self.world.run_with_limits(limits.clone().into()).map_err(error::Token::RunLimit)?;
+        Ok(errors)
+    }
+
+    fn run_scoped_tokens(
+        &self,
+        base: &Arc<datalog::World>,
+        limits: &VerifierLimits,
+    ) -> Result<(Vec<datalog::Fact>, Vec<error::FailedCaveat>), error::Token> {
+        let mut facts = vec![];
+        let mut errors = vec![];
+
+        for token in self.scoped_tokens.iter() {
+            let mut world = datalog::World::with_base(base.clone());
+            world.facts = token.world.facts.clone();
+            world.rules = token.world.rules.clone();
+            world.regexes = token.world.regexes.clone();
+            world.run_with_limits(limits.clone().into()).map_err(error::Token::RunLimit)?;
+
+            for (i, block_caveats) in token.caveats.iter().enumerate() {
+                for (j, caveat) in block_caveats.iter().enumerate() {
+                    if !caveat.queries.iter().any(|query| world.query_match(query, &self.regexes)) {
+                        errors.push(error::FailedCaveat::Token(error::FailedTokenCaveat {
+                            scope: token.scope.clone(),
+                            block_id: i as u32,
+                            caveat_id: j as u32,
+                            rule: self.symbols.print_caveat(caveat),
+                        }));
+                    }
+                }
+            }
+
+            let scope = datalog::ID::Symbol(token.symbol);
+            facts.extend(world.facts.drain().map(|mut fact| {
+                fact.predicate.ids.insert(0, scope.clone());
+                fact
+            }));
+        }
+
+        Ok((facts, errors))
+    }
+
     /// prints the content of the verifier
     pub fn print_world(&self) -> String {
         let mut facts = self.world
-            .facts
+            .all_facts()
             .iter()
             .map(|f| self.symbols.print_fact(f))
             .collect::<Vec<_>>();
         facts.sort();
 
         let mut rules = self.world
-            .rules
-            .iter()
+            .all_rules()
             .map(|r| self.symbols.print_rule(r))
             .collect::<Vec<_>>();
         rules.sort();
 
         let mut caveats = Vec::new();
         for (index, caveat) in self.caveats.iter().enumerate() {
-            caveats.push(format!("Verifier[{}]: {}", index, caveat));
+            caveats.push(format!("Verifier[{}]: {}", index, self.symbols.print_caveat(caveat)));
         }
 
         for (i, block_caveats) in self.token_caveats.iter().enumerate() {
@@ -323,21 +624,222 @@ impl Verifier {
             }
         }
 
+        for token in self.scoped_tokens.iter() {
+            for (i, block_caveats) in token.caveats.iter().enumerate() {
+                for (j, caveat) in block_caveats.iter().enumerate() {
+                    caveats.push(format!("Token[{}][{}][{}]: {}", token.scope, i, j, self.symbols.print_caveat(caveat)));
+                }
+            }
+        }
+
         format!("World {{\n  facts: {:#?}\n  rules: {:#?}\n  caveats: {:#?}\n}}", facts, rules, caveats)
     }
 
     /// returns all of the data loaded in the verifier
     pub fn dump(&self) -> (Vec<Fact>, Vec<Rule>, Vec<Caveat>) {
-        let mut caveats = self.caveats.clone();
-        caveats.extend(self.token_caveats.iter().flatten().map(|c| Caveat::convert_from(c, &self.symbols)));
+        let caveats = self.caveats.iter()
+            .chain(self.token_caveats.iter().flatten())
+            .map(|c| Caveat::convert_from(c, &self.symbols))
+            .collect();
 
-        (self.world.facts.iter().map(|f| Fact::convert_from(f, &self.symbols)).collect(),
-         self.world.rules.iter().map(|r| Rule::convert_from(r, &self.symbols)).collect(),
+        (self.world.all_facts().iter().map(|f| Fact::convert_from(f, &self.symbols)).collect(),
+         self.world.all_rules().map(|r| Rule::convert_from(r, &self.symbols)).collect(),
          caveats
         )
     }
 }
 
+/// looks up facts from the verifier's providers and builtins during a verification
+struct ProviderLookup {
+    providers: HashMap<datalog::Symbol, Arc<dyn FactProvider + Send + Sync>>,
+    builtins: HashMap<datalog::Symbol, Builtin>,
+    /// symbol table of the verifier, extended with the symbols of the provided facts
+    symbols: Mutex<datalog::SymbolTable>,
+    cache: Mutex<HashMap<datalog::Predicate, Arc<Vec<datalog::Fact>>>>,
+    /// first error returned by a provider
+    error: Mutex<Option<error::Token>>,
+}
+
+impl datalog::FactLookup for ProviderLookup {
+    fn provides(&self, name: datalog::Symbol) -> bool {
+        self.providers.contains_key(&name) || self.builtins.contains_key(&name)
+    }
+
+    fn is_builtin(&self, name: datalog::Symbol) -> bool {
+        self.builtins.contains_key(&name)
+    }
+
+    fn lookup(&self, predicate: &datalog::Predicate) -> Arc<Vec<datalog::Fact>> {
+        if let Some(f) = self.builtins.get(&predicate.name) {
+            let mut symbols = self.symbols.lock().unwrap();
+            let query = Predicate::convert_from(predicate, &symbols);
+            let facts = f(&query.ids)
+                .into_iter()
+                .filter(|ids| ids.len() == query.ids.len())
+                .filter(|ids| !ids.iter().any(|id| matches!(id, Term::Variable(_))))
+                .map(|ids| Fact::new(query.name.clone(), &ids).convert(&mut symbols))
+                .collect();
+            return Arc::new(facts);
+        }
+
+        if let Some(facts) = self.cache.lock().unwrap().get(predicate) {
+            return facts.clone();
+        }
+
+        let mut symbols = self.symbols.lock().unwrap();
+        let query = Predicate::convert_from(predicate, &symbols);
+        let facts = match self.providers[&predicate.name].facts(&query) {
+            Ok(facts) => facts
+                .iter()
+                .filter(|f| !f.0.ids.iter().any(|id| matches!(id, Term::Variable(_))))
+                .map(|f| f.convert(&mut symbols))
+                .collect(),
+            Err(e) => {
+                self.error.lock().unwrap().get_or_insert(e);
+                vec![]
+            }
+        };
+
+        let facts = Arc::new(facts);
+        self.cache.lock().unwrap().insert(predicate.clone(), facts.clone());
+        facts
+    }
+}
+
+/// facts, rules and caveats shared by the verification of many tokens
+///
+/// the facts and rules are stored once, and each [`Verifier`] created by
+/// [`VerifierTemplate::verifier`] refers to them instead of copying them.
+/// The token's facts, and the facts and rules added to that verifier,
+/// only exist for that verification
+///
+/// ```rust
+/// # use biscuit_auth::{crypto::KeyPair, token::{Biscuit, verifier::VerifierTemplate}};
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder(&root);
+/// builder.add_authority_fact("user(#authority, \"alice\")").unwrap();
+/// let token = builder.build().unwrap();
+///
+/// let mut template = VerifierTemplate::new();
+/// template.add_fact("owner(\"alice\", \"file1\")").unwrap();
+/// template.add_caveat("allowed($file) <- user(#authority, $user), owner($user, $file), resource(#ambient, $file)").unwrap();
+///
+/// let mut verifier = template.verifier_for(&token, root.public()).unwrap();
+/// verifier.add_resource("file1");
+/// verifier.verify().unwrap();
+///
+/// let mut verifier = template.verifier_for(&token, root.public()).unwrap();
+/// verifier.add_resource("file2");
+/// assert!(verifier.verify().is_err());
+/// ```
+#[derive(Clone)]
+pub struct VerifierTemplate {
+    world: Arc<datalog::World>,
+    symbols: datalog::SymbolTable,
+    caveats: Vec<datalog::Caveat>,
+    regexes: datalog::CompiledRegexes,
+    revocation_store: Option<Arc<dyn RevocationStore + Send + Sync>>,
+    fact_providers: HashMap<datalog::Symbol, Arc<dyn FactProvider + Send + Sync>>,
+    builtins: HashMap<datalog::Symbol, Builtin>,
+}
+
+impl VerifierTemplate {
+    /// creates a new empty template
+    pub fn new() -> Self {
+        VerifierTemplate {
+            world: Arc::new(datalog::World::new()),
+            symbols: super::default_symbol_table(),
+            caveats: vec![],
+            regexes: datalog::CompiledRegexes::new(),
+            revocation_store: None,
+            fact_providers: HashMap::new(),
+            builtins: HashMap::new(),
+        }
+    }
+
+    /// add a fact to the template
+    pub fn add_fact<F: TryInto<Fact>>(&mut self, fact: F) -> Result<(), error::Token> {
+        let fact = fact.try_into().map_err(|_| error::Token::ParseError)?;
+        let fact = fact.convert(&mut self.symbols);
+        Arc::make_mut(&mut self.world).facts.insert(fact);
+        Ok(())
+    }
+
+    /// add a rule to the template
+    pub fn add_rule<R: TryInto<Rule>>(&mut self, rule: R) -> Result<(), error::Token> {
+        let rule = rule.try_into().map_err(|_| error::Token::ParseError)?;
+        let rule = builtins_last(rule).convert(&mut self.symbols);
+        Arc::make_mut(&mut self.world).add_rule(rule);
+        Ok(())
+    }
+
+    /// add a caveat to the template
+    pub fn add_caveat<R: TryInto<Caveat>>(&mut self, caveat: R) -> Result<(), error::Token> {
+        let caveat = caveat.try_into().map_err(|_| error::Token::ParseError)?;
+        let caveat = caveat_builtins_last(caveat).convert(&mut self.symbols);
+        self.regexes.add_caveat(&caveat);
+        self.caveats.push(caveat);
+        Ok(())
+    }
+
+    /// sets the revocation store used by the verifiers
+    pub fn set_revocation_store(&mut self, store: Arc<dyn RevocationStore + Send + Sync>) {
+        self.revocation_store = Some(store);
+    }
+
+    /// registers a provider for the facts with this predicate name
+    pub fn add_fact_provider(&mut self, name: &str, provider: Arc<dyn FactProvider + Send + Sync>) {
+        let name = self.symbols.insert(name);
+        self.fact_providers.insert(name, provider);
+    }
+
+    /// registers a builtin predicate, called as `@name(..)` in rules and caveats
+    pub fn add_builtin<F>(&mut self, name: &str, f: F)
+    where
+        F: Fn(&[Term]) -> Vec<Vec<Term>> + Send + Sync + 'static,
+    {
+        let name = self.symbols.insert(&format!("{}{}", builtin::BUILTIN_PREFIX, name));
+        self.builtins.insert(name, Arc::new(f));
+    }
+
+    /// creates a verifier without token, sharing the template's facts and rules
+    ///
+    /// the symbol table, caveats and compiled regular expressions are cloned
+    /// for each verifier, since loading a token adds its symbols, caveats and
+    /// regular expressions to them, so the cost of this call grows with the
+    /// number of symbols and caveats of the template
+    pub fn verifier(&self) -> Verifier {
+        Verifier {
+            world: datalog::World::with_base(self.world.clone()),
+            symbols: self.symbols.clone(),
+            caveats: self.caveats.clone(),
+            token_caveats: vec![],
+            regexes: self.regexes.clone(),
+            token_revocation_ids: vec![],
+            token_proof_key: None,
+            revocation_store: self.revocation_store.clone(),
+            fact_providers: self.fact_providers.clone(),
+            builtins: self.builtins.clone(),
+            has_token: false,
+            scoped_tokens: vec![],
+        }
+    }
+
+    /// checks the token's root key, then creates a verifier loaded with the token
+    pub fn verifier_for(&self, token: &Biscuit, root: PublicKey) -> Result<Verifier, error::Token> {
+        token.check_root_key(root)?;
+        let mut verifier = self.verifier();
+        verifier.add_token(token).map_err(error::Token::FailedLogic)?;
+        Ok(verifier)
+    }
+}
+
+impl Default for VerifierTemplate {
+    fn default() -> Self {
+        VerifierTemplate::new()
+    }
+}
+
 /// runtime limits for the Datalog engine
 #[derive(Debug,Clone)]
 pub struct VerifierLimits {
@@ -0,0 +1,114 @@
+#[cfg(feature = "cli")]
+mod cli {
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Output, Stdio};
+
+    fn biscuit(args: &[&str], stdin: Option<&str>) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_biscuit"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut input = child.stdin.take().unwrap();
+        if let Some(stdin) = stdin {
+            input.write_all(stdin.as_bytes()).unwrap();
+        }
+        drop(input);
+
+        child.wait_with_output().unwrap()
+    }
+
+    fn success(args: &[&str], stdin: Option<&str>) -> String {
+        let output = biscuit(args, stdin);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn failure(args: &[&str]) -> String {
+        let output = biscuit(args, None);
+        assert!(!output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    }
+
+    fn write(dir: &Path, name: &str, content: &str) -> String {
+        let path: PathBuf = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn key(output: &str, label: &str) -> String {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(label))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn generate_attenuate_verify() {
+        let dir = std::env::temp_dir().join(format!("biscuit-cli-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let keypair = success(&["keypair"], None);
+        let private_key = key(&keypair, "private key: ");
+        let public_key = key(&keypair, "public key: ");
+
+        // the public key can be derived from the private key in a file or on stdin
+        let private_key_file = write(&dir, "root.key", &private_key);
+        assert_eq!(key(&success(&["keypair", "--private-key-file", &private_key_file], None), "public key: "), public_key);
+        assert_eq!(key(&success(&["keypair", "--private-key-file", "-"], Some(&private_key)), "public key: "), public_key);
+
+        let authority = write(&dir, "authority.datalog", "right(#authority, \"file1\", #read);\n");
+        let token1 = success(&["generate", "--private-key-file", &private_key_file, &authority], None);
+        let token1 = write(&dir, "token1", &token1);
+
+        let block = write(
+            &dir,
+            "block.datalog",
+            "// only reads\ncaveat check(#read) <- operation(#ambient, #read);\n",
+        );
+        let token2 = success(&["attenuate", &token1, &block], None);
+        let token2 = write(&dir, "token2", &token2);
+
+        let policy = write(
+            &dir,
+            "policy.datalog",
+            "caveat allowed($0) <- resource(#ambient, $0), operation(#ambient, $1), right(#authority, $0, $1);\n",
+        );
+        let verify = |operation: &str| {
+            biscuit(
+                &["verify", &token2, "--public-key", &public_key, &policy, "--resource", "file1", "--operation", operation],
+                None,
+            )
+        };
+        assert!(verify("read").status.success());
+        assert!(!verify("write").status.success());
+
+        let other = key(&success(&["keypair"], None), "public key: ");
+        failure(&["verify", &token2, "--public-key", &other]);
+
+        // tokens must be in base64 unless --raw is set
+        let invalid = write(&dir, "invalid", "not a token!\n");
+        assert!(failure(&["inspect", &invalid]).contains("is not in base64"));
+        let padding = write(&dir, "padding", &format!("{}===\n", std::fs::read_to_string(&token2).unwrap().trim()));
+        assert!(failure(&["inspect", &padding]).contains("is not in base64"));
+
+        // sealed tokens are only checked with their secret
+        let sealed = success(&["seal", &token2, "--secret", "s"], None);
+        let sealed = write(&dir, "sealed", &sealed);
+        assert!(success(&["verify", &sealed, "--secret", "s", "--operation", "read"], None).contains("verification succeeded"));
+        assert!(failure(&["verify", &sealed, "--secret", "s", "--public-key", &public_key])
+            .contains("--public-key cannot be used with --secret"));
+
+        // options are checked for each command
+        assert!(failure(&["verify", &token2, "--public-key", &public_key, "--private-key", "00"])
+            .contains("unknown option --private-key"));
+        assert!(failure(&["attenuate", &token1, &block, "--secret", "s"]).contains("unknown option --secret"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}